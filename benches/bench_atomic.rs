@@ -1,297 +1,118 @@
+use criterion::measurement::Measurement;
 use criterion::*;
-
-use core_affinity::CoreId;
-use async_bench::atomic_spin::MappedAtomics;
-use async_bench::{CLIENT_CPU, SERVER_CPU};
-
-fn rust_bench(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "target/release/atomic_spin_server",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "rust_atomic", &client );
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn rust_resume(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "target/release/atomic_async_resume",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "rust_async_resume", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn rust_suspend(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "target/release/atomic_async_suspend",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "rust_async_suspend", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn rust_callback(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "target/release/atomic_callback_server",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "rust_callback", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-
-fn cpp_bench(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child =
-        async_bench::bench_utils::launch_local("cpp/out/atomicSpin", vec![SERVER_CPU].as_ref());
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "cpp_atomic", &client);
-
-    child.kill().expect("error killing server process");
-    client.close();
+use std::path::Path;
+
+use async_bench::backing::{self, Backing, MapOptions};
+use async_bench::numa::{self, NumaPolicy};
+use async_bench::bench_utils::{bench_in_process, bench_server, snapshot_environment};
+use async_bench::bench_utils::{cpu_pairs, is_selected, pair_bench_name};
+use async_bench::registry::{self, SERVERS};
+use async_bench::servers::STOPPABLE_SERVERS;
+use async_bench::tsc::{RdtscMeasurement, TSC_OUTPUT_DIR};
+use async_bench::CONFIG;
+
+/// every server in the registry, in its own process. See src/registry.rs
+/// The ones that can't follow the configured layout or backing are skipped.
+fn out_of_process<M: Measurement>(c: &mut Criterion<M>) {
+    let options = CONFIG.map_options();
+    for server in SERVERS.iter() {
+        if !server.supports(&options) {
+            println!("skipping {}, it only maps the default segment", options.bench_name(server.name));
+            continue;
+        }
+        bench_server(c, "atomic_spin", server.name, options, |server_cpu| server.launch(server_cpu));
+    }
+}
+
+/// the Rust servers again, but on a thread in this process. They check
+/// for a stop after each echo, the out of process ones don't.
+fn rust_in_process<M: Measurement>(c: &mut Criterion<M>) {
+    for (name, server) in STOPPABLE_SERVERS.iter() {
+        bench_in_process(c, "in_process", name, CONFIG.map_options(), *server);
+    }
+}
+
+/// the plain spin server w/ its words in each layout in LAYOUT_SWEEP.
+/// The rest of the protocol's the same, so it's all in where the words are.
+fn layout_sweep<M: Measurement>(c: &mut Criterion<M>) {
+    let server = registry::find("rust_atomic").expect("rust_atomic isn't in the registry");
+    for layout in CONFIG.layout_sweep.iter() {
+        let options = MapOptions { layout: *layout, ..CONFIG.map_options() };
+        bench_server(c, "layout", server.name, options, |server_cpu| server.launch(server_cpu));
+    }
+}
+
+/// the plain spin server on each backing in BACKING_SWEEP. The first touch
+/// of each is timed on its own, the round trips don't see the faults.
+fn backing_sweep<M: Measurement>(c: &mut Criterion<M>) {
+    let server = registry::find("rust_atomic").expect("rust_atomic isn't in the registry");
+    for (b, p) in CONFIG.backing_sweep.iter() {
+        let options = MapOptions { backing: *b, prefault: *p, ..CONFIG.map_options() };
+        let name = options.bench_name(server.name);
+        if !cpu_pairs().iter().any(|(c, s)| is_selected("backing", &pair_bench_name(&name, *c, *s))) {
+            continue;
+        }
+        if *b == Backing::HugePages && backing::huge_pages_free() < 2 {
+            println!("skipping {}, there aren't 2 huge pages free. ex : echo 16 > /proc/sys/vm/nr_hugepages", name);
+            continue;
+        }
+        let touch = backing::first_touch(*b, *p);
+        if let Err(e) = backing::report_first_touch("backing", &name, &touch) {
+            println!("{} : can't write the first touch times : {}", name, e);
+        }
+        bench_server(c, "backing", server.name, options, |server_cpu| server.launch(server_cpu));
+    }
+}
+
+/// the plain spin server w/ the client and server on different NUMA nodes
+/// (see cpu_pairs()), and the segment on the client's node, then the server's.
+fn numa_placement<M: Measurement>(c: &mut Criterion<M>) {
+    let server = registry::find("rust_atomic").expect("rust_atomic isn't in the registry");
+    for numa in [NumaPolicy::Client, NumaPolicy::Server] {
+        let options = MapOptions { numa, ..CONFIG.map_options() };
+        bench_server(c, "numa", server.name, options, |server_cpu| server.launch(server_cpu));
+    }
+}
+
+fn tsc_criterion() -> Criterion<RdtscMeasurement> {
+    Criterion::default()
+        .with_measurement(RdtscMeasurement::new(CONFIG.tsc_unit))
+        .output_directory(Path::new(TSC_OUTPUT_DIR))
+        .configure_from_args()
+}
+
+/// run the benchmarks in a group w/ criterion's wall clock, or
+/// the TSC if the group is in tsc_groups. See src/config.rs
+macro_rules! run_group {
+    ($group:expr, $( $target:ident ),+ $(,)*) => {
+        if CONFIG.uses_tsc($group) {
+            let mut criterion = tsc_criterion();
+            $( $target(&mut criterion); )+
+        } else {
+            let mut criterion = Criterion::default().configure_from_args();
+            $( $target(&mut criterion); )+
+        }
+    };
+}
+
+fn main() {
+    snapshot_environment();
+
+    run_group!("atomic_spin", out_of_process);
+    run_group!("in_process", rust_in_process);
+    if !CONFIG.layout_sweep.is_empty() {
+        run_group!("layout", layout_sweep);
+    }
+    if !CONFIG.backing_sweep.is_empty() {
+        run_group!("backing", backing_sweep);
+    }
+    if CONFIG.cross_node {
+        if numa::is_numa() {
+            run_group!("numa", numa_placement);
+        } else {
+            println!("skipping the numa group, there's only one NUMA node");
+        }
+    }
+
+    Criterion::default().configure_from_args().final_summary();
 }
-
-fn cpp_resume(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child =
-        async_bench::bench_utils::launch_local("cpp/out/asyncResume", vec![SERVER_CPU].as_ref());
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "cpp_resume", &client);
-
-    child.kill().expect("error killing server process");
-    client.close();
-}
-
-fn cpp_suspend(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child =
-        async_bench::bench_utils::launch_local("cpp/out/asyncSuspend", vec![SERVER_CPU].as_ref());
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "cpp_suspend", &client);
-
-    child.kill().expect("error killing server process");
-    client.close();
-}
-
-fn cpp_callback(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child =
-        async_bench::bench_utils::launch_local("cpp/out/atomicCallback", vec![SERVER_CPU].as_ref());
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "cpp_callback", &client);
-
-    child.kill().expect("error killing server process");
-    client.close();
-}
-
-
-fn zig_bench(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child =
-        async_bench::bench_utils::launch_local("zig/zig-out/bin/atomicSpin", vec![SERVER_CPU].as_ref());
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "zig_atomic", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn zig_async_resume(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "zig/zig-out/bin/atomicAsyncResume",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "zig_resume", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn zig_async_suspend(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "zig/zig-out/bin/atomicAsyncSuspend",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "zig_suspend", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn zig_callback(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local(
-        "zig/zig-out/bin/atomicCallback",
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "zig_callback", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-
-fn kotlin_bench(c: &mut Criterion) {
-    // map memory
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local_java(
-        "kotlin/servers.jar",
-        "kotlin_servers.AtomicSpinKt",
-        Some(async_bench::bench_utils::JAVA_OPTS.as_ref()),
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "kotlin_atomic", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn kotlin_resume(c: &mut Criterion) {
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local_java(
-        "kotlin/servers.jar",
-        "kotlin_servers.AsyncResumeKt",
-        Some(async_bench::bench_utils::JAVA_OPTS.as_ref()),
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "kotlin_resume", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn kotlin_suspend(c: &mut Criterion) {
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-
-    let mut child = async_bench::bench_utils::launch_local_java(
-        "kotlin/servers.jar",
-        "kotlin_servers.AsyncSuspendKt",
-        Some(async_bench::bench_utils::JAVA_OPTS.as_ref()),
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "kotlin_suspend", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-fn kotlin_callback(c: &mut Criterion) {
-    let client = MappedAtomics::new(true);
-
-    core_affinity::set_for_current(CoreId { id: CLIENT_CPU });
-    
-    let mut child = async_bench::bench_utils::launch_local_java(
-        "kotlin/servers.jar",
-        "kotlin_servers.AtomicCallbackKt",
-        Some(async_bench::bench_utils::JAVA_OPTS.as_ref()),
-        vec![SERVER_CPU].as_ref(),
-    );
-
-    async_bench::bench_utils::run_bench(c, "atomic_spin", "kotlin_callback", &client);
-
-    client.close();
-    child.kill().expect("error killing server process");
-}
-
-criterion_group!(
-    benches,
-    cpp_bench,
-    cpp_resume,
-    cpp_suspend,
-    cpp_callback,
-    rust_bench,
-    rust_resume,
-    rust_suspend,
-    rust_callback,
-    zig_bench,
-    zig_async_resume,
-    zig_async_suspend,
-    zig_callback,
-    kotlin_bench,
-    kotlin_resume,
-    kotlin_suspend,
-    kotlin_callback,
-);
-// criterion_group!(benches, kotlin_bench);
-criterion_main!(benches);
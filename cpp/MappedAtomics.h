@@ -12,6 +12,11 @@
 #include <atomic>
 #include <stdlib.h>
 
+// the layout, generated from the Rust side by cargo run --bin gen_header. See src/layout.rs
+#include "spin_layout.h"
+
+static_assert(sizeof(spin_segment) == SPIN_SEGMENT_SIZE, "spin_layout.h doesn't match itself");
+
 struct MappedAtomics {
     std::atomic<long unsigned int> *clientPtr;
     std::atomic<long unsigned int> *serverPtr;
@@ -26,6 +31,7 @@ struct MappedAtomics {
     }
 
     MappedAtomics() {
+        // always shm, by name. A memfd backing (SHM_FD) is only for the Rust and kotlin servers.
         int shmFd = shm_open(
                 "/spinnmem",
                 O_RDWR,
@@ -36,10 +42,7 @@ struct MappedAtomics {
             exit(-1);
         }
 //        std::cout << "shm fd = " << shmFd << std::endl;
-        if( ftruncate( shmFd, getpagesize() ) != 0 )  {
-            std::cout << "ftruncate failed. exiting. error : " << std::strerror(errno) << std::endl;
-            exit(-1);
-        }
+        // no ftruncate, the client sized it. Some layouts are more than a page.
 
 
         void * mem_ptr = mmap(
@@ -55,12 +58,25 @@ struct MappedAtomics {
             throw std::system_error(errno, std::generic_category());
         }
 
-        clientPtr = static_cast<std::atomic<long unsigned int> *>(mem_ptr);
-
         auto charPtr = static_cast<char *>(mem_ptr);
-        auto voidPtr = static_cast<void *>(charPtr+2048);
+        auto header = reinterpret_cast<std::atomic<long unsigned int> *>(charPtr+SPIN_HEADER_OFFSET);
+        auto expected = (static_cast<long unsigned int>(SPIN_LAYOUT_MAGIC) << 32) | SPIN_LAYOUT_VERSION;
+        if( header->load(std::memory_order_acquire) != expected ) {
+            std::cout << "the shared memory has a different layout than this build. rebuild w/ the current spin_layout.h. exiting." << std::endl;
+            exit(-1);
+        }
+        auto mode = reinterpret_cast<std::atomic<long unsigned int> *>(charPtr+SPIN_MODE_OFFSET);
+        if( mode->load(std::memory_order_relaxed) != SPIN_MODE_DEFAULT ) {
+            std::cout << "the client picked a layout other than the default, only the Rust servers do those. exiting." << std::endl;
+            exit(-1);
+        }
+
+        clientPtr = reinterpret_cast<std::atomic<long unsigned int> *>(charPtr+SPIN_CLIENT_OFFSET);
+        serverPtr = reinterpret_cast<std::atomic<long unsigned int> *>(charPtr+SPIN_SERVER_OFFSET);
 
-        serverPtr = static_cast<std::atomic<long unsigned int> *>(voidPtr);
+        // the handshake, we're about to start echoing.
+        auto serverPid = reinterpret_cast<std::atomic<long unsigned int> *>(charPtr+SPIN_SERVER_PID_OFFSET);
+        serverPid->store(static_cast<long unsigned int>(getpid()), std::memory_order_release);
 
     }
 
@@ -1,4 +1,4 @@
-CMD="zig c++ -std=c++20 -lrt -Wall -Wextra -Wconversion -Wsign-conversion -Ofast -finline-functions"
+CMD="zig c++ -std=c++20 -lrt -Wall -Wextra -Wconversion -Wsign-conversion -Ofast -finline-functions -I../include"
 
 `mkdir -p out`
 
@@ -0,0 +1,46 @@
+// generated by gen_header from src/layout.rs, don't edit.
+// change the Rust, bump LAYOUT_VERSION, and cargo run --bin gen_header.
+
+#ifndef ASYNC_BENCH_SPIN_LAYOUT_H
+#define ASYNC_BENCH_SPIN_LAYOUT_H
+
+#include <stdint.h>
+
+#define SPIN_SEGMENT_SIZE 4096
+#define SPIN_CLIENT_OFFSET 0
+#define SPIN_SERVER_OFFSET 2048
+#define SPIN_PAYLOAD_OFFSET 8
+#define SPIN_CONTROL_OFFSET 3072
+#define SPIN_HEADER_OFFSET 3584
+#define SPIN_SERVER_PID_OFFSET 3592
+#define SPIN_MODE_OFFSET 3600
+#define SPIN_PREFAULT_OFFSET 3608
+#define SPIN_MODE_DEFAULT 0u
+#define SPIN_LAYOUT_MAGIC 0x5350494eu
+#define SPIN_LAYOUT_VERSION 4u
+#define SPIN_STOP 1u
+
+struct spin_channel {
+    uint64_t seq;
+    uint64_t payload;
+};
+
+struct spin_header {
+    uint64_t magic_version;
+    uint64_t server_pid;
+    uint64_t mode;
+    uint64_t prefault;
+};
+
+struct spin_segment {
+    struct spin_channel client;
+    uint8_t pad0[2032];
+    struct spin_channel server;
+    uint8_t pad1[1008];
+    uint64_t control;
+    uint8_t pad2[504];
+    struct spin_header header;
+    uint8_t pad3[480];
+};
+
+#endif // ASYNC_BENCH_SPIN_LAYOUT_H
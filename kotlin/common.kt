@@ -26,14 +26,23 @@ class Memory(val srvAddr:MemoryAddress, val cliAddr:MemoryAddress) {
 
 fun setupMemory(): Memory
 {
-    System.loadLibrary("zigmap")
+    // the C ABI in the Rust crate's cdylib, see src/ffi.rs.
+    // cargo builds it, JAVA_OPTS points java.library.path at it.
+    System.loadLibrary("async_bench")
     var linker = Linker.nativeLinker()
     var sym = SymbolLookup.loaderLookup()
-    var setup = linker.downcallHandle(
-        sym.lookup("mapSetup").get(),
-        FunctionDescriptor.of(ValueLayout.ADDRESS)
-    )
-    var memPtr = setup.invoke() as MemoryAddress;
-    var other = memPtr.addOffset(2048)
-    return Memory(other, memPtr)
-}
\ No newline at end of file
+    fun native(name: String, desc: FunctionDescriptor) = linker.downcallHandle(sym.lookup(name).get(), desc)
+    var ofSegment = FunctionDescriptor.of(ValueLayout.ADDRESS, ValueLayout.ADDRESS)
+
+    // null is the memfd in SHM_FD, or SHM_NAME, or the default. 0 is open, don't create.
+    // null back if it's missing, or the layout doesn't match.
+    var seg = native("spin_open", FunctionDescriptor.of(ValueLayout.ADDRESS, ValueLayout.ADDRESS, ValueLayout.JAVA_INT))
+        .invoke(MemoryAddress.NULL, 0) as MemoryAddress
+    if (seg == MemoryAddress.NULL) {
+        throw IllegalStateException("can't open the shared memory")
+    }
+    var cliAddr = native("spin_client_word", ofSegment).invoke(seg) as MemoryAddress
+    var srvAddr = native("spin_server_word", ofSegment).invoke(seg) as MemoryAddress
+    native("spin_server_ready", FunctionDescriptor.ofVoid(ValueLayout.ADDRESS)).invoke(seg)
+    return Memory(srvAddr, cliAddr)
+}
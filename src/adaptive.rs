@@ -0,0 +1,85 @@
+use crate::atomic_spin::MappedAtomics;
+use crate::config::Timing;
+use crate::stats;
+use std::time::{Duration, Instant};
+
+// Adaptive mode. Criterion names a benchmark's directory after it the
+// first time, and anything run again in the same process gets a _2 on
+// the end, so we can't just run it again w/ longer. Instead pilot runs,
+// timed like criterion's samples, find a length that gets the median's
+// CI under the target. Each sample is the mean of its round trips, so
+// the CI narrows w/ the square root of how long the samples are. That
+// says how long to try next, and the next pilot checks it, until one
+// is narrow enough or it's at the max. Criterion then measures for
+// the length that got there.
+
+/// the first pilot's share of the configured measurement time.
+const PILOT_SHARE: u32 = 10;
+
+/// 95% CI of the median, as a percent of the median.
+pub fn ci_width_pct(sorted: &[f64]) -> f64 {
+    let (lo, hi) = stats::median_ci(sorted);
+    (hi - lo) / stats::median(sorted) * 100.0
+}
+
+/// `sample_size` samples spread over `duration`, each the average time
+/// of its round trips in ns. Sorted.
+pub fn pilot_samples(client: &MappedAtomics, sample_size: usize, duration: Duration) -> Vec<f64> {
+    // roughly how long a round trip takes, to size the samples.
+    let start = Instant::now();
+    let mut probe = 0u64;
+    while probe < 1000 && start.elapsed() < Duration::from_millis(10) {
+        probe += 1;
+        client.client_run_once(probe);
+    }
+    let round_trip = start.elapsed().as_nanos() as f64 / probe as f64;
+    let iters = ((duration.as_nanos() as f64 / sample_size as f64 / round_trip) as u64).max(1);
+
+    let mut samples: Vec<f64> = (0..sample_size)
+        .map(|_| {
+            let start = Instant::now();
+            for i in 0..iters {
+                client.client_run_once(i + 1);
+            }
+            start.elapsed().as_nanos() as f64 / iters as f64
+        })
+        .collect();
+    samples.sort_by(f64::total_cmp);
+    samples
+}
+
+/// how long criterion should measure for. The configured time, or in adaptive
+/// mode, the first length a pilot got the CI under the target at, between
+/// that and the max.
+pub fn measurement_time(client: &MappedAtomics, timing: &Timing, bench_name: &str) -> Duration {
+    let Some(target) = timing.target_ci_pct else {
+        return timing.measurement;
+    };
+    let mut length = timing.measurement / PILOT_SHARE;
+    loop {
+        let width = ci_width_pct(&pilot_samples(client, timing.sample_size, length));
+        println!(
+            "{} : pilot over {:.2}s, median CI is {:.2}% wide, the target's {}%",
+            bench_name,
+            length.as_secs_f64(),
+            width,
+            target
+        );
+        if width <= target {
+            return length.max(timing.measurement).min(timing.max_measurement);
+        }
+        if length >= timing.max_measurement {
+            println!(
+                "{} : still over at the {}s limit, measuring for that",
+                bench_name,
+                timing.max_measurement.as_secs_f64()
+            );
+            return timing.max_measurement;
+        }
+        // CI ∝ 1/√n, so n has to go up by the square of how much too wide it is.
+        // At least double it, so a pilot that just misses doesn't creep up.
+        let needed = length.as_secs_f64() * (width / target).powi(2);
+        let next = needed.max(2.0 * length.as_secs_f64());
+        length = Duration::from_secs_f64(next.min(timing.max_measurement.as_secs_f64()));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::stats::Summary;
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::path::{Path, PathBuf};
+
+// Picks the best of the N runs of each benchmark that run_bench
+// copied into runs/, what find_best() in graphs/graph_results.R did.
+// A run is criterion's raw.csv, renamed <bench>_r<N>.csv
+
+/// how to pick the best run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BestBy {
+    /// the smallest 97.5th percentile, the top of the
+    /// whisker on the graphs. What the R code used.
+    Hi,
+    Median,
+    Mean,
+    P99,
+}
+
+impl BestBy {
+    pub fn parse(name: &str) -> BestBy {
+        match name {
+            "hi" => BestBy::Hi,
+            "median" => BestBy::Median,
+            "mean" => BestBy::Mean,
+            "p99" => BestBy::P99,
+            _ => panic!("best-by must be one of hi, median, mean, p99. got '{}'", name),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BestBy::Hi => "hi",
+            BestBy::Median => "median",
+            BestBy::Mean => "mean",
+            BestBy::P99 => "p99",
+        }
+    }
+
+    fn value(&self, s: &Summary) -> f64 {
+        match self {
+            BestBy::Hi => s.p97_5,
+            BestBy::Median => s.median,
+            BestBy::Mean => s.mean,
+            BestBy::P99 => s.p99,
+        }
+    }
+}
+
+/// one run of one benchmark.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub file: PathBuf,
+    /// the N in _r<N>
+    pub run: usize,
+    pub unit: String,
+    /// time per iteration, one per criterion sample.
+    pub samples: Vec<f64>,
+    pub summary: Summary,
+}
+
+/// every run of a benchmark, and which was best.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    pub id: String,
+    pub runs: Vec<Run>,
+    pub best: usize,
+}
+
+impl Benchmark {
+    pub fn best_run(&self) -> &Run {
+        &self.runs[self.best]
+    }
+}
+
+/// "rust_async_resume" -> ("rust", "resume"). The in_process and
+/// TSC results keep their suffix on the variant.
+pub fn split_bench_id(id: &str) -> (&str, String) {
+    let (language, variant) = id.split_once('_').unwrap_or((id, ""));
+    let variant = variant.strip_prefix("async_").unwrap_or(variant);
+    (language, variant.to_string())
+}
+
+/// "rust" -> "Rust", for titles.
+pub fn language_name(language: &str) -> String {
+    match language {
+        "cpp" => "C++".to_string(),
+        "rust" => "Rust".to_string(),
+        "zig" => "Zig".to_string(),
+        "kotlin" => "Kotlin".to_string(),
+        _ => language.to_string(),
+    }
+}
+
+/// "Rust", "Rust-Resume"... the names the R graphs used.
+pub fn display_name(language: &str, variant: &str) -> String {
+    let language = language_name(language);
+    if variant == "atomic" {
+        return language;
+    }
+    let variant = variant.replace('_', " ");
+    let mut chars = variant.chars();
+    match chars.next() {
+        Some(first) => format!("{}-{}{}", language, first.to_uppercase(), chars.as_str()),
+        None => language,
+    }
+}
+
+/// "rust_atomic_r12.csv" -> ("rust_atomic", 12)
+pub fn parse_run_file_name(name: &str) -> Option<(String, usize)> {
+    let stem = name.strip_suffix(".csv")?;
+    let (id, run) = stem.rsplit_once("_r")?;
+    Some((id.to_string(), run.parse().ok()?))
+}
+
+/// the per iteration times and unit from a criterion raw.csv.
+/// None if it isn't one (ex : the open loop csv files).
+pub fn parse_raw_csv(text: &str) -> Option<(String, Vec<f64>)> {
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines.next()?.split(',').collect();
+    let col = |name: &str| header.iter().position(|h| *h == name);
+    let (value_col, unit_col, iters_col) = (col("sample_measured_value")?, col("unit")?, col("iteration_count")?);
+
+    let mut unit = String::new();
+    let mut samples = Vec::new();
+    for line in lines.filter(|l| !l.trim().is_empty()) {
+        let fields: Vec<&str> = line.split(',').collect();
+        let value: f64 = fields.get(value_col)?.parse().ok()?;
+        let iters: f64 = fields.get(iters_col)?.parse().ok()?;
+        unit = fields.get(unit_col)?.to_string();
+        samples.push(value / iters);
+    }
+    Some((unit, samples))
+}
+
+pub fn load_run(file: &Path, run: usize) -> Option<Run> {
+    let text = std::fs::read_to_string(file).ok()?;
+    let (unit, samples) = parse_raw_csv(&text)?;
+    if samples.is_empty() {
+        return None;
+    }
+    Some(Run {
+        file: file.to_path_buf(),
+        run,
+        unit,
+        summary: Summary::of(&samples),
+        samples,
+    })
+}
+
+/// every benchmark w/ runs in `dir`, best run picked by `best_by`.
+pub fn load_dir(dir: &Path, best_by: BestBy) -> std::io::Result<Vec<Benchmark>> {
+    let mut by_id: BTreeMap<String, Vec<Run>> = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some((id, run)) = path.file_name().and_then(|n| parse_run_file_name(n.to_str()?)) else {
+            continue;
+        };
+        if let Some(run) = load_run(&path, run) {
+            by_id.entry(id).or_default().push(run);
+        }
+    }
+    Ok(by_id
+        .into_iter()
+        .map(|(id, mut runs)| {
+            runs.sort_by_key(|r| r.run);
+            let best = pick_best(&runs, best_by);
+            Benchmark { id, runs, best }
+        })
+        .collect())
+}
+
+/// the best run of each benchmark in `path`, which is any of :
+///   - a runs directory, ex : runs/ or runs/amd/
+///   - a summary aggregate wrote, its best_file's are read
+///   - a single criterion raw.csv, named after the file
+pub fn load_best(path: &Path, best_by: BestBy) -> std::io::Result<Vec<(String, Run)>> {
+    if path.is_dir() {
+        return Ok(load_dir(path, best_by)?
+            .into_iter()
+            .map(|b| {
+                let best = b.best;
+                (b.id, b.runs.into_iter().nth(best).unwrap())
+            })
+            .collect());
+    }
+    let bad = |what: String| std::io::Error::new(std::io::ErrorKind::InvalidData, what);
+    if path.extension().is_some_and(|x| x == "json") {
+        let summary = json::parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| bad(format!("can't parse {} : {}", path.display(), e)))?;
+        let mut results = Vec::new();
+        for b in summary["benchmarks"].members() {
+            let (Some(id), Some(file)) = (b["id"].as_str(), b["best_file"].as_str()) else {
+                continue;
+            };
+            let run = b["best_run"].as_usize().unwrap_or(0);
+            let run = load_run(Path::new(file), run).ok_or_else(|| bad(format!("can't read {} for {}", file, id)))?;
+            results.push((id.to_string(), run));
+        }
+        return Ok(results);
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let (id, run) = parse_run_file_name(&name).unwrap_or((name.trim_end_matches(".csv").to_string(), 0));
+    let run = load_run(path, run).ok_or_else(|| bad(format!("{} isn't a criterion raw.csv", path.display())))?;
+    Ok(vec![(id, run)])
+}
+
+/// the index of the best run.
+pub fn pick_best(runs: &[Run], best_by: BestBy) -> usize {
+    runs.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| best_by.value(&a.summary).total_cmp(&best_by.value(&b.summary)))
+        .map(|(i, _)| i)
+        .expect("no runs to pick from")
+}
+
+/// the machines in `runs`. Each sub directory w/ results is a machine,
+/// named after the directory. Results right in `runs` are `local_name`.
+pub fn machine_dirs(runs: &Path, local_name: &str) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let has_csv = |dir: &Path| {
+        std::fs::read_dir(dir)
+            .map(|d| d.flatten().any(|e| e.path().extension().is_some_and(|x| x == "csv")))
+            .unwrap_or(false)
+    };
+    let mut machines = Vec::new();
+    if has_csv(runs) {
+        machines.push((local_name.to_string(), runs.to_path_buf()));
+    }
+    let mut subdirs: Vec<PathBuf> = std::fs::read_dir(runs)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && has_csv(p))
+        .collect();
+    subdirs.sort();
+    for dir in subdirs {
+        let name = dir.file_name().unwrap().to_string_lossy().to_string();
+        machines.push((name, dir));
+    }
+    Ok(machines)
+}
+
+/// the newest environment_r<N>.json in `dir`. See environment.rs
+pub fn load_environment(dir: &Path) -> Option<json::JsonValue> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.starts_with("environment") && name.ends_with(".json")
+        })
+        .filter_map(|e| json::parse(&std::fs::read_to_string(e.path()).ok()?).ok())
+        .max_by_key(|env| env["timestamp"].as_u64().unwrap_or(0))
+}
+
+/// the CPU model the runs in `dir` were on.
+pub fn machine_name(dir: &Path) -> Option<String> {
+    load_environment(dir)?["cpu"]["model"].as_str().map(|m| m.to_string())
+}
+
+/// one row per benchmark, the best run's summary.
+pub fn to_csv(benchmarks: &[Benchmark]) -> String {
+    let mut out = String::new();
+    let _ = write!(out, "id,language,variant,runs,best_run,unit");
+    for field in Summary::FIELDS.iter() {
+        let _ = write!(out, ",{}", field);
+    }
+    let _ = writeln!(out);
+    for b in benchmarks.iter() {
+        let best = b.best_run();
+        let (language, variant) = split_bench_id(&b.id);
+        let _ = write!(
+            out,
+            "{},{},{},{},{},{}",
+            b.id,
+            language,
+            variant,
+            b.runs.len(),
+            best.run,
+            best.unit
+        );
+        for value in best.summary.to_values() {
+            let _ = write!(out, ",{}", value);
+        }
+        let _ = writeln!(out);
+    }
+    out
+}
+
+pub fn to_json(machine: &str, best_by: BestBy, benchmarks: &[Benchmark]) -> json::JsonValue {
+    let mut list = json::JsonValue::new_array();
+    for b in benchmarks.iter() {
+        let best = b.best_run();
+        let (language, variant) = split_bench_id(&b.id);
+        let mut runs = json::JsonValue::new_array();
+        for r in b.runs.iter() {
+            let _ = runs.push(json::object! {
+                run: r.run,
+                file: r.file.to_string_lossy().to_string(),
+                summary: r.summary.to_json(),
+            });
+        }
+        let _ = list.push(json::object! {
+            id: b.id.as_str(),
+            language: language,
+            variant: variant,
+            unit: best.unit.as_str(),
+            best_run: best.run,
+            best_file: best.file.to_string_lossy().to_string(),
+            best: best.summary.to_json(),
+            runs: runs,
+        });
+    }
+    json::object! {
+        machine: machine,
+        best_by: best_by.name(),
+        benchmarks: list,
+    }
+}
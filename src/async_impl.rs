@@ -14,8 +14,8 @@ use crate::atomic_spin::MappedAtomics;
 /// takes the mut ref on the event loop, there must be some 3rd place
 /// the Future and EventLoop all can reach mutably.
 /// this is it.
-pub struct RuntimeState {
-    pub atomics: MappedAtomics,
+pub struct RuntimeState<'a> {
+    pub atomics: &'a MappedAtomics,
     pub waker: Option<Waker>,
 
     /// The values that the async loop
@@ -28,8 +28,8 @@ pub struct RuntimeState {
     pub to_async_loop: Option<u64>,
 }
 
-impl RuntimeState {
-    pub fn new(atomics:MappedAtomics) -> RuntimeState {
+impl<'a> RuntimeState<'a> {
+    pub fn new(atomics: &'a MappedAtomics) -> RuntimeState<'a> {
         RuntimeState{
             atomics,
             waker : None,
@@ -42,12 +42,12 @@ impl RuntimeState {
 
 /// this is the Future we'll use
 /// as a suspend point.
-pub struct SpinFuture {
-    state: Rc<RefCell<RuntimeState>>,
+pub struct SpinFuture<'a> {
+    state: Rc<RefCell<RuntimeState<'a>>>,
 }
 
-impl SpinFuture {
-    pub fn new(state: Rc<RefCell<RuntimeState>>) -> SpinFuture {
+impl<'a> SpinFuture<'a> {
+    pub fn new(state: Rc<RefCell<RuntimeState<'a>>>) -> SpinFuture<'a> {
         SpinFuture { state }
     }
 
@@ -58,7 +58,7 @@ impl SpinFuture {
 }
 
 
-impl Future for SpinFuture {
+impl Future for SpinFuture<'_> {
     type Output = u64;
 
     /// do work.
@@ -89,9 +89,12 @@ impl Future for SpinFuture {
 /// waker containing a reference to the
 /// struct it's housed in.
 /// So the creation is super janky.
+/// The Task is boxed so the pointer in the waker
+/// doesn't move out from under it.
 pub struct Task<F> {
     code: Pin<Box<F>>,
     waker: Waker,
+    done: bool,
 }
 
 impl<F> Task<F>
@@ -100,17 +103,18 @@ impl<F> Task<F>
 {
 
     // create a waker for a block of async code.
-    pub fn init(block: F) -> Task<F> {
+    pub fn init(block: F) -> Box<Task<F>> {
         unsafe {
-            let mut task = Task {
+            let mut task = Box::new(Task {
                 code: Box::pin(block),
                 // first create a waker w/ a null pointer. We'll overwrite it in a moment.
                 waker: Waker::from_raw(RawWaker::new(std::ptr::null(), &Task::<F>::VTABLE)), // set a waker w/ a bad pointer.
-            };
+                done: false,
+            });
 
             // now replace the waker, with a waker that points to it's own struct.
             task.waker = {
-                let ptr = &task as *const Task<F> as *const ();
+                let ptr = &*task as *const Task<F> as *const ();
                 let raw_waker = RawWaker::new(ptr, &Task::<F>::VTABLE);
                 Waker::from_raw(raw_waker)
             };
@@ -122,26 +126,31 @@ impl<F> Task<F>
     // it's also called once from main() to
     // start the async task.
     pub fn advance(&mut self) {
-
+        // polling a finished async block panics.
+        if self.done {
+            return;
+        }
         let mut cx = Context::from_waker(&self.waker);
-        // keep kicking it until it's suspended.
-        while self.code.as_mut().poll(&mut cx).is_ready() {}
+        // kick it until it's suspended, or it returns.
+        self.done = self.code.as_mut().poll(&mut cx).is_ready();
+    }
+
+    /// true once the async code has returned.
+    pub fn is_done(&self) -> bool {
+        self.done
     }
 
     const VTABLE: RawWakerVTable = RawWakerVTable::new(
         |s| Task::<F>::run_clone(s),
-        |s| Task::<F>::run_wake(s),
-        |s| Task::<F>::run_wake(s),
+        |s| unsafe { Task::<F>::run_wake(s) },
+        |s| unsafe { Task::<F>::run_wake(s) },
         |_| {}, // droping on a reference doesn't do anyting.
     );
 
     // decode the pointer as a
     // task, and call advance.
-    pub fn run_wake(s: *const ()) {
-        let r = unsafe {
-            // can't cast to mutable w/o transmute
-            std::mem::transmute::<*const (), &mut Task<F>>(s)
-        };
+    unsafe fn run_wake(s: *const ()) {
+        let r = &mut *(s as *mut Task<F>);
         r.advance();
     }
 
@@ -1,95 +1,391 @@
-use std::ffi::c_void;
+use std::ffi::{c_void, CString};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::os::raw::c_int;
 
+/// the layout lives in layout.rs, the other languages get it from
+/// the header gen_header generates from it.
+pub use crate::layout::{
+    layout_header, LayoutMode, CLIENT_OFFSET, CONTROL_OFFSET, HEADER_OFFSET, LAYOUT_MAGIC, LAYOUT_VERSION,
+    MODE_OFFSET, PAYLOAD_OFFSET, PREFAULT_OFFSET, SERVER_OFFSET, SERVER_PID_OFFSET,
+};
+pub use crate::backing::{Backing, MapOptions, Prefault};
+use crate::backing;
+use crate::numa;
+use crate::layout::STOP;
+
+
+/// Ok if `header` is what this build writes. See layout_header()
+pub fn check_header_word(header: u64) -> Result<(), String> {
+    if header == layout_header() {
+        return Ok(());
+    }
+    let (magic, version) = ((header >> 32) as u32, header as u32);
+    if magic != LAYOUT_MAGIC {
+        Err(format!("no layout header in the segment, found {:#x}", header))
+    } else {
+        Err(format!("segment layout is version {}, this is version {}", version, LAYOUT_VERSION))
+    }
+}
 
 /// A common utility class for client and server.
 /// the contract is the client will only write to
-/// the client atomic, and the server only to
-/// the server atomic.
+/// the client atomics, and the server only to
+/// the server atomics.
+///
+/// client_write/server_write hold a sequence number, not the payload.
+/// The client bumps it for every message, so it always changes, even
+/// if the payload is 0, or the same as last time. The servers only
+/// echo the sequence word, which is all the timing needs. The Checked
+/// Rust servers copy the payload first, so the tests can see it's right.
 pub struct MappedAtomics {
     pub client_write: &'static AtomicU64,
     pub server_write: &'static AtomicU64,
-    mmap_ptr: *mut c_void,
+    pub client_payload: &'static AtomicU64,
+    pub server_payload: &'static AtomicU64,
+    control: &'static AtomicU64,
+    header: &'static AtomicU64,
+    server_pid: &'static AtomicU64,
+    layout: LayoutMode,
+    /// what to munmap, and how much of it.
+    mappings: Vec<(*mut c_void, usize)>,
+    /// the memfd, if it's not in shm. See shared_fd()
+    fd: Option<c_int>,
+    /// None if the memory isn't shm, and only
+    /// shared w/ threads in this process.
+    shm_name: Option<CString>,
 }
 
+/// what a server loop does besides echo the sequence number.
+/// The const ifs on these compile away, so Bench is the same
+/// loop as the other languages' servers.
+pub trait ServerMode {
+    /// copy the payload too, w/ the ordering that takes.
+    const ECHO_PAYLOAD: bool;
+    /// return once the client calls request_stop().
+    const STOPS: bool;
+}
+
+/// what the benchmarks time. A relaxed echo of the sequence
+/// number and nothing else, like the C++, zig and kotlin servers.
+pub struct Bench;
+
+/// Bench, but it checks for a stop after each echo. For a server
+/// on a thread, which can't be killed. See bench_in_process()
+pub struct Stoppable;
+
+/// echoes the payload and stops. What the tests check, and
+/// the server binaries run w/ --checked.
+pub struct Checked;
+
+impl ServerMode for Bench {
+    const ECHO_PAYLOAD: bool = false;
+    const STOPS: bool = false;
+}
+
+impl ServerMode for Stoppable {
+    const ECHO_PAYLOAD: bool = false;
+    const STOPS: bool = true;
+}
+
+impl ServerMode for Checked {
+    const ECHO_PAYLOAD: bool = true;
+    const STOPS: bool = true;
+}
+
+// the pointers are to shared memory, and all access
+// to it is via the atomics. So it's fine to share
+// between threads.
+unsafe impl Send for MappedAtomics {}
+unsafe impl Sync for MappedAtomics {}
+
 impl MappedAtomics {
 
     /// this is only called from the server_loop.
     /// The compilers seem to like this better
     /// in a separate function than in-line by hand.
     #[inline(always)]
-    pub fn server_spin_until_change(&self, last_value: u64) -> u64 {
+    pub fn server_spin_until_change<M: ServerMode>(&self, last_value: u64) -> u64 {
+        // only a payload needs the acquire, the sequence number is the message.
+        let order = if M::ECHO_PAYLOAD { Ordering::Acquire } else { Ordering::Relaxed };
         let mut new_value = last_value;
         while new_value == last_value {
             core::hint::spin_loop();
-            new_value = self.client_write.load(Ordering::Relaxed);
+            new_value = self.client_write.load(order);
         }
         new_value
     }
 
-    pub fn do_server_loop(&self) {
+    /// echo the sequence number. w/ ECHO_PAYLOAD, copy the client's
+    /// payload first, it has to be in place before the client
+    /// sees the sequence number.
+    #[inline(always)]
+    pub fn server_echo<M: ServerMode>(&self, seq: u64) {
+        if M::ECHO_PAYLOAD {
+            let payload = self.client_payload.load(Ordering::Relaxed);
+            self.server_payload.store(payload, Ordering::Relaxed);
+            self.server_write.store(seq, Ordering::Release);
+        } else {
+            self.server_write.store(seq, Ordering::Relaxed);
+        }
+    }
+
+    /// true if a loop in mode M should return now.
+    #[inline(always)]
+    pub fn should_stop<M: ServerMode>(&self) -> bool {
+        M::STOPS && self.stop_requested()
+    }
+
+    pub fn do_server_loop<M: ServerMode>(&self) {
         let mut last_value: u64 = 0;
         loop {
-            last_value = self.server_spin_until_change(last_value);
-            self.server_write.store(last_value, Ordering::Relaxed);
+            last_value = self.server_spin_until_change::<M>(last_value);
+            self.server_echo::<M>(last_value);
+            // after the store, so it's not in the part the client times.
+            if self.should_stop::<M>() {
+                return;
+            }
+        }
+    }
+
+    /// ask the server loop to return. The server only looks
+    /// after the sequence number changes, so change it.
+    /// Only call this from the client side.
+    pub fn request_stop(&self) {
+        self.control.store(STOP, Ordering::Release);
+        let last = self.client_write.load(Ordering::Relaxed);
+        self.client_write.store(last.wrapping_add(1), Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub fn stop_requested(&self) -> bool {
+        self.control.load(Ordering::Acquire) == STOP
+    }
+
+    /// Ok if whoever made the segment used the same layout as us.
+    pub fn check_header(&self) -> Result<(), String> {
+        check_header_word(self.header.load(Ordering::Acquire))
+    }
+
+    /// the server side of the handshake. Call once the server is about to
+    /// start echoing.
+    pub fn mark_server_ready(&self) {
+        self.server_pid.store(std::process::id() as u64, Ordering::Release);
+    }
+
+    /// the pid of the server, once it's marked itself ready.
+    pub fn server_ready(&self) -> Option<u32> {
+        match self.server_pid.load(Ordering::Acquire) {
+            0 => None,
+            pid => Some(pid as u32),
         }
     }
 
+    /// send `value`, and wait for the server to echo it.
+    /// returns the payload the server echoed. Only the Checked
+    /// servers echo the payload, the others only the sequence number.
     #[inline(always)]
-    pub fn client_run_once(&self, value: u64) {
-        self.client_write.store(value, Ordering::Relaxed);
+    pub fn client_run_once(&self, value: u64) -> u64 {
+        // only the client writes this, so it's always our last one.
+        let seq = self.client_write.load(Ordering::Relaxed).wrapping_add(1);
+        self.client_payload.store(value, Ordering::Relaxed);
+        self.client_write.store(seq, Ordering::Release);
 
-        let mut last_read = !value;
+        let mut last_read = !seq;
 
-        while value != last_read {
+        while seq != last_read {
             core::hint::spin_loop();
-            last_read = self.server_write.load(Ordering::Relaxed);
+            last_read = self.server_write.load(Ordering::Acquire);
         }
+        self.server_payload.load(Ordering::Relaxed)
     }
 
 
     /// open or create the shared memory atomics.
     /// whichever is the first to start should
     /// create, the second should pass 'false' and fail
-    /// if the expected named memory doesn't exist.
+    /// if the expected named memory doesn't exist, or
+    /// has a different layout. If the client passed
+    /// down a memfd (SHM_FD), that's opened instead.
     pub fn new(do_create: bool) -> MappedAtomics {
+        match crate::shm_fd() {
+            Some(fd) if !do_create => unsafe { MappedAtomics::open_fd(fd, None) },
+            _ => MappedAtomics::new_named(crate::shm_name(), do_create),
+        }
+    }
+
+    /// same as new(), but w/ a shm name other than the default.
+    /// Creates it w/ the default options, or opens it w/ whatever
+    /// layout its creator picked.
+    pub fn new_named(shm_name: CString, do_create: bool) -> MappedAtomics {
+        if do_create {
+            return MappedAtomics::create(shm_name, MapOptions::default());
+        }
         unsafe {
-            let mem_fd = MappedAtomics::shm_open(do_create);
+            let mem_fd = MappedAtomics::shm_open(&shm_name, false);
+            MappedAtomics::open_fd(mem_fd, Some(shm_name))
+        }
+    }
+
+    /// open the segment behind `fd`, and close the fd.
+    unsafe fn open_fd(mem_fd: c_int, shm_name: Option<CString>) -> MappedAtomics {
+        let page = backing::fd_page_size(mem_fd);
+        let what = shm_name.as_ref().map(|n| format!("{:?}", n)).unwrap_or_else(|| format!("fd {}", mem_fd));
+
+        // the first page has the header, which says where the rest is.
+        let first = MappedAtomics::mmap(mem_fd, libc::MAP_SHARED, page, 0);
+        let word = |offset: usize| (*((first as *const u8).add(offset) as *const AtomicU64)).load(Ordering::Acquire);
+        let (header, mode, prefault) = (word(HEADER_OFFSET), word(MODE_OFFSET), word(PREFAULT_OFFSET));
+        libc::munmap(first, page);
+
+        if let Err(e) = check_header_word(header) {
+            libc::close(mem_fd);
+            panic!("{} : {}", what, e);
+        }
+        let (Some(layout), Some(prefault)) = (LayoutMode::from_id(mode), Prefault::from_id(prefault)) else {
+            libc::close(mem_fd);
+            panic!("{} : unknown layout mode {} or prefault {}", what, mode, prefault);
+        };
+
+        // the backing doesn't matter to this side, it's whatever the fd is.
+        let options = MapOptions { layout, prefault, ..MapOptions::default() };
+        let atomics = MappedAtomics::map(mem_fd, page, options, shm_name, false);
+        libc::close(mem_fd);
+        atomics
+    }
+
+    /// create the segment the way `options` says. A memfd stays open, so it
+    /// can be passed to the server. See shared_fd()
+    pub fn create(shm_name: CString, options: MapOptions) -> MappedAtomics {
+        unsafe {
+            let mem_fd = match options.backing {
+                Backing::Shm => MappedAtomics::shm_open(&shm_name, true),
+                backing => backing::memfd(shm_name.to_str().unwrap(), backing),
+            };
+            let page = backing::fd_page_size(mem_fd);
+            let size = page * options.layout.pages();
 
-            if libc::ftruncate(mem_fd, page_size::get() as i64) < 0 {
+            if libc::ftruncate(mem_fd, size as i64) < 0 {
                 panic!(
-                    "can't truncate shared memory FD. error num : {}. page size = {}",
+                    "can't truncate shared memory FD. error num : {}. size = {}",
                     *libc::__errno_location(),
-                    page_size::get()
+                    size
                 );
             }
 
-            let mem_ptr = MappedAtomics::mmap( mem_fd );
-
-            let first_ptr = mem_ptr as *mut u64;
-            // set the second atomic a few cache lines down.
-            let second_ptr = (mem_ptr as *mut u8).add(2048) as *mut u64;
-            assert_ne!(first_ptr, second_ptr);
-            let mapped_atomics = MappedAtomics {
-                client_write: &*(first_ptr as *const AtomicU64),
-                // scooch down a cache line or two.
-                server_write: &*(second_ptr as *const AtomicU64),
-                mmap_ptr: mem_ptr,
-            };
-            // only zero out on creation, lest we romp on the values
-            // when the server starts up, after the client has been running.
-            if do_create {
-                mapped_atomics.client_write.store(0, Ordering::Relaxed);
-                mapped_atomics.server_write.store(0, Ordering::Relaxed);
+            let mut atomics = MappedAtomics::map(mem_fd, page, options, Some(shm_name), true);
+            if options.backing == Backing::Shm {
+                libc::close(mem_fd);
+            } else {
+                // there's no name to find it by, only the fd.
+                atomics.shm_name = None;
+                atomics.fd = Some(mem_fd);
             }
+            atomics
+        }
+    }
 
-            mapped_atomics
+    /// memory that isn't in shm, for when the client and server
+    /// are threads in this process.
+    pub fn new_anonymous() -> MappedAtomics {
+        MappedAtomics::new_anonymous_with(MapOptions::default())
+    }
+
+    /// Shm is anonymous memory, the others a memfd only this process has.
+    pub fn new_anonymous_with(options: MapOptions) -> MappedAtomics {
+        if options.backing == Backing::Shm {
+            return unsafe { MappedAtomics::map(-1, page_size::get(), options, None, true) };
+        }
+        let atomics = MappedAtomics::create(CString::new("in_process").unwrap(), options);
+        if let Some(fd) = atomics.fd {
+            unsafe { libc::close(fd) };
         }
+        MappedAtomics { fd: None, ..atomics }
+    }
+
+    /// the memfd the server needs, if it's not in shm. It's inherited by
+    /// children, pass its number in SHM_FD.
+    pub fn shared_fd(&self) -> Option<c_int> {
+        self.fd
     }
-    unsafe fn shm_open(do_create:bool) -> c_int {
+
+    /// map `fd` (or anonymous memory if it's -1), as one mapping,
+    /// or the server's page on its own. `page` is the size of the fd's pages.
+    /// The opener leaves the NUMA policy to the creator, it's on the shm.
+    unsafe fn map(fd: c_int, page: usize, options: MapOptions, shm_name: Option<CString>, do_create: bool) -> MappedAtomics {
+        let layout = options.layout;
+        let flags = backing::map_flags(fd, options.prefault);
+        let server_offset = layout.server_offset(page);
+        let mappings = numa::map_on(options.numa, || {
+            if layout.separate_mapping() {
+                let first = MappedAtomics::mmap(fd, flags, page, 0);
+                let second = MappedAtomics::mmap(fd, flags, page, if fd < 0 { 0 } else { server_offset });
+                vec![(first, page), (second, page)]
+            } else {
+                vec![(MappedAtomics::mmap(fd, flags, page * layout.pages(), 0), page * layout.pages())]
+            }
+        });
+        let mem_ptr = mappings[0].0;
+        let server_ptr = match mappings.get(1) {
+            Some((second, _)) => *second,
+            None => mem_ptr.byte_add(server_offset),
+        };
+        for (ptr, len) in mappings.iter() {
+            backing::lock(*ptr, *len, options.prefault);
+        }
+        MappedAtomics::from_ptr(mem_ptr, server_ptr, options, mappings, shm_name, do_create)
+    }
+
+    /// `mem_ptr` is the first page, `server_ptr` where the server's channel is.
+    /// `mappings` are unmapped by unmap().
+    unsafe fn from_ptr(
+        mem_ptr: *mut c_void,
+        server_ptr: *mut c_void,
+        options: MapOptions,
+        mappings: Vec<(*mut c_void, usize)>,
+        shm_name: Option<CString>,
+        do_create: bool,
+    ) -> MappedAtomics {
+        let word = |ptr: *mut c_void, offset: usize| &*((ptr as *const u8).add(offset) as *const AtomicU64);
+        assert_ne!(mem_ptr.wrapping_byte_add(CLIENT_OFFSET), server_ptr);
+        let mapped_atomics = MappedAtomics {
+            client_write: word(mem_ptr, CLIENT_OFFSET),
+            // wherever the layout put it. See LayoutMode
+            server_write: word(server_ptr, 0),
+            client_payload: word(mem_ptr, CLIENT_OFFSET + PAYLOAD_OFFSET),
+            server_payload: word(server_ptr, PAYLOAD_OFFSET),
+            control: word(mem_ptr, CONTROL_OFFSET),
+            header: word(mem_ptr, HEADER_OFFSET),
+            server_pid: word(mem_ptr, SERVER_PID_OFFSET),
+            layout: options.layout,
+            mappings,
+            shm_name,
+            fd: None,
+        };
+        // only zero out on creation, lest we romp on the values
+        // when the server starts up, after the client has been running.
+        if do_create {
+            mapped_atomics.client_write.store(0, Ordering::Relaxed);
+            mapped_atomics.server_write.store(0, Ordering::Relaxed);
+            mapped_atomics.client_payload.store(0, Ordering::Relaxed);
+            mapped_atomics.server_payload.store(0, Ordering::Relaxed);
+            mapped_atomics.control.store(0, Ordering::Relaxed);
+            mapped_atomics.server_pid.store(0, Ordering::Relaxed);
+            word(mem_ptr, MODE_OFFSET).store(options.layout.id(), Ordering::Relaxed);
+            word(mem_ptr, PREFAULT_OFFSET).store(options.prefault.id(), Ordering::Relaxed);
+            mapped_atomics.header.store(layout_header(), Ordering::Release);
+        }
+
+        mapped_atomics
+    }
+
+    /// where the server's words are.
+    pub fn layout(&self) -> LayoutMode {
+        self.layout
+    }
+
+    unsafe fn shm_open(shm_name: &CString, do_create:bool) -> c_int {
         let mem_fd = libc::shm_open(
-            crate::SH_MEM_NAME.as_ptr(),
+            shm_name.as_ptr(),
             if do_create {
                 libc::O_CREAT | libc::O_RDWR
             } else {
@@ -105,14 +401,14 @@ impl MappedAtomics {
         }
         mem_fd
     }
-    unsafe fn mmap(shm_fd:c_int) -> *mut c_void {
+    unsafe fn mmap(shm_fd: c_int, flags: c_int, len: usize, offset: usize) -> *mut c_void {
         let mem_ptr = libc::mmap(
             std::ptr::null_mut(),
-            page_size::get(),
+            len,
             libc::PROT_READ | libc::PROT_WRITE,
-            libc::MAP_SHARED,
+            flags,
             shm_fd,
-            0,
+            offset as libc::off_t,
         );
         if mem_ptr == libc::MAP_FAILED {
             panic!(
@@ -123,10 +419,27 @@ impl MappedAtomics {
         mem_ptr
     }
 
+    /// unmap, and remove the shm name so nothing else can open it.
     pub fn close(&self) {
-        unsafe {
-            libc::munmap(self.mmap_ptr, page_size::get());
-            libc::shm_unlink(crate::SH_MEM_NAME.as_ptr());
+        self.unmap();
+        if let Some(name) = &self.shm_name {
+            unsafe {
+                libc::shm_unlink(name.as_ptr());
+            }
+        }
+    }
+
+    /// unmap, but leave the shm for whoever else has it open.
+    pub fn unmap(&self) {
+        for (ptr, len) in self.mappings.iter() {
+            unsafe {
+                libc::munmap(*ptr, *len);
+            }
+        }
+        if let Some(fd) = self.fd {
+            unsafe {
+                libc::close(fd);
+            }
         }
     }
 }
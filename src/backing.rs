@@ -0,0 +1,267 @@
+use crate::layout::LayoutMode;
+use crate::numa::NumaPolicy;
+use std::ffi::{c_void, CString};
+use std::os::raw::c_int;
+use std::time::{Duration, Instant};
+
+// What's behind the segment, and whether it's faulted in up front.
+// POSIX shm is a tmpfs file, found by name. A memfd has no name, the
+// client passes the fd down to the server it launches. Huge pages are a
+// memfd on hugetlbfs, so a 2MB page (and one TLB entry) holds the lot.
+// Prefaulting moves the page faults from the first round trips to
+// when it's mapped.
+
+/// the env var the server finds the memfd in. See crate::shm_fd()
+pub static SHM_FD_ENV: &str = "SHM_FD";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backing {
+    /// shm_open(), the default.
+    #[default]
+    Shm,
+    /// memfd_create(), passed to the server as an inherited fd.
+    Memfd,
+    /// memfd_create(MFD_HUGETLB). Needs free huge pages,
+    /// ex : echo 16 > /proc/sys/vm/nr_hugepages
+    HugePages,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Prefault {
+    /// fault each page in on first touch.
+    #[default]
+    None,
+    /// MAP_POPULATE, fault them all in when mapped.
+    Populate,
+    /// MAP_POPULATE and mlock(), so they can't be paged out either.
+    Lock,
+}
+
+impl Backing {
+    pub const ALL: [Backing; 3] = [Backing::Shm, Backing::Memfd, Backing::HugePages];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backing::Shm => "shm",
+            Backing::Memfd => "memfd",
+            Backing::HugePages => "hugetlb",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Backing> {
+        Backing::ALL.into_iter().find(|b| b.name() == name.trim())
+    }
+}
+
+impl Prefault {
+    pub const ALL: [Prefault; 3] = [Prefault::None, Prefault::Populate, Prefault::Lock];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Prefault::None => "none",
+            Prefault::Populate => "populate",
+            Prefault::Lock => "mlock",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Prefault> {
+        Prefault::ALL.into_iter().find(|p| p.name() == name.trim())
+    }
+
+    /// what goes in the header, so the server prefaults its side too.
+    pub fn id(self) -> u64 {
+        match self {
+            Prefault::None => 0,
+            Prefault::Populate => 1,
+            Prefault::Lock => 2,
+        }
+    }
+
+    pub fn from_id(id: u64) -> Option<Prefault> {
+        Prefault::ALL.into_iter().find(|p| p.id() == id)
+    }
+}
+
+/// how the client sets up the segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MapOptions {
+    pub layout: LayoutMode,
+    pub backing: Backing,
+    pub prefault: Prefault,
+    /// which NUMA node the pages go on. See numa.rs
+    pub numa: NumaPolicy,
+}
+
+impl MapOptions {
+    /// "rust_atomic" -> "rust_atomic_memfd_mlock", so results w/ other options
+    /// aren't mixed up w/ the usual ones. The defaults leave the name alone.
+    pub fn bench_name(&self, bench_name: &str) -> String {
+        let mut name = bench_name.to_string();
+        if self.layout != LayoutMode::Default {
+            name = format!("{}_{}", name, self.layout.name());
+        }
+        if self.backing != Backing::Shm {
+            name = format!("{}_{}", name, self.backing.name());
+        }
+        if self.prefault != Prefault::None {
+            name = format!("{}_{}", name, self.prefault.name());
+        }
+        if self.numa != NumaPolicy::Default {
+            name = format!("{}_numa_{}", name, self.numa.name());
+        }
+        name
+    }
+
+    /// true if the server has to read the segment's header to go along
+    /// w/ these. Only some can, see registry::ServerSpec::follows_header
+    /// A memfd is passed down in SHM_FD, and the prefault is in the header
+    /// too. NUMA is all on the client's side.
+    pub fn needs_header(&self) -> bool {
+        self.layout != LayoutMode::Default || self.backing != Backing::Shm || self.prefault != Prefault::None
+    }
+
+    /// ex : "memfd", "hugetlb+mlock", "shm+populate"
+    pub fn parse_backing(text: &str) -> Option<(Backing, Prefault)> {
+        let (backing, prefault) = text.trim().split_once('+').unwrap_or((text.trim(), "none"));
+        Some((Backing::parse(backing)?, Prefault::parse(prefault)?))
+    }
+}
+
+/// the huge pages free right now, from /proc/meminfo.
+pub fn huge_pages_free() -> usize {
+    std::fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find_map(|l| l.strip_prefix("HugePages_Free:"))
+                .and_then(|n| n.trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+/// a memfd for the segment, left open across exec so the server can have it.
+pub(crate) fn memfd(name: &str, backing: Backing) -> c_int {
+    let name = CString::new(name.trim_start_matches('/')).unwrap();
+    let flags = match backing {
+        Backing::HugePages => libc::MFD_HUGETLB,
+        _ => 0,
+    };
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    if fd < 0 {
+        panic!("memfd_create failed. error num : {}", std::io::Error::last_os_error());
+    }
+    fd
+}
+
+/// the page size behind `fd`. The huge page size for hugetlbfs.
+pub(crate) fn fd_page_size(fd: c_int) -> usize {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        panic!("fstat on the shared memory fd failed : {}", std::io::Error::last_os_error());
+    }
+    (stat.st_blksize as usize).max(page_size::get())
+}
+
+pub(crate) fn map_flags(fd: c_int, prefault: Prefault) -> c_int {
+    let mut flags = libc::MAP_SHARED;
+    if fd < 0 {
+        flags |= libc::MAP_ANONYMOUS;
+    }
+    if prefault != Prefault::None {
+        flags |= libc::MAP_POPULATE;
+    }
+    flags
+}
+
+/// mlock() a mapping, if that's what `prefault` says.
+pub(crate) fn lock(ptr: *mut c_void, len: usize, prefault: Prefault) {
+    if prefault == Prefault::Lock && unsafe { libc::mlock(ptr, len) } < 0 {
+        panic!(
+            "mlock of {} bytes failed : {}. Check ulimit -l",
+            len,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// how long it takes to get a page of the backing to write to.
+#[derive(Debug, Clone)]
+pub struct FirstTouch {
+    pub page_size: usize,
+    /// the mmap() call, and the prefaulting if there is any.
+    pub map: Duration,
+    /// the first write to each of the two pages.
+    pub first_write: [Duration; 2],
+    /// a write to the first page again, once it's in.
+    pub warm_write: Duration,
+}
+
+/// map two fresh pages of `backing`, and time the first writes to them.
+/// Shm is anonymous shared memory here, it's the same shmem as shm_open().
+pub fn first_touch(backing: Backing, prefault: Prefault) -> FirstTouch {
+    unsafe {
+        let fd = match backing {
+            Backing::Shm => -1,
+            _ => memfd("first_touch", backing),
+        };
+        let page = if fd < 0 { page_size::get() } else { fd_page_size(fd) };
+        if fd >= 0 && libc::ftruncate(fd, (page * 2) as libc::off_t) < 0 {
+            panic!("can't size the memfd : {}", std::io::Error::last_os_error());
+        }
+        let start = Instant::now();
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            page * 2,
+            libc::PROT_READ | libc::PROT_WRITE,
+            map_flags(fd, prefault),
+            fd,
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            panic!("mmap of 2 {} pages failed : {}", backing.name(), std::io::Error::last_os_error());
+        }
+        lock(ptr, page * 2, prefault);
+        let map = start.elapsed();
+
+        let write = |offset: usize| {
+            let start = Instant::now();
+            std::ptr::write_volatile((ptr as *mut u8).add(offset) as *mut u64, 1);
+            start.elapsed()
+        };
+        let first_write = [write(0), write(page)];
+        let warm_write = write(8);
+
+        libc::munmap(ptr, page * 2);
+        if fd >= 0 {
+            libc::close(fd);
+        }
+        FirstTouch { page_size: page, map, first_write, warm_write }
+    }
+}
+
+/// print it, and write it to target/first_touch/<group>/<bench>.json
+pub fn report_first_touch(group_name: &str, bench_name: &str, touch: &FirstTouch) -> std::io::Result<()> {
+    println!(
+        "{} : {} KB pages, map {:.1} us, first writes {} / {} ns, warm write {} ns",
+        bench_name,
+        touch.page_size / 1024,
+        touch.map.as_secs_f64() * 1e6,
+        touch.first_write[0].as_nanos(),
+        touch.first_write[1].as_nanos(),
+        touch.warm_write.as_nanos()
+    );
+    let out = json::object! {
+        group: group_name,
+        name: bench_name,
+        page_size: touch.page_size,
+        map_ns: touch.map.as_nanos() as u64,
+        first_write_ns: touch.first_write.iter().map(|d| d.as_nanos() as u64).collect::<Vec<_>>(),
+        warm_write_ns: touch.warm_write.as_nanos() as u64,
+    };
+    let file = std::path::Path::new("target")
+        .join("first_touch")
+        .join(group_name)
+        .join(format!("{}.json", bench_name));
+    std::fs::create_dir_all(file.parent().unwrap())?;
+    std::fs::write(file, out.pretty(2))
+}
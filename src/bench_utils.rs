@@ -1,39 +1,101 @@
-use crate::{SAMPLE_SIZE, SERVER_CPU};
+use crate::CONFIG;
+use criterion::measurement::Measurement;
 use criterion::{BatchSize, Criterion};
 use rand::RngCore;
 use crate::atomic_spin::MappedAtomics;
+use crate::backing::{MapOptions, SHM_FD_ENV};
+use crate::servers::ServerFn;
+use crate::topology;
+use crate::perf::PerfCounters;
+use crate::warmup::CompilationLog;
+use std::cell::Cell;
+use std::process::Child;
+use std::time::{Duration, Instant};
+use crate::conformance::DEFAULT_STARTUP_TIMEOUT;
 use thread_priority::ThreadPriority;
+use core_affinity::CoreId;
 
 lazy_static! {
     // Must have java 19
     pub static ref JAVA_OPTS: Vec<&'static str> = vec![
         "-server",
-        "-Djava.library.path=zig/zig-out/lib",  // JVM used zig for shm_open call
+        "-Djava.library.path=target/release",  // libasync_bench.so, see src/ffi.rs
         "--enable-native-access=ALL-UNNAMED",
-        // "-XX:+PrintCompilation", // un-comment to see when the JIT decides to compile.
+        // -XX:+PrintCompilation is added w/ PRINT_COMPILATION=1, see warmup.rs
 
     ];
 }
-pub fn launch_local(cmd: &str, params: &Vec<&str>) -> std::process::Child {
+lazy_static! {
+    /// the CPUs we're allowed to run on. Grabbed once, before anything is pinned.
+    /// Asking later only gives back the CPU the thread was pinned to.
+    static ref AVAILABLE_CPUS: Vec<usize> = core_affinity::get_core_ids()
+        .expect("can't get the list of core ids")
+        .iter()
+        .map(|c| c.id)
+        .collect();
+}
+
+pub fn available_cpus() -> &'static [usize] {
+    &AVAILABLE_CPUS
+}
+
+/// criterion's options that take a value, so the value isn't the filter.
+const CRITERION_VALUE_OPTIONS: [&str; 19] = [
+    "-c", "--color", "--colour", "-s", "--save-baseline", "-b", "--baseline", "--baseline-lenient",
+    "--load-baseline", "--profile-time", "--sample-size", "--warm-up-time", "--measurement-time",
+    "--nresamples", "--noise-threshold", "--confidence-level", "--significance-level",
+    "--plotting-backend", "--output-format",
+];
+
+/// the filter criterion was given, its first plain argument,
+/// ex : `in_process` from `cargo bench -- --noplot in_process`.
+pub fn criterion_filter(args: &[String]) -> Option<&str> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if CRITERION_VALUE_OPTIONS.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+lazy_static! {
+    static ref FILTER: Option<regex::Regex> = criterion_filter(&std::env::args().collect::<Vec<_>>())
+        .map(|f| regex::Regex::new(f).unwrap_or_else(|e| panic!("bad benchmark filter {} : {}", f, e)));
+}
+
+/// false if criterion's filter skips `group_name/bench_name`. Then so
+/// do we, w/ the server, warm up, jitter, and everything after the timing.
+pub fn is_selected(group_name: &str, bench_name: &str) -> bool {
+    match FILTER.as_ref() {
+        Some(filter) => filter.is_match(&format!("{}/{}", group_name, bench_name)),
+        None => true,
+    }
+}
+
+pub fn launch_local(cmd: &str, server_cpu: usize, params: &Vec<&str>) -> std::process::Child {
     let mut process = std::process::Command::new("nice");
-    process.arg("-n").arg("-20").arg("taskset").arg("-c").arg(SERVER_CPU).arg(cmd);
+    process.arg("-n").arg("-20").arg("taskset").arg("-c").arg(server_cpu.to_string()).arg(cmd);
 
     for prm in params.iter() {
         process.arg(prm);
     }
     process
         .spawn()
-        .expect(format!("Can't spawn child process {}", cmd).as_str())
+        .unwrap_or_else(|_| panic!("Can't spawn child process {}", cmd))
 }
 
 pub fn launch_local_java(
     jar_file: &str,
     run_class: &str,
+    server_cpu: usize,
     java_opts: Option<&Vec<&str>>,
     program_args: &Vec<&str>,
 ) -> std::process::Child {
     let mut process = std::process::Command::new("nice");
-    process.arg("-n").arg("-20").arg("taskset").arg("-c").arg(SERVER_CPU).arg("java");
+    process.arg("-n").arg("-20").arg("taskset").arg("-c").arg(server_cpu.to_string()).arg("java");
 
     if let Some(j_opts) = java_opts {
         for opt in j_opts.iter() {
@@ -41,6 +103,12 @@ pub fn launch_local_java(
         }
     }
 
+    // bench_server() reads what the JIT is up to from stdout.
+    if CONFIG.print_compilation {
+        process.arg("-XX:+PrintCompilation");
+        process.stdout(std::process::Stdio::piped());
+    }
+
     process.arg("-cp").arg(jar_file).arg(run_class);
 
     for prm in program_args.iter() {
@@ -50,9 +118,303 @@ pub fn launch_local_java(
     process.spawn().expect("can't start java process")
 }
 
-/// some boilerplate code pulled out into a function.
-pub fn run_bench(c: &mut Criterion, group_name: &str, bench_name: &str, client:&MappedAtomics )
+/// record what machine this run is on, next to the results.
+/// Call before anything is pinned. See environment.rs
+pub fn snapshot_environment() {
+    // before pinning, while the whole list is still visible.
+    available_cpus();
+    crate::environment::save().expect("can't write the environment snapshot");
+    println!("environment snapshot in {}", crate::environment::output_file().display());
+}
+
+/// pin the calling thread to `cpu`. Panics if it's not
+/// a CPU we're allowed to run on, rather than silently
+/// benchmarking on whatever CPU the scheduler picks.
+pub fn pin_to(cpu: usize) {
+    if !available_cpus().contains(&cpu) {
+        panic!("CPU {} isn't available to pin to. Can use : {:?}", cpu, available_cpus());
+    }
+    if !core_affinity::set_for_current(CoreId { id: cpu }) {
+        panic!("can't pin this thread to CPU {}", cpu);
+    }
+}
+
+/// the (client, server) CPU pairs to benchmark.
+/// Normally just the configured pair. In sweep mode it's every
+/// ordered pair of different CPUs, to map out core-to-core latency.
+pub fn cpu_pairs() -> Vec<(usize, usize)> {
+    let pairs = configured_pairs();
+    if CONFIG.cross_node {
+        cross_node_pairs(pairs)
+    } else {
+        pairs
+    }
+}
+
+/// in cross-node mode, the server on another NUMA node from the client.
+/// The configured server CPU if it already is, else the first CPU that is.
+/// In sweep mode, the pairs that cross nodes. The pairs are left alone
+/// if there's only the one node.
+fn cross_node_pairs(pairs: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let crosses = |(c, s): &(usize, usize)| topology::cpu_node(*c) != topology::cpu_node(*s);
+    let crossing: Vec<(usize, usize)> = if CONFIG.sweep {
+        pairs.iter().copied().filter(crosses).collect()
+    } else {
+        pairs
+            .iter()
+            .filter_map(|p| match crosses(p) {
+                true => Some(*p),
+                false => crate::numa::cross_node_cpu(p.0, available_cpus()).map(|s| (p.0, s)),
+            })
+            .collect()
+    };
+    if crossing.is_empty() {
+        static WARN: std::sync::Once = std::sync::Once::new();
+        WARN.call_once(|| println!("only one NUMA node to run on, the cross-node mode does nothing"));
+        return pairs;
+    }
+    crossing
+}
+
+fn configured_pairs() -> Vec<(usize, usize)> {
+    if !CONFIG.sweep {
+        return vec![(CONFIG.client_cpu, CONFIG.server_cpu)];
+    }
+    let cpus = match &CONFIG.sweep_cpus {
+        Some(cpus) => cpus.clone(),
+        None => available_cpus().to_vec(),
+    };
+    let mut pairs = Vec::new();
+    for client in cpus.iter() {
+        for server in cpus.iter() {
+            if client != server {
+                pairs.push((*client, *server));
+            }
+        }
+    }
+    pairs
+}
+
+/// the benchmark name. In sweep mode the CPUs are tacked on the
+/// end so each pair gets its own criterion directory.
+pub fn pair_bench_name(bench_name: &str, client_cpu: usize, server_cpu: usize) -> String {
+    if CONFIG.sweep {
+        format!("{}_c{}_s{}", bench_name, client_cpu, server_cpu)
+    } else {
+        bench_name.to_string()
+    }
+}
+
+/// map the memory, pin the client, start the server and benchmark it.
+/// Once for each CPU pair in cpu_pairs() criterion's filter selects. `launch` is given the CPU
+/// the server should run on. The server finds the layout in the header,
+/// and a memfd in SHM_FD.
+pub fn bench_server<M, L>(c: &mut Criterion<M>, group_name: &str, bench_name: &str, options: MapOptions, launch: L)
+where
+    M: Measurement,
+    L: Fn(usize) -> std::process::Child,
 {
+    let bench_name = &options.bench_name(bench_name);
+    for (client_cpu, server_cpu) in cpu_pairs() {
+        if !is_selected(group_name, &pair_bench_name(bench_name, client_cpu, server_cpu)) {
+            continue;
+        }
+        let numa = options.numa.resolve(client_cpu, server_cpu);
+        let client = MappedAtomics::create(crate::shm_name(), MapOptions { numa, ..options });
+        report_placement(&pair_bench_name(bench_name, client_cpu, server_cpu), client_cpu, server_cpu, &client);
+
+        pin_to(client_cpu);
+
+        let noise_before = measure_noise(client_cpu, server_cpu);
+
+        // the server inherits the memfd, it only needs to know its number.
+        if let Some(fd) = client.shared_fd() {
+            std::env::set_var(SHM_FD_ENV, fd.to_string());
+        }
+        let mut child = launch(server_cpu);
+        std::env::remove_var(SHM_FD_ENV);
+        wait_for_server(&client, &mut child, bench_name);
+
+        // nice and taskset exec the server, so it keeps this pid.
+        // every thread of it, so the JVM's are counted too.
+        let server_counters = open_counters(child.id() as libc::pid_t, true);
+
+        // only piped when it's the JIT's log. See launch_local_java()
+        let compilations = child.stdout.take().map(CompilationLog::watch);
+
+        run_bench(
+            c,
+            group_name,
+            &pair_bench_name(bench_name, client_cpu, server_cpu),
+            &client,
+            server_cpu,
+            server_counters,
+            compilations.as_ref(),
+        );
+
+        client.close();
+        child.kill().expect("error killing server process");
+        child.wait().expect("error waiting on server process");
+
+        report_noise(
+            group_name,
+            &pair_bench_name(bench_name, client_cpu, server_cpu),
+            noise_before,
+            client_cpu,
+            server_cpu,
+        );
+    }
+}
+
+/// wait for the server to say it's ready. Panics if it exits first, or
+/// takes longer than conformance's startup timeout, rather than leave the
+/// client spinning on a server that isn't there.
+pub fn wait_for_server(client: &MappedAtomics, child: &mut Child, bench_name: &str) {
+    let start = Instant::now();
+    while client.server_ready().is_none() {
+        if let Some(status) = child.try_wait().expect("can't check on the server process") {
+            panic!("{} : the server exited w/ {} before it was ready", bench_name, status);
+        }
+        if start.elapsed() > DEFAULT_STARTUP_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            panic!("{} : the server wasn't ready after {:?}", bench_name, DEFAULT_STARTUP_TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// like bench_server(), but the server is a thread in this process,
+/// pinned to the server CPU, sharing anonymous memory (or a memfd) instead of shm.
+/// Only works for the Rust servers. `server` has to return on request_stop(),
+/// a thread can't be killed, so it's one of servers::STOPPABLE_SERVERS.
+pub fn bench_in_process<M: Measurement>(
+    c: &mut Criterion<M>,
+    group_name: &str,
+    bench_name: &str,
+    options: MapOptions,
+    server: ServerFn,
+) {
+    let bench_name = &options.bench_name(bench_name);
+    for (client_cpu, server_cpu) in cpu_pairs() {
+        if !is_selected(group_name, &pair_bench_name(bench_name, client_cpu, server_cpu)) {
+            continue;
+        }
+        let numa = options.numa.resolve(client_cpu, server_cpu);
+        let client = MappedAtomics::new_anonymous_with(MapOptions { numa, ..options });
+        report_placement(&pair_bench_name(bench_name, client_cpu, server_cpu), client_cpu, server_cpu, &client);
+        let (tid_tx, tid_rx) = std::sync::mpsc::channel();
+        let noise_before = measure_noise(client_cpu, server_cpu);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                pin_to(server_cpu);
+                ThreadPriority::Max.set_for_current().unwrap();
+                tid_tx.send(unsafe { libc::gettid() }).unwrap();
+                server(&client);
+            });
+
+            pin_to(client_cpu);
+
+            let server_tid = tid_rx.recv().expect("server thread didn't start");
+            let server_counters = open_counters(server_tid, false);
+
+            run_bench(
+                c,
+                group_name,
+                &pair_bench_name(bench_name, client_cpu, server_cpu),
+                &client,
+                server_cpu,
+                server_counters,
+                None,
+            );
+
+            client.request_stop();
+        });
+        client.close();
+
+        report_noise(
+            group_name,
+            &pair_bench_name(bench_name, client_cpu, server_cpu),
+            noise_before,
+            client_cpu,
+            server_cpu,
+        );
+    }
+}
+
+/// system noise on the client and server CPUs, w/ nothing running
+/// on them. None if it's turned off. See jitter.rs
+pub fn measure_noise(client_cpu: usize, server_cpu: usize) -> Option<Vec<crate::jitter::CpuJitter>> {
+    if CONFIG.jitter_secs <= 0.0 {
+        return None;
+    }
+    let mut cpus = vec![client_cpu, server_cpu];
+    cpus.dedup();
+    Some(crate::jitter::measure(
+        &cpus,
+        std::time::Duration::from_secs_f64(CONFIG.jitter_secs),
+        CONFIG.jitter_threshold_ns,
+    ))
+}
+
+/// which NUMA node each CPU and the segment are on.
+fn report_placement(bench_name: &str, client_cpu: usize, server_cpu: usize, client: &MappedAtomics) {
+    crate::numa::report_placement(
+        bench_name,
+        client_cpu,
+        server_cpu,
+        client.client_write as *const _ as usize,
+        client.server_write as *const _ as usize,
+    );
+}
+
+/// measure the noise again, now the benchmark is done, and
+/// report it w/ what it was before.
+fn report_noise(
+    group_name: &str,
+    bench_name: &str,
+    before: Option<Vec<crate::jitter::CpuJitter>>,
+    client_cpu: usize,
+    server_cpu: usize,
+) {
+    if let (Some(before), Some(after)) = (before, measure_noise(client_cpu, server_cpu)) {
+        crate::jitter::report(group_name, bench_name, &before, &after, CONFIG.jitter_noisy_pct)
+            .expect("can't write jitter output");
+    }
+}
+
+/// perf counters on thread `pid`, or each thread of process `pid` w/
+/// `whole_process`, if they're turned on and we're allowed them.
+pub fn open_counters(pid: libc::pid_t, whole_process: bool) -> Option<PerfCounters> {
+    if !CONFIG.perf_counters {
+        return None;
+    }
+    let counters = if whole_process { PerfCounters::open_process(pid) } else { PerfCounters::open(pid, false) };
+    if counters.is_empty() {
+        static WARN: std::sync::Once = std::sync::Once::new();
+        WARN.call_once(|| {
+            eprintln!("warning : can't open any perf counters. Check /proc/sys/kernel/perf_event_paranoid");
+        });
+        return None;
+    }
+    Some(counters)
+}
+
+/// some boilerplate code pulled out into a function.
+/// The client runs on this thread, the server's on `server_cpu`.
+/// `server_counters` are counting the server, the client's
+/// are opened here, on this thread. `compilations` is the
+/// JVM's JIT log, for the warm up to wait on.
+pub fn run_bench<M: Measurement>(
+    c: &mut Criterion<M>,
+    group_name: &str,
+    bench_name: &str,
+    client: &MappedAtomics,
+    server_cpu: usize,
+    server_counters: Option<PerfCounters>,
+    compilations: Option<&CompilationLog>,
+) {
     ThreadPriority::Max.set_for_current().unwrap();
 
     // run the client code once, just to make sure the server is up
@@ -60,12 +422,48 @@ pub fn run_bench(c: &mut Criterion, group_name: &str, bench_name: &str, client:&
     // while to startup... bless it's little heart.
     client.client_run_once(12345678 );
 
+    // and then until it's warmed up, and the JIT has settled down.
+    if CONFIG.stable_max_secs > 0.0 {
+        let warmup = crate::warmup::wait_until_stable(
+            client,
+            CONFIG.stable_window,
+            CONFIG.stable_tolerance_pct,
+            std::time::Duration::from_secs_f64(CONFIG.stable_max_secs),
+            compilations,
+        );
+        crate::warmup::report(bench_name, &warmup, compilations);
+    }
+
+    let timing = CONFIG.timing_for(group_name, bench_name);
+    let measurement = crate::adaptive::measurement_time(client, &timing, bench_name);
+
+    let client_counters = open_counters(0, false);
+    let read = |p: &Option<PerfCounters>| p.as_ref().map(|p| p.read()).unwrap_or_default();
+    // the counters only run while criterion is running the client, not
+    // while it works out its stats between samples and at the end.
+    let counting = |on: bool| {
+        for counters in [&client_counters, &server_counters].into_iter().flatten() {
+            if on {
+                counters.enable()
+            } else {
+                counters.disable()
+            }
+        }
+    };
+    counting(false);
+    let (client_before, server_before) = (read(&client_counters), read(&server_counters));
+    // counted in the setup, so it's not part of the timing. The setup
+    // only runs inside iter_batched, so this is every round trip
+    // the counters saw, and nothing else.
+    let round_trips = Cell::new(0u64);
+
     // let thid = std::thread::current().id();
     let mut group = c.benchmark_group(group_name);
-    // group.warm_up_time( WARMUP_TIME );
-    // group.measurement_time(RUN_TIME);
-    group.sample_size(SAMPLE_SIZE);
+    group.warm_up_time(timing.warmup);
+    group.measurement_time(measurement);
+    group.sample_size(timing.sample_size);
     group.bench_function(bench_name, |b| {
+        counting(true);
         b.iter_batched(
             || {
                 // convince myself that the time to gen the rnd
@@ -73,6 +471,7 @@ pub fn run_bench(c: &mut Criterion, group_name: &str, bench_name: &str, client:&
                 // out next like and see if the benchmark
                 // results change
                 // std::thread::sleep( std::time::Duration::from_millis(1));
+                round_trips.set(round_trips.get() + 1);
                 rand::thread_rng().next_u64()
             },
             |payload| {
@@ -80,7 +479,41 @@ pub fn run_bench(c: &mut Criterion, group_name: &str, bench_name: &str, client:&
                 client.client_run_once(payload)
             },
             BatchSize::SmallInput,
-        )
+        );
+        counting(false);
     });
     group.finish();
+
+    // the same results, where scripts can find them.
+    let summary = crate::results::report(group_name, bench_name).expect("can't write the results record");
+    if let (Some(target), Some(s)) = (timing.target_ci_pct, summary) {
+        let width = (s.median_ci_high - s.median_ci_low) / s.median * 100.0;
+        if width > target {
+            println!("{} : median CI ended up {:.2}% wide, over the {}% target", bench_name, width, target);
+        }
+    }
+
+    // either side may have been refused, report what we got.
+    if client_counters.is_some() || server_counters.is_some() {
+        let client_delta = crate::perf::delta(&client_before, &read(&client_counters));
+        let server_delta = crate::perf::delta(&server_before, &read(&server_counters));
+        crate::perf::report(group_name, bench_name, &client_delta, &server_delta, round_trips.get())
+            .expect("can't write perf counter output");
+    }
+
+    // time each round trip on its own too, for the tail.
+    if CONFIG.hdr_samples > 0 {
+        let hist = crate::hdr::record_round_trips(client, CONFIG.hdr_samples);
+        crate::hdr::report(group_name, bench_name, &hist).expect("can't write histogram output");
+    }
+
+    // and at fixed rates, w/o the closed loop hiding the queueing.
+    if !CONFIG.open_loop_rates.is_empty() {
+        match crate::open_loop::sweep(client, &CONFIG.open_loop_rates, CONFIG.open_loop_secs, server_cpu) {
+            Some(points) => {
+                crate::open_loop::report(group_name, bench_name, &points).expect("can't write open loop output")
+            }
+            None => println!("{} : skipping open loop, no CPU left for the load generator", bench_name),
+        }
+    }
 }
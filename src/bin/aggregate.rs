@@ -0,0 +1,95 @@
+use async_bench::aggregate::{load_dir, machine_dirs, machine_name, to_csv, to_json, BestBy};
+use std::path::{Path, PathBuf};
+
+///
+/// Summarize the runs run_bench copied into runs/. For each benchmark
+/// it works out the median, CI and percentiles of every run, and picks
+/// the best one. Sub directories of runs/ are other machines.
+///
+/// usage : aggregate [--runs runs] [--best-by hi|median|mean|p99] [--machine NAME] [--out summary]
+/// writes <out>/<machine>.json and <out>/<machine>.csv
+struct Args {
+    runs: PathBuf,
+    best_by: BestBy,
+    machine: Option<String>,
+    out: PathBuf,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        runs: PathBuf::from("runs"),
+        best_by: BestBy::Hi,
+        machine: None,
+        out: PathBuf::from("summary"),
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--runs" => args.runs = PathBuf::from(value()),
+            "--best-by" => args.best_by = BestBy::parse(&value()),
+            "--machine" => args.machine = Some(value()),
+            "--out" => args.out = PathBuf::from(value()),
+            _ => panic!(
+                "unknown option {}. usage : aggregate [--runs DIR] [--best-by hi|median|mean|p99] [--machine NAME] [--out DIR]",
+                arg
+            ),
+        }
+    }
+    args
+}
+
+/// "Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz" -> "Intel_R_Core_TM_i7-8700K_CPU_3.70GHz"
+fn file_name(machine: &str) -> String {
+    machine
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn main() -> std::io::Result<()> {
+    let args = parse_args();
+    let local = args
+        .machine
+        .clone()
+        .or_else(|| machine_name(&args.runs))
+        .unwrap_or_else(|| "local".to_string());
+
+    std::fs::create_dir_all(&args.out)?;
+    for (machine, dir) in machine_dirs(&args.runs, &local)? {
+        let benchmarks = load_dir(&dir, args.best_by)?;
+        if benchmarks.is_empty() {
+            continue;
+        }
+        println!("{} : best run by {}", machine, args.best_by.name());
+        println!(
+            "{:24} {:>5} {:>5} {:>10} {:>10} {:>10} {:>10}  unit",
+            "benchmark", "runs", "best", "median", "p97.5", "p99", "max"
+        );
+        for b in benchmarks.iter() {
+            let best = b.best_run();
+            println!(
+                "{:24} {:>5} {:>5} {:>10.1} {:>10.1} {:>10.1} {:>10.1}  {}",
+                b.id,
+                b.runs.len(),
+                best.run,
+                best.summary.median,
+                best.summary.p97_5,
+                best.summary.p99,
+                best.summary.max,
+                best.unit
+            );
+        }
+
+        let base: &Path = &args.out;
+        let name = file_name(&machine);
+        std::fs::write(
+            base.join(format!("{}.json", name)),
+            to_json(&machine, args.best_by, &benchmarks).pretty(2),
+        )?;
+        std::fs::write(base.join(format!("{}.csv", name)), to_csv(&benchmarks))?;
+        println!("wrote {0}/{1}.json and {0}/{1}.csv\n", base.display(), name);
+    }
+    Ok(())
+}
@@ -1,12 +1,14 @@
 use async_bench::atomic_spin::MappedAtomics;
+use async_bench::servers::run_from_args;
 use std::io;
 
 fn main() -> io::Result<()> {
 
     let server = MappedAtomics::new(false);
+    server.mark_server_ready();
 
     println!("\nstarting server");
-    server.do_server_loop();
+    run_from_args("rust_atomic", &server);
 
     Ok(())
 }
@@ -0,0 +1,131 @@
+use async_bench::aggregate::{load_best, BestBy};
+use async_bench::compare::{compare_all, Thresholds, Verdict, DEFAULT_RESAMPLES};
+use async_bench::stats::effect_size;
+use std::path::PathBuf;
+
+///
+/// Compare the same benchmarks from two result sets, instead of eyeballing
+/// criterion's "change" line. Each is a runs directory, a summary from
+/// aggregate, or a single raw.csv. The best run of each benchmark is used.
+///
+/// usage : compare BASE NEW [--threshold PCT] [--p99-threshold PCT] [--alpha 0.05]
+///                 [--best-by hi|median|mean|p99] [--resamples N] [--json FILE]
+///   --threshold      the median has to get this many percent slower (and
+///                    Mann-Whitney say it's significant) to be a regression. 5 by default.
+///   --p99-threshold  the p99 CI has to be entirely this many percent slower.
+///                    Off by default.
+/// Exits w/ 1 if anything regressed, so it can gate changes.
+struct Args {
+    base: PathBuf,
+    new: PathBuf,
+    thresholds: Thresholds,
+    best_by: BestBy,
+    resamples: usize,
+    json: Option<PathBuf>,
+}
+
+fn parse_args() -> Args {
+    let usage = "usage : compare BASE NEW [--threshold PCT] [--p99-threshold PCT] [--alpha 0.05] [--best-by hi|median|mean|p99] [--resamples N] [--json FILE]";
+    let mut paths = Vec::new();
+    let mut thresholds = Thresholds::default();
+    let mut best_by = BestBy::Hi;
+    let mut resamples = DEFAULT_RESAMPLES;
+    let mut json = None;
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--threshold" => thresholds.median_pct = value().parse().expect("--threshold must be a percent"),
+            "--p99-threshold" => {
+                thresholds.p99_pct = Some(value().parse().expect("--p99-threshold must be a percent"))
+            }
+            "--alpha" => thresholds.alpha = value().parse().expect("--alpha must be a number"),
+            "--best-by" => best_by = BestBy::parse(&value()),
+            "--resamples" => resamples = value().parse().expect("--resamples must be a number"),
+            "--json" => json = Some(PathBuf::from(value())),
+            _ if arg.starts_with("--") => panic!("unknown option {}. {}", arg, usage),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.len() != 2 {
+        panic!("need a base and a new result set. {}", usage);
+    }
+    let new = paths.pop().unwrap();
+    let base = paths.pop().unwrap();
+    Args {
+        base,
+        new,
+        thresholds,
+        best_by,
+        resamples,
+        json,
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let args = parse_args();
+    let base = load_best(&args.base, args.best_by)?;
+    let new = load_best(&args.new, args.best_by)?;
+    let (comparisons, unmatched) = compare_all(&base, &new, args.resamples);
+    let t = &args.thresholds;
+
+    println!("{} -> {}", args.base.display(), args.new.display());
+    println!(
+        "{:24} {:>10} {:>10} {:>24} {:>24} {:>8} {:>7} {:>10}  verdict",
+        "benchmark", "median", "new", "median change [95% CI]", "p99 change [95% CI]", "p", "delta", "effect"
+    );
+    let mut regressions = Vec::new();
+    for c in comparisons.iter() {
+        let verdict = c.verdict(t);
+        if verdict == Verdict::Slower {
+            regressions.push(c.id.clone());
+        }
+        let change = |(estimate, lo, hi): (f64, f64, f64)| format!("{:+.1}% [{:+.1}, {:+.1}]", estimate, lo, hi);
+        println!(
+            "{:24} {:>10.1} {:>10.1} {:>24} {:>24} {:>8.4} {:>+7.2} {:>10}  {}",
+            c.id,
+            c.base.median,
+            c.new.median,
+            change(c.median_change),
+            change(c.p99_change),
+            c.test.p,
+            c.test.delta,
+            effect_size(c.test.delta),
+            match verdict {
+                Verdict::Faster => "faster",
+                Verdict::Slower => "SLOWER",
+                Verdict::Same => "same",
+            }
+        );
+    }
+    for id in unmatched.iter() {
+        println!("{:24} only in one set, skipped", id);
+    }
+
+    if let Some(file) = args.json.as_ref() {
+        let mut list = json::JsonValue::new_array();
+        for c in comparisons.iter() {
+            let _ = list.push(c.to_json(t));
+        }
+        let out = json::object! {
+            base: args.base.to_string_lossy().to_string(),
+            new: args.new.to_string_lossy().to_string(),
+            alpha: t.alpha,
+            threshold_pct: t.median_pct,
+            p99_threshold_pct: t.p99_pct,
+            benchmarks: list,
+            unmatched: unmatched.clone(),
+        };
+        std::fs::write(file, out.pretty(2))?;
+    }
+
+    if comparisons.is_empty() {
+        eprintln!("no benchmarks in both sets");
+        std::process::exit(2);
+    }
+    if !regressions.is_empty() {
+        eprintln!("regressed : {}", regressions.join(", "));
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use async_bench::conformance::{check, print_report, Expect, Options, Outcome, Report};
+use async_bench::registry::{self, SERVERS};
+use async_bench::servers::CHECKED_FLAG;
+use std::process::Command;
+use std::time::Duration;
+
+///
+/// Checks each server speaks the shared memory protocol, before a
+/// benchmark hangs on it. See src/conformance.rs for the checks.
+///
+/// usage : conformance [NAME ...] [--values N] [--startup-timeout SECS] [--timeout SECS]
+///   checks the servers in the registry (src/registry.rs), or just the ones named.
+///   Servers that haven't been built are skipped. The Rust ones are
+///   checked again w/ --checked, their test mode.
+///
+/// usage : conformance --cmd "COMMAND ARGS" [--echoes-payload] [--stops] ...
+///   checks any command, ex : a server that isn't in the registry yet.
+///   --echoes-payload and --stops say it does what the Rust servers do w/ --checked.
+///
+/// Run it from the repo root, the registry's paths are relative to it.
+/// Exits 1 if anything failed.
+struct Args {
+    names: Vec<String>,
+    cmd: Option<String>,
+    expect: Expect,
+    options: Options,
+}
+
+fn parse_args() -> Args {
+    let usage = "usage : conformance [NAME ...] [--cmd COMMAND] [--echoes-payload] [--stops] [--values N] [--startup-timeout SECS] [--timeout SECS]";
+    let mut args = Args {
+        names: vec![],
+        cmd: None,
+        expect: Expect::default(),
+        options: Options::default(),
+    };
+    let secs = |v: String, flag: &str| {
+        Duration::from_secs_f64(v.parse().unwrap_or_else(|_| panic!("{} must be a number of seconds", flag)))
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--cmd" => args.cmd = Some(value()),
+            "--echoes-payload" => args.expect.echoes_payload = true,
+            "--stops" => args.expect.stops = true,
+            "--values" => args.options.values = value().parse().expect("--values must be a number"),
+            "--startup-timeout" => args.options.startup_timeout = secs(value(), "--startup-timeout"),
+            "--timeout" => args.options.echo_timeout = secs(value(), "--timeout"),
+            "-h" | "--help" => {
+                println!("{}", usage);
+                std::process::exit(0);
+            }
+            _ if arg.starts_with('-') => panic!("unknown option {}. {}", arg, usage),
+            _ => args.names.push(arg),
+        }
+    }
+    args
+}
+
+fn main() {
+    let args = parse_args();
+    let shm_name = async_bench::shm_name();
+
+    let mut reports: Vec<Report> = vec![];
+    if let Some(cmd) = &args.cmd {
+        let mut words = cmd.split_whitespace();
+        let mut command = Command::new(words.next().expect("--cmd is empty"));
+        command.args(words);
+        reports.push(check(cmd, command, args.expect, shm_name.clone(), &args.options));
+        print_report(reports.last().unwrap());
+    } else {
+        for name in args.names.iter() {
+            if registry::find(name).is_none() {
+                let known: Vec<&str> = SERVERS.iter().map(|s| s.name).collect();
+                panic!("no server called {} in the registry. There's {}", name, known.join(", "));
+            }
+        }
+        for server in SERVERS.iter().filter(|s| args.names.is_empty() || args.names.iter().any(|n| n == s.name)) {
+            if !server.is_built() {
+                reports.push(Report::skipped(server.name, "not built"));
+                print_report(reports.last().unwrap());
+                continue;
+            }
+            let expect = Expect { echoes_payload: server.echoes_payload, stops: server.stops };
+            reports.push(check(server.name, server.command(), expect, shm_name.clone(), &args.options));
+            print_report(reports.last().unwrap());
+            // the Rust servers' test mode, which echoes the payload and stops.
+            if let Some(cmd) = server.checked_command() {
+                let name = format!("{} {}", server.name, CHECKED_FLAG);
+                let expect = Expect { echoes_payload: true, stops: true };
+                reports.push(check(&name, cmd, expect, shm_name.clone(), &args.options));
+                print_report(reports.last().unwrap());
+            }
+        }
+    }
+
+    let failed: Vec<&str> = reports.iter().filter(|r| !r.passed()).map(|r| r.server.as_str()).collect();
+    let skipped = reports
+        .iter()
+        .filter(|r| r.checks.iter().all(|c| c.outcome == Outcome::Skip))
+        .count();
+    println!();
+    println!(
+        "{} servers, {} passed, {} skipped, {} failed{}",
+        reports.len(),
+        reports.len() - failed.len() - skipped,
+        skipped,
+        failed.len(),
+        if failed.is_empty() { String::new() } else { format!(" : {}", failed.join(", ")) }
+    );
+    if !failed.is_empty() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,181 @@
+use async_bench::atomic_spin::{MappedAtomics, Stoppable};
+use async_bench::bench_utils::{available_cpus, pin_to};
+use async_bench::config::parse_cpu_list;
+use async_bench::topology::{online_cpus, CpuRelation};
+use std::io;
+use std::io::Write;
+use std::time::Instant;
+
+///
+/// Ping-pong between every ordered pair of CPUs, to see which
+/// client/server pair is the fastest on this machine.
+/// The server is a thread in this process, so no server binary needed.
+///
+/// usage : core_matrix [--cpus 0-5] [--iters 100000] [--out core_matrix]
+/// writes <out>.csv and <out>.json
+struct Args {
+    cpus: Vec<usize>,
+    iters: usize,
+    out: String,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        cpus: online_cpus()
+            .into_iter()
+            .filter(|c| available_cpus().contains(c))
+            .collect(),
+        iters: 100_000,
+        out: "core_matrix".to_string(),
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--cpus" => args.cpus = parse_cpu_list(&value()),
+            "--iters" => args.iters = value().parse().expect("--iters must be a number"),
+            "--out" => args.out = value(),
+            _ => panic!("unknown option {}. usage : core_matrix [--cpus LIST] [--iters N] [--out PREFIX]", arg),
+        }
+    }
+    if args.cpus.len() < 2 {
+        panic!("need at least two CPUs, got {:?}", args.cpus);
+    }
+    // one side failing to pin would leave the other spinning forever.
+    let missing: Vec<usize> = args.cpus.iter().copied().filter(|c| !available_cpus().contains(c)).collect();
+    if !missing.is_empty() {
+        panic!("CPUs {:?} aren't available. Can use : {:?}", missing, available_cpus());
+    }
+    args
+}
+
+/// median and p99 round trip, in ns.
+#[derive(Clone, Copy)]
+struct PairResult {
+    median: u64,
+    p99: u64,
+}
+
+fn measure_pair(client_cpu: usize, server_cpu: usize, iters: usize) -> PairResult {
+    let atomics = MappedAtomics::new_anonymous();
+
+    let mut times = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            pin_to(server_cpu);
+            atomics.do_server_loop::<Stoppable>();
+        });
+
+        pin_to(client_cpu);
+
+        let mut value: u64 = 0;
+        for _ in 0..iters / 10 {
+            value += 1;
+            atomics.client_run_once(value);
+        }
+
+        let mut times = Vec::with_capacity(iters);
+        for _ in 0..iters {
+            value += 1;
+            let start = Instant::now();
+            atomics.client_run_once(value);
+            times.push(start.elapsed().as_nanos() as u64);
+        }
+        atomics.request_stop();
+        times
+    });
+    atomics.close();
+
+    times.sort_unstable();
+    PairResult {
+        median: times[times.len() / 2],
+        p99: times[(times.len() * 99) / 100],
+    }
+}
+
+/// one NxN block. rows are the client CPU, columns the server CPU.
+fn write_block(
+    file: &mut std::fs::File,
+    name: &str,
+    cpus: &[usize],
+    cell: impl Fn(usize, usize) -> String,
+) -> io::Result<()> {
+    let header: Vec<String> = cpus.iter().map(|c| c.to_string()).collect();
+    writeln!(file, "{},{}", name, header.join(","))?;
+    for (r, cpu) in cpus.iter().enumerate() {
+        let row: Vec<String> = (0..cpus.len()).map(|c| cell(r, c)).collect();
+        writeln!(file, "{},{}", cpu, row.join(","))?;
+    }
+    writeln!(file)
+}
+
+fn write_csv(args: &Args, results: &[Vec<Option<PairResult>>]) -> io::Result<()> {
+    let mut file = std::fs::File::create(format!("{}.csv", args.out))?;
+    write_block(&mut file, "median_ns", &args.cpus, |r, c| {
+        results[r][c].map(|p| p.median.to_string()).unwrap_or_default()
+    })?;
+    write_block(&mut file, "p99_ns", &args.cpus, |r, c| {
+        results[r][c].map(|p| p.p99.to_string()).unwrap_or_default()
+    })?;
+    write_block(&mut file, "relation", &args.cpus, |r, c| {
+        CpuRelation::of(args.cpus[r], args.cpus[c]).marker().to_string()
+    })
+}
+
+fn write_json(args: &Args, results: &[Vec<Option<PairResult>>]) -> io::Result<()> {
+    let matrix = |f: &dyn Fn(&PairResult) -> u64| -> json::JsonValue {
+        results
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|r| match r {
+                        Some(p) => json::JsonValue::from(f(p)),
+                        None => json::JsonValue::Null,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .into()
+    };
+    let relations: Vec<Vec<&str>> = args
+        .cpus
+        .iter()
+        .map(|a| args.cpus.iter().map(|b| CpuRelation::of(*a, *b).marker()).collect())
+        .collect();
+
+    let out = json::object! {
+        cpus: args.cpus.clone(),
+        iterations: args.iters,
+        median_ns: matrix(&|p| p.median),
+        p99_ns: matrix(&|p| p.p99),
+        relation: relations,
+    };
+    std::fs::write(format!("{}.json", args.out), out.pretty(2))
+}
+
+fn main() -> io::Result<()> {
+    let args = parse_args();
+
+    let mut results = vec![vec![None; args.cpus.len()]; args.cpus.len()];
+    for (r, client_cpu) in args.cpus.iter().enumerate() {
+        for (c, server_cpu) in args.cpus.iter().enumerate() {
+            if client_cpu == server_cpu {
+                continue;
+            }
+            let result = measure_pair(*client_cpu, *server_cpu, args.iters);
+            eprintln!(
+                "client {:3} server {:3} ({:>4}) : median {:6} ns p99 {:6} ns",
+                client_cpu,
+                server_cpu,
+                CpuRelation::of(*client_cpu, *server_cpu).marker(),
+                result.median,
+                result.p99
+            );
+            results[r][c] = Some(result);
+        }
+    }
+
+    write_csv(&args, &results)?;
+    write_json(&args, &results)?;
+    eprintln!("wrote {0}.csv and {0}.json", args.out);
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use async_bench::env_check::{check_all, Check};
+use async_bench::CONFIG;
+
+///
+/// Check the machine is ready to benchmark on the client and
+/// server CPUs (isolcpus, SMT siblings, governors, turbo, IRQs,
+/// other runnable tasks), and say what to do about it.
+/// With --fix it makes the changes it can. Needs root for that.
+///
+/// usage : env_check [--client-cpu N] [--server-cpu N] [--fix]
+/// The CPUs default to the benchmark config, see src/config.rs
+/// Exits w/ 1 if there are still warnings. Notes don't count.
+struct Args {
+    client_cpu: usize,
+    server_cpu: usize,
+    fix: bool,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        client_cpu: CONFIG.client_cpu,
+        server_cpu: CONFIG.server_cpu,
+        fix: false,
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--client-cpu" => args.client_cpu = value().parse().expect("--client-cpu must be a number"),
+            "--server-cpu" => args.server_cpu = value().parse().expect("--server-cpu must be a number"),
+            "--fix" => args.fix = true,
+            _ => panic!("unknown option {}. usage : env_check [--client-cpu N] [--server-cpu N] [--fix]", arg),
+        }
+    }
+    args
+}
+
+fn print_checks(checks: &[Check]) {
+    for check in checks.iter() {
+        if check.ok() {
+            println!("[ ok ] {}", check.name);
+        }
+        for warning in check.warnings.iter() {
+            println!("[warn] {} : {}", check.name, warning);
+        }
+        for note in check.notes.iter() {
+            println!("[note] {} : {}", check.name, note);
+        }
+    }
+}
+
+fn main() {
+    let args = parse_args();
+    println!("client CPU {}, server CPU {}", args.client_cpu, args.server_cpu);
+
+    let mut checks = check_all(args.client_cpu, args.server_cpu);
+    print_checks(&checks);
+
+    let fixes: Vec<_> = checks.iter().flat_map(|c| c.fixes.iter()).collect();
+    if !fixes.is_empty() && !args.fix {
+        println!("\nrun w/ --fix (as root) to :");
+        for fix in fixes.iter() {
+            println!("  echo {} > {}", fix.value, fix.path);
+        }
+    }
+
+    if args.fix && !fixes.is_empty() {
+        println!();
+        for fix in fixes {
+            match fix.apply() {
+                Ok(()) => println!("[fix ] {} -> {}", fix.path, fix.value),
+                Err(e) => println!("[fail] {} -> {} : {}", fix.path, fix.value, e),
+            }
+        }
+        println!("\nafter fixing :");
+        checks = check_all(args.client_cpu, args.server_cpu);
+        print_checks(&checks);
+    }
+
+    if checks.iter().any(|c| !c.ok()) {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,36 @@
+use async_bench::layout;
+use std::path::Path;
+
+///
+/// Write the shared memory layout out as the C header the C++ and
+/// zig servers include. See src/layout.rs. Run it after changing the
+/// layout, and commit the header w/ it. The layout_header_matches_the_rust
+/// test fails until you do.
+///
+/// usage : gen_header [--check]
+///   --check  don't write it, exit w/ 1 if it's stale.
+static LAYOUT_HEADER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/include/spin_layout.h");
+
+fn main() {
+    let mut check = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => panic!("unknown option {}. usage : gen_header [--check]", arg),
+        }
+    }
+
+    let header = layout::c_header();
+    let current = std::fs::read_to_string(LAYOUT_HEADER).ok();
+    if current.as_deref() == Some(header.as_str()) {
+        println!("{} is up to date", LAYOUT_HEADER);
+        return;
+    }
+    if check {
+        println!("{} is stale, run cargo run --bin gen_header", LAYOUT_HEADER);
+        std::process::exit(1);
+    }
+    std::fs::create_dir_all(Path::new(LAYOUT_HEADER).parent().unwrap()).unwrap();
+    std::fs::write(LAYOUT_HEADER, header).unwrap_or_else(|e| panic!("can't write {} : {}", LAYOUT_HEADER, e));
+    println!("wrote {}", LAYOUT_HEADER);
+}
@@ -0,0 +1,154 @@
+use async_bench::aggregate::{load_best, load_environment, BestBy};
+use async_bench::compare::Thresholds;
+use async_bench::history::{append, default_file, load, series, Record};
+use std::path::PathBuf;
+
+///
+/// The results of every commit, kept in an append only JSONL file.
+///
+/// usage : history add [--runs runs] [--best-by hi|median|mean|p99] [--store history.jsonl]
+///   adds the best run of each benchmark in runs/, w/ the commit and machine from
+///   the environment_r<N>.json there. run_bench does this. For another machine's
+///   results, point --runs at them, ex : runs/amd
+///
+/// usage : history [--store history.jsonl] [--bench NAME] [--threshold PCT] [--p99-threshold PCT]
+///                 [--alpha 0.05] [--resamples N]
+///   prints each benchmark's trend, and marks the first commit that was slower
+///   than the one before it, by the same rules as compare.
+struct Args {
+    add: bool,
+    runs: PathBuf,
+    best_by: BestBy,
+    store: PathBuf,
+    bench: Option<String>,
+    thresholds: Thresholds,
+    resamples: usize,
+}
+
+fn parse_args() -> Args {
+    let usage = "usage : history [add] [--runs DIR] [--best-by hi|median|mean|p99] [--store FILE] [--bench NAME] [--threshold PCT] [--p99-threshold PCT] [--alpha 0.05] [--resamples N]";
+    let mut args = Args {
+        add: false,
+        runs: PathBuf::from("runs"),
+        best_by: BestBy::Hi,
+        store: default_file(),
+        bench: None,
+        thresholds: Thresholds::default(),
+        resamples: 2000,
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "add" => args.add = true,
+            "--runs" => args.runs = PathBuf::from(value()),
+            "--best-by" => args.best_by = BestBy::parse(&value()),
+            "--store" => args.store = PathBuf::from(value()),
+            "--bench" => args.bench = Some(value()),
+            "--threshold" => args.thresholds.median_pct = value().parse().expect("--threshold must be a percent"),
+            "--p99-threshold" => {
+                args.thresholds.p99_pct = Some(value().parse().expect("--p99-threshold must be a percent"))
+            }
+            "--alpha" => args.thresholds.alpha = value().parse().expect("--alpha must be a number"),
+            "--resamples" => args.resamples = value().parse().expect("--resamples must be a number"),
+            _ => panic!("unknown option {}. {}", arg, usage),
+        }
+    }
+    args
+}
+
+/// days since the epoch -> "2024-03-01". Howard Hinnant's civil_from_days.
+fn date(timestamp: u64) -> String {
+    let z = (timestamp / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn add(args: &Args) -> std::io::Result<()> {
+    let Some(env) = load_environment(&args.runs) else {
+        panic!(
+            "no environment_r<N>.json in {}, can't tell which commit or machine these are",
+            args.runs.display()
+        );
+    };
+    let records: Vec<Record> = load_best(&args.runs, args.best_by)?
+        .iter()
+        .map(|(id, run)| Record::new(id, run, &env))
+        .collect();
+    append(&args.store, &records)?;
+    println!(
+        "added {} benchmarks at {} to {}",
+        records.len(),
+        records.first().map(|r| r.short_commit()).unwrap_or_default(),
+        args.store.display()
+    );
+    Ok(())
+}
+
+fn show(args: &Args) -> std::io::Result<()> {
+    let records = load(&args.store)?;
+    if records.is_empty() {
+        println!("nothing in {} yet, run history add", args.store.display());
+        return Ok(());
+    }
+    let t = &args.thresholds;
+    for s in series(&records) {
+        if args.bench.as_ref().is_some_and(|b| !s.id.contains(b.as_str())) {
+            continue;
+        }
+        let steps = s.steps(args.resamples);
+        let first = s.first_regression(&steps, t);
+        println!("{} on {} ({})", s.id, s.machine, s.fingerprint);
+        println!(
+            "  {:10}  {:9}  {:>10} {:>10}  {:>24} {:>8}",
+            "date", "commit", "median", "p99", "median change [95% CI]", "p"
+        );
+        for (i, (r, step)) in s.records.iter().zip(steps.iter()).enumerate() {
+            let (change, p) = match step {
+                Some(c) => (
+                    format!(
+                        "{:+.1}% [{:+.1}, {:+.1}]",
+                        c.median_change.0, c.median_change.1, c.median_change.2
+                    ),
+                    format!("{:.4}", c.test.p),
+                ),
+                None => (String::new(), String::new()),
+            };
+            println!(
+                "  {:10}  {:9}  {:>10.1} {:>10.1}  {:>24} {:>8}{}",
+                date(r.timestamp),
+                r.short_commit(),
+                r.summary.median,
+                r.summary.p99,
+                change,
+                p,
+                if first == Some(i) { "  <- first regression" } else { "" }
+            );
+        }
+        if let (Some(oldest), Some(newest)) = (s.records.first(), s.records.last()) {
+            println!(
+                "  {:+.1}% median since {} over {} commits\n",
+                (newest.summary.median - oldest.summary.median) / oldest.summary.median * 100.0,
+                oldest.short_commit(),
+                s.records.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let args = parse_args();
+    if args.add {
+        add(&args)
+    } else {
+        show(&args)
+    }
+}
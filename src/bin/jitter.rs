@@ -0,0 +1,58 @@
+use async_bench::config::parse_cpu_list;
+use async_bench::jitter;
+use async_bench::CONFIG;
+use std::time::Duration;
+
+///
+/// Spin on each CPU reading the TSC, and report every time
+/// something took the CPU away for longer than the threshold,
+/// w/ the interrupts that went to it meanwhile. Like sysjitter.
+///
+/// usage : jitter [--cpus 4,5] [--secs 10] [--threshold NS] [--json FILE]
+/// The CPUs default to the benchmark client and server CPUs.
+struct Args {
+    cpus: Vec<usize>,
+    secs: f64,
+    threshold_ns: u64,
+    json: Option<String>,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        cpus: vec![CONFIG.client_cpu, CONFIG.server_cpu],
+        secs: 10.0,
+        threshold_ns: CONFIG.jitter_threshold_ns,
+        json: None,
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--cpus" => args.cpus = parse_cpu_list(&value()),
+            "--secs" => args.secs = value().parse().expect("--secs must be a number"),
+            "--threshold" => args.threshold_ns = value().parse().expect("--threshold must be a number of ns"),
+            "--json" => args.json = Some(value()),
+            _ => panic!(
+                "unknown option {}. usage : jitter [--cpus LIST] [--secs N] [--threshold NS] [--json FILE]",
+                arg
+            ),
+        }
+    }
+    args.cpus.dedup();
+    args
+}
+
+fn main() -> std::io::Result<()> {
+    let args = parse_args();
+    println!(
+        "spinning on CPUs {:?} for {}s, counting gaps over {} ns",
+        args.cpus, args.secs, args.threshold_ns
+    );
+    let results = jitter::measure(&args.cpus, Duration::from_secs_f64(args.secs), args.threshold_ns);
+    print!("{}", jitter::summary(&results));
+    if let Some(file) = args.json {
+        std::fs::write(&file, jitter::to_json(&results).pretty(2))?;
+        println!("wrote {}", file);
+    }
+    Ok(())
+}
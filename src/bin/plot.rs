@@ -0,0 +1,193 @@
+use async_bench::aggregate::{display_name, language_name, load_run};
+use async_bench::plot::{plot, Labels, Row, Style, PALETTE};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+///
+/// Draw the graphs from aggregate's summaries, w/o needing R.
+/// One SVG per language per machine, a row for each variant,
+/// from the best run of each benchmark.
+///
+/// usage : plot [--summary FILE]... [--out DIR] [--clip PCT] [--style violin|box] [--by language|variant]
+///   --summary  the json aggregate wrote. Defaults to every one in summary/
+///              More than one machine puts them on the same graphs, colored by machine.
+///   --by       variant puts the languages side by side instead, one graph per variant.
+///   --clip     the percentile the x axis stops at, 99 by default.
+struct Args {
+    summaries: Vec<PathBuf>,
+    out: PathBuf,
+    clip: f64,
+    style: Style,
+    by_variant: bool,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        summaries: Vec::new(),
+        out: PathBuf::from("graphs"),
+        clip: 99.0,
+        style: Style::Violin,
+        by_variant: false,
+    };
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+        match arg.as_str() {
+            "--summary" => args.summaries.push(PathBuf::from(value())),
+            "--out" => args.out = PathBuf::from(value()),
+            "--clip" => args.clip = value().parse().expect("--clip must be a percentile"),
+            "--style" => args.style = Style::parse(&value()),
+            "--by" => {
+                args.by_variant = match value().as_str() {
+                    "language" => false,
+                    "variant" => true,
+                    v => panic!("--by must be 'language' or 'variant', got '{}'", v),
+                }
+            }
+            _ => panic!(
+                "unknown option {}. usage : plot [--summary FILE]... [--out DIR] [--clip PCT] [--style violin|box] [--by language|variant]",
+                arg
+            ),
+        }
+    }
+    if args.summaries.is_empty() {
+        let mut found: Vec<PathBuf> = std::fs::read_dir("summary")
+            .expect("no summary directory, run aggregate first or use --summary")
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|x| x == "json"))
+            .collect();
+        found.sort();
+        args.summaries = found;
+    }
+    args
+}
+
+/// the best run of one benchmark on one machine.
+struct Result {
+    machine: String,
+    language: String,
+    variant: String,
+    unit: String,
+    samples: Vec<f64>,
+}
+
+fn load_summary(file: &Path) -> Vec<Result> {
+    let text = std::fs::read_to_string(file).unwrap_or_else(|e| panic!("can't read {} : {}", file.display(), e));
+    let summary = json::parse(&text).unwrap_or_else(|e| panic!("can't parse {} : {}", file.display(), e));
+    let machine = summary["machine"].as_str().unwrap_or("unknown").to_string();
+    let mut results = Vec::new();
+    for b in summary["benchmarks"].members() {
+        let best_file = b["best_file"].as_str().unwrap_or_default();
+        match load_run(Path::new(best_file), 0) {
+            Some(run) => results.push(Result {
+                machine: machine.clone(),
+                language: b["language"].as_str().unwrap_or_default().to_string(),
+                variant: b["variant"].as_str().unwrap_or_default().to_string(),
+                unit: run.unit,
+                samples: run.samples,
+            }),
+            None => eprintln!("warning : can't read {}, skipping {}", best_file, b["id"]),
+        }
+    }
+    results
+}
+
+/// atomic, resume, suspend, callback, then the rest.
+fn variant_order(variant: &str) -> (usize, String) {
+    let rank = ["atomic", "resume", "suspend", "callback"]
+        .iter()
+        .position(|v| *v == variant)
+        .unwrap_or(4);
+    (rank, variant.to_string())
+}
+
+/// "Intel(R) Core(TM) i7-8700K" -> "Intel", like the R file names.
+fn short_machine(machine: &str) -> String {
+    machine
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|s| !s.is_empty())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+fn main() -> std::io::Result<()> {
+    let args = parse_args();
+    let results: Vec<Result> = args.summaries.iter().flat_map(|f| load_summary(f)).collect();
+    let mut machines: Vec<String> = results.iter().map(|r| r.machine.clone()).collect();
+    machines.dedup();
+    let overlay = machines.len() > 1;
+
+    // graph name, unit -> results on it. Different units can't share an axis.
+    let mut graphs: BTreeMap<(String, String, String), Vec<&Result>> = BTreeMap::new();
+    for r in results.iter() {
+        let group = if args.by_variant { r.variant.clone() } else { r.language.clone() };
+        let machine = if overlay { "machines".to_string() } else { r.machine.clone() };
+        graphs.entry((group, machine, r.unit.clone())).or_default().push(r);
+    }
+
+    std::fs::create_dir_all(&args.out)?;
+    for ((group, machine, unit), mut on_graph) in graphs {
+        on_graph.sort_by_key(|r| {
+            let (rank, variant) = variant_order(&r.variant);
+            (rank, variant, r.language.clone(), machines.iter().position(|m| *m == r.machine))
+        });
+
+        let rows: Vec<Row> = on_graph
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let mut label = display_name(&r.language, &r.variant);
+                let color = if overlay {
+                    label = format!("{} ({})", label, short_machine(&r.machine));
+                    PALETTE[machines.iter().position(|m| *m == r.machine).unwrap() % PALETTE.len()]
+                } else {
+                    PALETTE[i % PALETTE.len()]
+                };
+                Row {
+                    label,
+                    color: color.to_string(),
+                    samples: r.samples.clone(),
+                }
+            })
+            .collect();
+
+        let legend: Vec<(String, String)> = if overlay {
+            machines
+                .iter()
+                .enumerate()
+                .map(|(i, m)| (short_machine(m), PALETTE[i % PALETTE.len()].to_string()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let title = if args.by_variant {
+            format!("{} round-trip times", group)
+        } else {
+            format!("{} round-trip times", language_name(&group))
+        };
+        let subtitle = if overlay { machines.join(" vs ") } else { machine.clone() };
+        let svg = plot(
+            &rows,
+            &Labels {
+                title: &title,
+                subtitle: &subtitle,
+                unit: &unit,
+                legend: &legend,
+            },
+            args.clip,
+            args.style,
+        );
+
+        let unit_suffix = if unit == "ns" { String::new() } else { format!("-{}", unit) };
+        let file = args.out.join(format!(
+            "{}-{}{}.svg",
+            language_name(&group).to_lowercase().replace("++", "pp"),
+            short_machine(&machine),
+            unit_suffix
+        ));
+        svg.save(&file)?;
+        println!("wrote {}", file.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::aggregate::Run;
+use crate::stats::{self, MannWhitney, Summary};
+
+// A/B comparison of the same benchmarks from two result sets,
+// ex : before and after a change to a server, or two machines.
+// See the compare binary.
+
+/// bootstrap resamples for the confidence intervals.
+pub static DEFAULT_RESAMPLES: usize = 10_000;
+
+/// one benchmark, base vs new.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub id: String,
+    pub unit: String,
+    pub base: Summary,
+    pub new: Summary,
+    /// percent change of the median, and its 95% CI.
+    pub median_change: (f64, f64, f64),
+    /// percent change of the p99, and its 95% CI.
+    pub p99_change: (f64, f64, f64),
+    /// new against base. Positive delta is new being slower.
+    pub test: MannWhitney,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Faster,
+    Slower,
+    /// not significant, or smaller than the threshold.
+    Same,
+}
+
+/// when a change counts.
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// significance level for the Mann-Whitney test.
+    pub alpha: f64,
+    /// percent the median has to move by.
+    pub median_pct: f64,
+    /// percent the p99 has to move by, w/ the whole CI past it.
+    /// None only looks at the median.
+    pub p99_pct: Option<f64>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            alpha: 0.05,
+            median_pct: 5.0,
+            p99_pct: None,
+        }
+    }
+}
+
+pub fn compare(id: &str, base: &Run, new: &Run, resamples: usize) -> Comparison {
+    if base.unit != new.unit {
+        panic!("{} is in {} in one set and {} in the other", id, base.unit, new.unit);
+    }
+    // the seed only has to be the same from run to run.
+    let seed = id.bytes().fold(0u64, |h, b| h.wrapping_mul(31).wrapping_add(b as u64));
+    Comparison {
+        id: id.to_string(),
+        unit: base.unit.clone(),
+        base: base.summary,
+        new: new.summary,
+        median_change: stats::bootstrap_change(&base.samples, &new.samples, 50.0, resamples, seed),
+        p99_change: stats::bootstrap_change(&base.samples, &new.samples, 99.0, resamples, seed + 1),
+        test: stats::mann_whitney(&new.samples, &base.samples),
+    }
+}
+
+/// every benchmark in both sets, in `base`'s order. And the ids only in one.
+pub fn compare_all(
+    base: &[(String, Run)],
+    new: &[(String, Run)],
+    resamples: usize,
+) -> (Vec<Comparison>, Vec<String>) {
+    let mut comparisons = Vec::new();
+    let mut unmatched = Vec::new();
+    for (id, b) in base.iter() {
+        match new.iter().find(|(n, _)| n == id) {
+            Some((_, n)) => comparisons.push(compare(id, b, n, resamples)),
+            None => unmatched.push(id.clone()),
+        }
+    }
+    for (id, _) in new.iter() {
+        if !base.iter().any(|(b, _)| b == id) {
+            unmatched.push(id.clone());
+        }
+    }
+    (comparisons, unmatched)
+}
+
+impl Comparison {
+    pub fn significant(&self, t: &Thresholds) -> bool {
+        self.test.p < t.alpha
+    }
+
+    /// the median moved past the threshold and Mann-Whitney agrees,
+    /// or the whole p99 CI is past its threshold.
+    pub fn verdict(&self, t: &Thresholds) -> Verdict {
+        let (median, _, _) = self.median_change;
+        let (_, p99_lo, p99_hi) = self.p99_change;
+        let significant = self.significant(t);
+        let slower = (significant && median > t.median_pct) || t.p99_pct.is_some_and(|p| p99_lo > p);
+        let faster = (significant && median < -t.median_pct) || t.p99_pct.is_some_and(|p| p99_hi < -p);
+        match (slower, faster) {
+            (true, _) => Verdict::Slower,
+            (false, true) => Verdict::Faster,
+            _ => Verdict::Same,
+        }
+    }
+
+    pub fn to_json(&self, t: &Thresholds) -> json::JsonValue {
+        let change = |(estimate, lo, hi): (f64, f64, f64)| {
+            json::object! {
+                pct: estimate,
+                ci_low: lo,
+                ci_high: hi,
+            }
+        };
+        json::object! {
+            id: self.id.as_str(),
+            unit: self.unit.as_str(),
+            base: self.base.to_json(),
+            new: self.new.to_json(),
+            median_change: change(self.median_change),
+            p99_change: change(self.p99_change),
+            mann_whitney_u: self.test.u,
+            p_value: self.test.p,
+            cliffs_delta: self.test.delta,
+            effect_size: stats::effect_size(self.test.delta),
+            verdict: format!("{:?}", self.verdict(t)).to_lowercase(),
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use crate::backing::{Backing, MapOptions, Prefault};
+use crate::layout::LayoutMode;
+use crate::numa::NumaPolicy;
+use crate::tsc::TscUnit;
+use std::path::Path;
+use std::time::Duration;
+
+/// the CPUs the README results were taken on.
+pub static DEFAULT_CLIENT_CPU: usize = 4;
+pub static DEFAULT_SERVER_CPU: usize = 5;
+
+/// if this file is in the directory the benchmarks are run from
+/// it's loaded. Point BENCH_CONFIG at a file to use a different one.
+pub static DEFAULT_CONFIG_FILE: &str = "bench_config.json";
+
+/// how many round trips the histogram run times, after criterion is done.
+pub static DEFAULT_HDR_SAMPLES: usize = 1_000_000;
+
+/// how long the open loop client runs at each rate.
+pub static DEFAULT_OPEN_LOOP_SECS: f64 = 2.0;
+
+/// how long to measure system noise for, before and after each benchmark.
+pub static DEFAULT_JITTER_SECS: f64 = 1.0;
+
+/// gaps in the TSC longer than this are counted as the CPU being taken away.
+pub static DEFAULT_JITTER_THRESHOLD_NS: u64 = 1000;
+
+/// a run is noisy if a CPU lost more than this percent of its time.
+pub static DEFAULT_JITTER_NOISY_PCT: f64 = 0.1;
+
+/// round trips in each window of the warm up's rolling median.
+pub static DEFAULT_STABLE_WINDOW: usize = 1000;
+
+/// the warm up is done when the last few window medians are within this percent.
+pub static DEFAULT_STABLE_TOLERANCE_PCT: f64 = 2.0;
+
+/// give up waiting for the round trip time to settle after this long.
+pub static DEFAULT_STABLE_MAX_SECS: f64 = 60.0;
+
+/// the most adaptive mode will stretch a measurement to.
+pub static DEFAULT_MAX_MEASUREMENT_SECS: f64 = 300.0;
+
+/// Where things run. Filled in from, lowest priority first :
+///   - the defaults above
+///   - a json config file, ex : `{ "client_cpu" : 2, "server_cpu" : 3, "sweep" : false }`
+///   - env vars CLIENT_CPU, SERVER_CPU, BENCH_SWEEP, SWEEP_CPUS, HDR_SAMPLES,
+///     TSC_GROUPS, TSC_UNIT, PERF_COUNTERS, OPEN_LOOP_RATES, OPEN_LOOP_SECS,
+///     JITTER_SECS, JITTER_THRESHOLD_NS, JITTER_NOISY_PCT, WARMUP_SECS,
+///     MEASUREMENT_SECS, SAMPLE_SIZE, TARGET_CI_PCT, MAX_MEASUREMENT_SECS,
+///     STABLE_WINDOW, STABLE_TOLERANCE_PCT, STABLE_MAX_SECS, PRINT_COMPILATION,
+///     LAYOUT, LAYOUT_SWEEP, BACKING, PREFAULT, BACKING_SWEEP, NUMA and CROSS_NODE
+///
+/// Warm up, measurement time and sample size can also be set per group
+/// or benchmark in the file, ex : `{ "timing" : { "kotlin_atomic" : { "warmup_secs" : 30 } } }`
+/// The most specific setting in the file is used, but the env wins over
+/// all of them, it's what was asked for on this run.
+///
+/// The `run_bench` script turns its command line flags into the env vars.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub client_cpu: usize,
+    pub server_cpu: usize,
+
+    /// if true, benchmark every client/server CPU pair
+    /// instead of just client_cpu/server_cpu
+    pub sweep: bool,
+
+    /// which CPUs the sweep uses. None is every CPU we're allowed to run on.
+    pub sweep_cpus: Option<Vec<usize>>,
+
+    /// round trips to time one by one into a HDR histogram,
+    /// after the criterion run. 0 turns it off.
+    pub hdr_samples: usize,
+
+    /// criterion groups to time w/ the TSC instead of the wall clock.
+    pub tsc_groups: Vec<String>,
+
+    /// TSC ticks or ns, for the TSC groups.
+    pub tsc_unit: TscUnit,
+
+    /// count cycles, cache misses etc. on both sides w/ perf_event_open.
+    pub perf_counters: bool,
+
+    /// messages per second for the open loop client, after the
+    /// criterion run. Empty turns it off. See open_loop.rs
+    pub open_loop_rates: Vec<u64>,
+
+    /// seconds to run at each open loop rate.
+    pub open_loop_secs: f64,
+
+    /// seconds to measure system noise on the client and server CPUs,
+    /// before and after each benchmark. 0 turns it off. See jitter.rs
+    pub jitter_secs: f64,
+    pub jitter_threshold_ns: u64,
+    /// runs w/ more noise than this are marked noisy, and run_bench drops them.
+    pub jitter_noisy_pct: f64,
+
+    /// before criterion starts, ping the server until the median of each
+    /// window of round trips settles. See warmup.rs
+    pub stable_window: usize,
+    pub stable_tolerance_pct: f64,
+    /// 0 turns the warm up off.
+    pub stable_max_secs: f64,
+
+    /// run the JVM servers w/ -XX:+PrintCompilation, and have the
+    /// warm up wait for the JIT to go quiet too.
+    pub print_compilation: bool,
+
+    /// where the server's words go in the shared memory. See layout.rs
+    pub layout: LayoutMode,
+
+    /// the layouts the `layout` group benchmarks. Empty skips it.
+    pub layout_sweep: Vec<LayoutMode>,
+
+    /// what's behind the shared memory, and whether it's faulted in
+    /// up front. See backing.rs
+    pub backing: Backing,
+    pub prefault: Prefault,
+
+    /// the backings the `backing` group benchmarks. Empty skips it.
+    pub backing_sweep: Vec<(Backing, Prefault)>,
+
+    /// which NUMA node the shared memory goes on. See numa.rs
+    pub numa: NumaPolicy,
+
+    /// put the server on another NUMA node from the client, and benchmark
+    /// the segment on each side in the `numa` group. Does nothing w/ one node.
+    pub cross_node: bool,
+
+    /// criterion's settings for every benchmark, unless `timing` says otherwise.
+    pub timing: Timing,
+
+    /// group or benchmark name -> its own settings. See timing_for()
+    pub bench_timing: Vec<(String, TimingOverride)>,
+
+    /// the timing env vars. Applied over bench_timing too.
+    pub env_timing: TimingOverride,
+}
+
+/// how long criterion warms up and measures for, and how many samples it takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub warmup: Duration,
+    pub measurement: Duration,
+    pub sample_size: usize,
+    /// adaptive mode, if set. Measure for longer until the 95% CI
+    /// of the median is narrower than this percent of the median.
+    pub target_ci_pct: Option<f64>,
+    /// how long adaptive mode can stretch the measurement to.
+    pub max_measurement: Duration,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            warmup: crate::WARMUP_TIME,
+            measurement: crate::RUN_TIME,
+            sample_size: crate::SAMPLE_SIZE,
+            target_ci_pct: None,
+            max_measurement: Duration::from_secs_f64(DEFAULT_MAX_MEASUREMENT_SECS),
+        }
+    }
+}
+
+/// what a group or benchmark changes about the Timing.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimingOverride {
+    pub warmup: Option<Duration>,
+    pub measurement: Option<Duration>,
+    pub sample_size: Option<usize>,
+    pub target_ci_pct: Option<f64>,
+    pub max_measurement: Option<Duration>,
+}
+
+impl TimingOverride {
+    /// ex : `{ "warmup_secs" : 30, "measurement_secs" : 60, "sample_size" : 500, "target_ci_pct" : 1 }`
+    pub fn from_json(parsed: &json::JsonValue) -> TimingOverride {
+        let secs = |key: &str| parsed[key].as_f64().map(Duration::from_secs_f64);
+        TimingOverride {
+            warmup: secs("warmup_secs"),
+            measurement: secs("measurement_secs"),
+            sample_size: parsed["sample_size"].as_usize(),
+            target_ci_pct: parsed["target_ci_pct"].as_f64(),
+            max_measurement: secs("max_measurement_secs"),
+        }
+    }
+
+    pub fn apply(&self, timing: &mut Timing) {
+        timing.warmup = self.warmup.unwrap_or(timing.warmup);
+        timing.measurement = self.measurement.unwrap_or(timing.measurement);
+        timing.sample_size = self.sample_size.unwrap_or(timing.sample_size);
+        timing.target_ci_pct = self.target_ci_pct.or(timing.target_ci_pct);
+        timing.max_measurement = self.max_measurement.unwrap_or(timing.max_measurement);
+    }
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            client_cpu: DEFAULT_CLIENT_CPU,
+            server_cpu: DEFAULT_SERVER_CPU,
+            sweep: false,
+            sweep_cpus: None,
+            hdr_samples: DEFAULT_HDR_SAMPLES,
+            tsc_groups: Vec::new(),
+            tsc_unit: TscUnit::TscTicks,
+            perf_counters: true,
+            open_loop_rates: Vec::new(),
+            open_loop_secs: DEFAULT_OPEN_LOOP_SECS,
+            jitter_secs: DEFAULT_JITTER_SECS,
+            jitter_threshold_ns: DEFAULT_JITTER_THRESHOLD_NS,
+            jitter_noisy_pct: DEFAULT_JITTER_NOISY_PCT,
+            stable_window: DEFAULT_STABLE_WINDOW,
+            stable_tolerance_pct: DEFAULT_STABLE_TOLERANCE_PCT,
+            stable_max_secs: DEFAULT_STABLE_MAX_SECS,
+            print_compilation: false,
+            layout: LayoutMode::Default,
+            layout_sweep: Vec::new(),
+            backing: Backing::Shm,
+            prefault: Prefault::None,
+            backing_sweep: Vec::new(),
+            numa: NumaPolicy::Default,
+            cross_node: false,
+            timing: Timing::default(),
+            bench_timing: Vec::new(),
+            env_timing: TimingOverride::default(),
+        }
+    }
+}
+
+impl BenchConfig {
+    /// defaults, then the config file, then the env.
+    pub fn load() -> BenchConfig {
+        let mut config = BenchConfig::default();
+
+        match std::env::var("BENCH_CONFIG") {
+            Ok(file) => config.apply_file(Path::new(&file)),
+            Err(_) => {
+                let file = Path::new(DEFAULT_CONFIG_FILE);
+                if file.exists() {
+                    config.apply_file(file);
+                }
+            }
+        }
+        config.apply_env();
+        config
+    }
+
+    fn apply_file(&mut self, file: &Path) {
+        let text = std::fs::read_to_string(file)
+            .unwrap_or_else(|e| panic!("can't read config file {} : {}", file.display(), e));
+        let parsed = json::parse(&text)
+            .unwrap_or_else(|e| panic!("can't parse config file {} : {}", file.display(), e));
+        self.apply_json(&parsed);
+    }
+
+    pub fn apply_json(&mut self, parsed: &json::JsonValue) {
+        if let Some(cpu) = parsed["client_cpu"].as_usize() {
+            self.client_cpu = cpu;
+        }
+        if let Some(cpu) = parsed["server_cpu"].as_usize() {
+            self.server_cpu = cpu;
+        }
+        if let Some(sweep) = parsed["sweep"].as_bool() {
+            self.sweep = sweep;
+        }
+        if parsed["sweep_cpus"].is_array() {
+            self.sweep_cpus = Some(
+                parsed["sweep_cpus"]
+                    .members()
+                    .map(|c| c.as_usize().expect("sweep_cpus must be a list of CPU numbers"))
+                    .collect(),
+            );
+        } else if let Some(list) = parsed["sweep_cpus"].as_str() {
+            self.sweep_cpus = Some(parse_cpu_list(list));
+        }
+        if let Some(samples) = parsed["hdr_samples"].as_usize() {
+            self.hdr_samples = samples;
+        }
+        if parsed["tsc_groups"].is_array() {
+            self.tsc_groups = parsed["tsc_groups"]
+                .members()
+                .map(|g| g.as_str().expect("tsc_groups must be a list of group names").to_string())
+                .collect();
+        }
+        if let Some(unit) = parsed["tsc_unit"].as_str() {
+            self.tsc_unit = parse_tsc_unit(unit);
+        }
+        if let Some(perf) = parsed["perf_counters"].as_bool() {
+            self.perf_counters = perf;
+        }
+        if parsed["open_loop_rates"].is_array() {
+            self.open_loop_rates = parsed["open_loop_rates"]
+                .members()
+                .map(|r| r.as_u64().expect("open_loop_rates must be a list of messages per second"))
+                .collect();
+        }
+        if let Some(secs) = parsed["open_loop_secs"].as_f64() {
+            self.open_loop_secs = secs;
+        }
+        if let Some(secs) = parsed["jitter_secs"].as_f64() {
+            self.jitter_secs = secs;
+        }
+        if let Some(ns) = parsed["jitter_threshold_ns"].as_u64() {
+            self.jitter_threshold_ns = ns;
+        }
+        if let Some(pct) = parsed["jitter_noisy_pct"].as_f64() {
+            self.jitter_noisy_pct = pct;
+        }
+        if let Some(window) = parsed["stable_window"].as_usize() {
+            self.stable_window = window;
+        }
+        if let Some(pct) = parsed["stable_tolerance_pct"].as_f64() {
+            self.stable_tolerance_pct = pct;
+        }
+        if let Some(secs) = parsed["stable_max_secs"].as_f64() {
+            self.stable_max_secs = secs;
+        }
+        if let Some(print) = parsed["print_compilation"].as_bool() {
+            self.print_compilation = print;
+        }
+        if let Some(layout) = parsed["layout"].as_str() {
+            self.layout = parse_layout(layout);
+        }
+        if let Some(sweep) = parsed["layout_sweep"].as_bool() {
+            self.layout_sweep = if sweep { LayoutMode::ALL.to_vec() } else { Vec::new() };
+        } else if parsed["layout_sweep"].is_array() {
+            self.layout_sweep = parsed["layout_sweep"]
+                .members()
+                .map(|l| parse_layout(l.as_str().expect("layout_sweep must be a list of layout names")))
+                .collect();
+        }
+        if let Some(backing) = parsed["backing"].as_str() {
+            self.backing = parse_backing(backing);
+        }
+        if let Some(prefault) = parsed["prefault"].as_str() {
+            self.prefault = parse_prefault(prefault);
+        }
+        if let Some(sweep) = parsed["backing_sweep"].as_bool() {
+            self.backing_sweep = if sweep { all_backings() } else { Vec::new() };
+        } else if parsed["backing_sweep"].is_array() {
+            self.backing_sweep = parsed["backing_sweep"]
+                .members()
+                .map(|b| parse_backing_combo(b.as_str().expect("backing_sweep must be a list, ex : \"hugetlb+mlock\"")))
+                .collect();
+        }
+        if let Some(numa) = parsed["numa"].as_str() {
+            self.numa = parse_numa(numa);
+        } else if let Some(node) = parsed["numa"].as_usize() {
+            self.numa = NumaPolicy::Node(node);
+        }
+        if let Some(cross) = parsed["cross_node"].as_bool() {
+            self.cross_node = cross;
+        }
+        TimingOverride::from_json(parsed).apply(&mut self.timing);
+        for (name, timing) in parsed["timing"].entries() {
+            self.bench_timing.push((name.to_string(), TimingOverride::from_json(timing)));
+        }
+    }
+
+    fn apply_env(&mut self) {
+        if let Some(cpu) = env_usize("CLIENT_CPU") {
+            self.client_cpu = cpu;
+        }
+        if let Some(cpu) = env_usize("SERVER_CPU") {
+            self.server_cpu = cpu;
+        }
+        if let Some(sweep) = env_bool("BENCH_SWEEP") {
+            self.sweep = sweep;
+        }
+        if let Ok(list) = std::env::var("SWEEP_CPUS") {
+            self.sweep_cpus = Some(parse_cpu_list(&list));
+        }
+        if let Some(samples) = env_usize("HDR_SAMPLES") {
+            self.hdr_samples = samples;
+        }
+        if let Ok(groups) = std::env::var("TSC_GROUPS") {
+            self.tsc_groups = groups
+                .split(',')
+                .map(|g| g.trim().to_string())
+                .filter(|g| !g.is_empty())
+                .collect();
+        }
+        if let Ok(unit) = std::env::var("TSC_UNIT") {
+            self.tsc_unit = parse_tsc_unit(&unit);
+        }
+        if let Some(perf) = env_bool("PERF_COUNTERS") {
+            self.perf_counters = perf;
+        }
+        if let Ok(rates) = std::env::var("OPEN_LOOP_RATES") {
+            self.open_loop_rates = parse_rates(&rates);
+        }
+        if let Some(secs) = env_f64("OPEN_LOOP_SECS") {
+            self.open_loop_secs = secs;
+        }
+        if let Some(secs) = env_f64("JITTER_SECS") {
+            self.jitter_secs = secs;
+        }
+        if let Some(ns) = env_usize("JITTER_THRESHOLD_NS") {
+            self.jitter_threshold_ns = ns as u64;
+        }
+        if let Some(pct) = env_f64("JITTER_NOISY_PCT") {
+            self.jitter_noisy_pct = pct;
+        }
+        if let Some(window) = env_usize("STABLE_WINDOW") {
+            self.stable_window = window;
+        }
+        if let Some(pct) = env_f64("STABLE_TOLERANCE_PCT") {
+            self.stable_tolerance_pct = pct;
+        }
+        if let Some(secs) = env_f64("STABLE_MAX_SECS") {
+            self.stable_max_secs = secs;
+        }
+        if let Some(print) = env_bool("PRINT_COMPILATION") {
+            self.print_compilation = print;
+        }
+        if let Ok(layout) = std::env::var("LAYOUT") {
+            self.layout = parse_layout(&layout);
+        }
+        if let Ok(sweep) = std::env::var("LAYOUT_SWEEP") {
+            self.layout_sweep = parse_layout_list(&sweep);
+        }
+        if let Ok(backing) = std::env::var("BACKING") {
+            self.backing = parse_backing(&backing);
+        }
+        if let Ok(prefault) = std::env::var("PREFAULT") {
+            self.prefault = parse_prefault(&prefault);
+        }
+        if let Ok(sweep) = std::env::var("BACKING_SWEEP") {
+            self.backing_sweep = parse_backing_list(&sweep);
+        }
+        if let Ok(numa) = std::env::var("NUMA") {
+            self.numa = parse_numa(&numa);
+        }
+        if let Some(cross) = env_bool("CROSS_NODE") {
+            self.cross_node = cross;
+        }
+        let secs = |name: &str| env_f64(name).map(Duration::from_secs_f64);
+        self.env_timing = TimingOverride {
+            warmup: secs("WARMUP_SECS"),
+            measurement: secs("MEASUREMENT_SECS"),
+            sample_size: env_usize("SAMPLE_SIZE"),
+            target_ci_pct: env_f64("TARGET_CI_PCT"),
+            max_measurement: secs("MAX_MEASUREMENT_SECS"),
+        };
+        self.env_timing.apply(&mut self.timing);
+    }
+
+    /// the Timing for a benchmark. The group's settings, then the benchmark's,
+    /// over the run's, then the env over all of them. Sweep names
+    /// (ex : rust_atomic_c4_s5) match rust_atomic.
+    pub fn timing_for(&self, group_name: &str, bench_name: &str) -> Timing {
+        let (base_name, _) = crate::results::split_cpu_pair(bench_name);
+        let mut timing = self.timing;
+        for name in [group_name, base_name, bench_name] {
+            for (_, o) in self.bench_timing.iter().filter(|(n, _)| n == name) {
+                o.apply(&mut timing);
+            }
+        }
+        self.env_timing.apply(&mut timing);
+        if timing.sample_size < 10 {
+            panic!("{} : criterion needs a sample size of at least 10, got {}", bench_name, timing.sample_size);
+        }
+        timing
+    }
+
+    /// how the benchmarks map the shared memory, unless they sweep it.
+    pub fn map_options(&self) -> MapOptions {
+        MapOptions { layout: self.layout, backing: self.backing, prefault: self.prefault, numa: self.numa }
+    }
+
+    /// true if this criterion group should be timed w/ the TSC.
+    pub fn uses_tsc(&self, group_name: &str) -> bool {
+        self.tsc_groups.iter().any(|g| g == group_name)
+    }
+}
+
+fn parse_tsc_unit(unit: &str) -> TscUnit {
+    match unit {
+        "ticks" => TscUnit::TscTicks,
+        "ns" => TscUnit::Nanos,
+        _ => panic!("TSC unit must be 'ticks' or 'ns', got '{}'", unit),
+    }
+}
+
+fn parse_layout(name: &str) -> LayoutMode {
+    LayoutMode::parse(name).unwrap_or_else(|| {
+        let names: Vec<&str> = LayoutMode::ALL.iter().map(|l| l.name()).collect();
+        panic!("unknown layout '{}', there's {}", name, names.join(", "))
+    })
+}
+
+/// ex : "same_line,separate_pages", or "all". "" or "0" is none.
+pub fn parse_layout_list(list: &str) -> Vec<LayoutMode> {
+    match list.trim() {
+        "all" | "1" => LayoutMode::ALL.to_vec(),
+        "" | "0" => Vec::new(),
+        list => list.split(',').map(parse_layout).collect(),
+    }
+}
+
+fn parse_backing(name: &str) -> Backing {
+    Backing::parse(name).unwrap_or_else(|| {
+        let names: Vec<&str> = Backing::ALL.iter().map(|b| b.name()).collect();
+        panic!("unknown backing '{}', there's {}", name, names.join(", "))
+    })
+}
+
+fn parse_prefault(name: &str) -> Prefault {
+    Prefault::parse(name).unwrap_or_else(|| {
+        let names: Vec<&str> = Prefault::ALL.iter().map(|p| p.name()).collect();
+        panic!("unknown prefault '{}', there's {}", name, names.join(", "))
+    })
+}
+
+fn parse_numa(name: &str) -> NumaPolicy {
+    NumaPolicy::parse(name)
+        .unwrap_or_else(|| panic!("unknown NUMA policy '{}', there's default, client, server or a node number", name))
+}
+
+fn parse_backing_combo(text: &str) -> (Backing, Prefault) {
+    MapOptions::parse_backing(text)
+        .unwrap_or_else(|| panic!("unknown backing '{}', ex : memfd, hugetlb+mlock, shm+populate", text))
+}
+
+/// every backing, each w/ every prefault.
+fn all_backings() -> Vec<(Backing, Prefault)> {
+    Backing::ALL
+        .into_iter()
+        .flat_map(|b| Prefault::ALL.into_iter().map(move |p| (b, p)))
+        .collect()
+}
+
+/// ex : "memfd,hugetlb+mlock", or "all". "" or "0" is none.
+pub fn parse_backing_list(list: &str) -> Vec<(Backing, Prefault)> {
+    match list.trim() {
+        "all" | "1" => all_backings(),
+        "" | "0" => Vec::new(),
+        list => list.split(',').map(parse_backing_combo).collect(),
+    }
+}
+
+fn env_usize(name: &str) -> Option<usize> {
+    std::env::var(name).ok().map(|v| {
+        v.trim()
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number, got '{}'", name, v))
+    })
+}
+
+/// ex : "10000,100000,1e6"
+pub fn parse_rates(rates: &str) -> Vec<u64> {
+    rates
+        .split(',')
+        .map(|r| r.trim())
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            r.parse::<f64>()
+                .unwrap_or_else(|_| panic!("bad rate '{}' in '{}'", r, rates)) as u64
+        })
+        .collect()
+}
+
+fn env_f64(name: &str) -> Option<f64> {
+    std::env::var(name).ok().map(|v| {
+        v.trim()
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number, got '{}'", name, v))
+    })
+}
+
+fn env_bool(name: &str) -> Option<bool> {
+    std::env::var(name)
+        .ok()
+        .map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+}
+
+/// parse the kernel's cpu list format. ex : "0-3,6,8-9"
+/// this is what's in /sys/devices/system/cpu/online
+/// and what isolcpus and taskset take.
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.trim().is_empty()) {
+        let part = part.trim();
+        let bad = || -> usize { panic!("bad cpu list '{}'", list) };
+        match part.split_once('-') {
+            Some((lo, hi)) => {
+                let lo: usize = lo.parse().unwrap_or_else(|_| bad());
+                let hi: usize = hi.parse().unwrap_or_else(|_| bad());
+                cpus.extend(lo..=hi);
+            }
+            None => cpus.push(part.parse().unwrap_or_else(|_| bad())),
+        }
+    }
+    cpus
+}
@@ -0,0 +1,332 @@
+use crate::atomic_spin::MappedAtomics;
+use rand::{Rng, SeedableRng};
+use std::ffi::CString;
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+// Checks a server speaks the protocol, w/o hanging when it doesn't.
+// Starts it on its own segment, then :
+//   handshake   it writes its pid in the header once it's ready
+//   edge values 0s, u64::MAX, repeats, and the sequence number wrapping
+//   ordering    a run of random values, each echoed once, in order
+//   stop        the Rust servers exit when asked w/ --checked, the rest keep echoing
+//   crash       once it's killed, we notice, rather than spin forever
+// Every wait has a timeout, and keeps an eye on the process.
+
+pub static DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+pub static DEFAULT_ECHO_TIMEOUT: Duration = Duration::from_secs(5);
+pub static DEFAULT_VALUES: usize = 10_000;
+
+/// what the server is supposed to do beyond echoing the sequence number.
+/// See registry::ServerSpec
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Expect {
+    pub echoes_payload: bool,
+    pub stops: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// how long to wait for the first echo. The JVM takes a while.
+    pub startup_timeout: Duration,
+    /// how long any one round trip after that can take.
+    pub echo_timeout: Duration,
+    /// random values for the ordering check.
+    pub values: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            echo_timeout: DEFAULT_ECHO_TIMEOUT,
+            values: DEFAULT_VALUES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub server: String,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.outcome != Outcome::Fail)
+    }
+
+    fn add(&mut self, name: &'static str, result: Result<String, String>) {
+        let (outcome, detail) = match result {
+            Ok(detail) => (Outcome::Pass, detail),
+            Err(detail) => (Outcome::Fail, detail),
+        };
+        self.checks.push(Check { name, outcome, detail });
+    }
+
+    /// a report for a server we couldn't run.
+    pub fn skipped(server: &str, why: &str) -> Report {
+        Report {
+            server: server.to_string(),
+            checks: CHECKS
+                .iter()
+                .map(|name| Check { name, outcome: Outcome::Skip, detail: why.to_string() })
+                .collect(),
+        }
+    }
+}
+
+pub static CHECKS: [&str; 5] = ["handshake", "edge values", "ordering", "stop", "crash"];
+
+/// why a round trip didn't come back.
+enum Broken {
+    Exited(ExitStatus),
+    TimedOut,
+    OutOfOrder(u64),
+}
+
+/// the server under test, and our side of the segment.
+/// Kills the server and removes the segment however it ends.
+struct Session {
+    atomics: MappedAtomics,
+    child: Child,
+    /// the last sequence number the server echoed.
+    last_seq: u64,
+    exited: Option<ExitStatus>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.atomics.close();
+    }
+}
+
+impl Session {
+    fn poll_exit(&mut self) -> Option<ExitStatus> {
+        if self.exited.is_none() {
+            self.exited = self.child.try_wait().ok().flatten();
+        }
+        self.exited
+    }
+
+    /// send `seq` and `payload`, and wait for `seq` to come back. Anything
+    /// other than the last sequence number or this one is out of order.
+    fn round_trip(&mut self, seq: u64, payload: u64, timeout: Duration) -> Result<u64, Broken> {
+        self.atomics.client_payload.store(payload, std::sync::atomic::Ordering::Relaxed);
+        self.atomics.client_write.store(seq, std::sync::atomic::Ordering::Release);
+        self.wait_for_echo(seq, timeout)
+    }
+
+    fn wait_for_echo(&mut self, seq: u64, timeout: Duration) -> Result<u64, Broken> {
+        let start = Instant::now();
+        let mut spins = 0u64;
+        loop {
+            let echoed = self.atomics.server_write.load(std::sync::atomic::Ordering::Acquire);
+            if echoed == seq {
+                self.last_seq = seq;
+                return Ok(self.atomics.server_payload.load(std::sync::atomic::Ordering::Relaxed));
+            }
+            if echoed != self.last_seq {
+                return Err(Broken::OutOfOrder(echoed));
+            }
+            spins += 1;
+            // don't make a syscall every spin, it'd time that, not the server.
+            if spins.is_multiple_of(4096) {
+                if let Some(status) = self.poll_exit() {
+                    return Err(Broken::Exited(status));
+                }
+                if start.elapsed() > timeout {
+                    return Err(Broken::TimedOut);
+                }
+                // give the server a go, if it's sharing our CPU.
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    fn describe(&self, broken: Broken, seq: u64, timeout: Duration) -> String {
+        match broken {
+            Broken::Exited(status) => format!("server exited w/ {} waiting for {}", status, seq),
+            Broken::TimedOut => format!("no echo of {} in {:?}", seq, timeout),
+            Broken::OutOfOrder(echoed) => {
+                format!("echoed {} while waiting for {} (last was {})", echoed, seq, self.last_seq)
+            }
+        }
+    }
+
+    /// send each (seq, payload), checking the payload comes back if it should.
+    fn send_all(&mut self, values: &[(u64, u64)], expect: Expect, timeout: Duration) -> Result<(), String> {
+        for (i, &(seq, payload)) in values.iter().enumerate() {
+            let echoed = self.round_trip(seq, payload, timeout).map_err(|b| self.describe(b, seq, timeout))?;
+            if expect.echoes_payload && echoed != payload {
+                return Err(format!("value #{} : sent payload {:#x}, got back {:#x}", i, payload, echoed));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn pid_alive(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// the payloads and sequence numbers most likely to trip a server up. Repeated
+/// payloads still get a new sequence number, so they must still be echoed.
+/// Then the sequence number wraps.
+pub fn edge_values(first_seq: u64) -> Vec<(u64, u64)> {
+    let payloads = [0, 0, u64::MAX, u64::MAX, 1, 0];
+    let mut values: Vec<(u64, u64)> =
+        payloads.iter().enumerate().map(|(i, &p)| (first_seq + i as u64, p)).collect();
+    for (i, seq) in [u64::MAX - 1, u64::MAX, 0, 1].into_iter().enumerate() {
+        values.push((seq, i as u64));
+    }
+    values
+}
+
+/// run every check against the server `cmd` starts. `shm_name` is passed to it in
+/// SHM_NAME, the servers that don't read it always use the default.
+pub fn check(server: &str, mut cmd: Command, expect: Expect, shm_name: CString, options: &Options) -> Report {
+    let mut report = Report { server: server.to_string(), checks: vec![] };
+
+    let atomics = MappedAtomics::new_named(shm_name.clone(), true);
+    cmd.env("SHM_NAME", shm_name.to_str().unwrap());
+    let child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            atomics.close();
+            let mut report = Report::skipped(server, "didn't start");
+            report.checks[0].outcome = Outcome::Fail;
+            report.checks[0].detail = format!("can't start it : {}", e);
+            return report;
+        }
+    };
+    let mut session = Session { atomics, child, last_seq: 0, exited: None };
+
+    // handshake. Then the first echo, which may take a while to start.
+    let start = Instant::now();
+    let ready = loop {
+        if let Some(pid) = session.atomics.server_ready() {
+            break Ok(pid);
+        }
+        if let Some(status) = session.poll_exit() {
+            break Err(format!("exited w/ {} before it was ready", status));
+        }
+        if start.elapsed() > options.startup_timeout {
+            break Err(format!("never wrote its pid in {:?}", options.startup_timeout));
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    let ready_pid = ready.as_ref().ok().copied();
+    let child_pid = session.child.id();
+    report.add(
+        "handshake",
+        ready.and_then(|pid| {
+            if pid == child_pid {
+                Ok(format!("pid {} after {:.1}s", pid, start.elapsed().as_secs_f64()))
+            } else {
+                Err(format!("ready as pid {}, but we started pid {}", pid, child_pid))
+            }
+        }),
+    );
+
+    // the first echo gets whatever's left of the startup time.
+    let remaining = options.startup_timeout.saturating_sub(start.elapsed()).max(options.echo_timeout);
+    let edges = edge_values(1);
+    let first = session.send_all(&edges[..1], expect, remaining);
+    let edge_result = first.and_then(|_| session.send_all(&edges[1..], expect, options.echo_timeout));
+    report.add("edge values", edge_result.map(|_| format!("{} values", edges.len())));
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x5350494e);
+    let ordered: Vec<(u64, u64)> = (0..options.values as u64)
+        .map(|i| (session.last_seq.wrapping_add(1 + i), rng.gen()))
+        .collect();
+    let order_start = Instant::now();
+    let order_result = session.send_all(&ordered, expect, options.echo_timeout);
+    report.add(
+        "ordering",
+        order_result.map(|_| {
+            let per = order_start.elapsed().as_nanos() as f64 / ordered.len().max(1) as f64;
+            format!("{} values, {:.0} ns/round trip", ordered.len(), per)
+        }),
+    );
+
+    // stop. request_stop() bumps the sequence number, so the others echo it.
+    let stop_result = if session.exited.is_some() {
+        Err("server had already exited".to_string())
+    } else {
+        session.atomics.request_stop();
+        let seq = session.last_seq.wrapping_add(1);
+        if expect.stops {
+            let stop_start = Instant::now();
+            loop {
+                if let Some(status) = session.poll_exit() {
+                    // it echoes the bump on the way out.
+                    if session.atomics.server_write.load(std::sync::atomic::Ordering::Acquire) == seq {
+                        session.last_seq = seq;
+                    }
+                    break if status.success() {
+                        Ok(format!("exited after {:.0} ms", stop_start.elapsed().as_secs_f64() * 1000.0))
+                    } else {
+                        Err(format!("exited w/ {}", status))
+                    };
+                }
+                if stop_start.elapsed() > options.echo_timeout {
+                    break Err(format!("still running {:?} after the stop request", options.echo_timeout));
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        } else {
+            match session.wait_for_echo(seq, options.echo_timeout) {
+                Ok(_) => Ok("ignores it, kept echoing".to_string()),
+                Err(b) => Err(session.describe(b, seq, options.echo_timeout)),
+            }
+        }
+    };
+    report.add("stop", stop_result);
+
+    // crash. Kill it if it's still going, the next round trip has to say so.
+    if session.poll_exit().is_none() {
+        let _ = session.child.kill();
+    }
+    let seq = session.last_seq.wrapping_add(7);
+    let crash_result = match session.round_trip(seq, 0, options.echo_timeout) {
+        Err(Broken::Exited(status)) => match ready_pid.filter(|pid| *pid != child_pid && pid_alive(*pid)) {
+            Some(pid) => Err(format!("we saw {}, but the ready pid {} is still running", status, pid)),
+            None => Ok(format!("saw {}", status)),
+        },
+        Ok(_) => Err(format!("{} was echoed after the server was killed", seq)),
+        Err(b) => Err(session.describe(b, seq, options.echo_timeout)),
+    };
+    report.add("crash", crash_result);
+
+    report
+}
+
+/// one line per check, then PASS or FAIL.
+pub fn print_report(report: &Report) {
+    println!("{}", report.server);
+    for check in report.checks.iter() {
+        let outcome = match check.outcome {
+            Outcome::Pass => "pass",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "skip",
+        };
+        println!("  {:<12} {:<4}  {}", check.name, outcome, check.detail);
+    }
+}
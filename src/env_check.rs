@@ -0,0 +1,262 @@
+use crate::topology;
+use std::collections::BTreeSet;
+
+// Checks the machine is set up for benchmarking the client and
+// server CPUs, and knows how to fix what it can. Used by the
+// env_check binary. Everything is read from /sys and /proc, the
+// fixes are writes to the same files, so they need root.
+
+/// a file to write, to fix a check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub path: String,
+    pub value: String,
+}
+
+impl Fix {
+    fn new(path: String, value: &str) -> Fix {
+        Fix {
+            path,
+            value: value.to_string(),
+        }
+    }
+
+    pub fn apply(&self) -> std::io::Result<()> {
+        std::fs::write(&self.path, &self.value)
+    }
+}
+
+/// what one check found.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    /// empty if everything was ok.
+    pub warnings: Vec<String>,
+    /// worth knowing, but not enough to fail the check over.
+    pub notes: Vec<String>,
+    /// the writes that would fix the warnings, if there are any.
+    pub fixes: Vec<Fix>,
+}
+
+impl Check {
+    fn new(name: &'static str) -> Check {
+        Check {
+            name,
+            warnings: Vec::new(),
+            notes: Vec::new(),
+            fixes: Vec::new(),
+        }
+    }
+
+    pub fn ok(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+static SYS_CPU: &str = "/sys/devices/system/cpu";
+
+fn read(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// cpu0 usually can't be taken offline, so has no online file.
+fn cpu_online(cpu: usize) -> bool {
+    read(&format!("{}/cpu{}/online", SYS_CPU, cpu)).is_none_or(|o| o == "1")
+}
+
+fn format_cpu_list(cpus: &[usize]) -> String {
+    cpus.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// state, the CPU it last ran on, and the name,
+/// from a /proc/<pid>/task/<tid>/stat line.
+pub fn parse_task_stat(stat: &str) -> Option<(char, usize, String)> {
+    // the name is in (), and can have spaces and ) in it.
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let comm = stat[open + 1..close].to_string();
+    let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+    // state is field 3, processor is field 39. Counting from 1, w/ pid and comm.
+    let state = fields.first()?.chars().next()?;
+    let cpu = fields.get(36)?.parse().ok()?;
+    Some((state, cpu, comm))
+}
+
+/// the client and server CPUs should be isolcpus, so the
+/// scheduler doesn't put anything else on them.
+pub fn check_isolated(cpus: &[usize], isolated: &[usize]) -> Check {
+    let mut check = Check::new("isolated");
+    let missing: Vec<usize> = cpus.iter().filter(|c| !isolated.contains(c)).copied().collect();
+    if !missing.is_empty() {
+        check.warnings.push(format!(
+            "CPUs {:?} aren't isolated. Add isolcpus={} (and nohz_full, rcu_nocbs) to the kernel cmdline and reboot",
+            missing,
+            format_cpu_list(cpus)
+        ));
+    }
+    check
+}
+
+/// a hyper-thread sibling that's doing something takes
+/// execution units and L1 from the CPU we're timing.
+pub fn check_smt_siblings(cpus: &[usize]) -> Check {
+    let mut siblings = BTreeSet::new();
+    for cpu in cpus.iter() {
+        for sibling in topology::smt_siblings(*cpu) {
+            if !cpus.contains(&sibling) && cpu_online(sibling) {
+                siblings.insert(sibling);
+            }
+        }
+    }
+    check_online_siblings(&siblings.into_iter().collect::<Vec<_>>(), &runnable_tasks())
+}
+
+/// `siblings` are online, `busy` is (cpu, task) for everything runnable.
+/// A busy one is a warning. An idle one is only a note, it's not
+/// costing anything now, but nothing stops the scheduler using it.
+pub fn check_online_siblings(siblings: &[usize], busy: &[(usize, String)]) -> Check {
+    let mut check = Check::new("smt siblings");
+    for sibling in siblings.iter() {
+        let tasks: Vec<&String> = busy.iter().filter(|(cpu, _)| cpu == sibling).map(|(_, t)| t).collect();
+        if tasks.is_empty() {
+            check.notes.push(format!(
+                "SMT sibling CPU {} is online. It's idle now, but nothing stops the scheduler using it",
+                sibling
+            ));
+        } else {
+            check
+                .warnings
+                .push(format!("SMT sibling CPU {} is online and running {:?}", sibling, tasks));
+        }
+        check.fixes.push(Fix::new(format!("{}/cpu{}/online", SYS_CPU, sibling), "0"));
+    }
+    check
+}
+
+/// frequency scaling changes the round trip time as the CPU warms up.
+pub fn check_governors(cpus: &[usize]) -> Check {
+    let mut check = Check::new("governor");
+    for cpu in cpus.iter() {
+        match topology::governor(*cpu) {
+            Some(g) if g == "performance" => {}
+            Some(g) => {
+                check
+                    .warnings
+                    .push(format!("CPU {} governor is '{}', not 'performance'", cpu, g));
+                check.fixes.push(Fix::new(
+                    format!("{}/cpu{}/cpufreq/scaling_governor", SYS_CPU, cpu),
+                    "performance",
+                ));
+            }
+            None => {}
+        }
+    }
+    check
+}
+
+/// turbo makes the clock depend on what the other cores are doing.
+pub fn check_turbo() -> Check {
+    let mut check = Check::new("turbo");
+    let intel = format!("{}/intel_pstate/no_turbo", SYS_CPU);
+    let boost = format!("{}/cpufreq/boost", SYS_CPU);
+    if let Some(no_turbo) = read(&intel) {
+        if no_turbo != "1" {
+            check.warnings.push("turbo is on (intel_pstate/no_turbo is 0)".to_string());
+            check.fixes.push(Fix::new(intel, "1"));
+        }
+    } else if let Some(b) = read(&boost) {
+        if b != "0" {
+            check.warnings.push("turbo is on (cpufreq/boost is 1)".to_string());
+            check.fixes.push(Fix::new(boost, "0"));
+        }
+    }
+    check
+}
+
+/// interrupts on the client or server CPU show up as tail latency.
+/// The fix moves them to the other online CPUs.
+pub fn check_irqs(cpus: &[usize]) -> Check {
+    let mut check = Check::new("irqs");
+    let others: Vec<usize> = topology::online_cpus()
+        .into_iter()
+        .filter(|c| !cpus.contains(c))
+        .collect();
+    let Ok(dir) = std::fs::read_dir("/proc/irq") else {
+        return check;
+    };
+    let mut irqs: Vec<(usize, Vec<usize>)> = dir
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.parse::<usize>().ok())
+        .filter_map(|irq| {
+            let list = read(&format!("/proc/irq/{}/smp_affinity_list", irq))?;
+            Some((irq, crate::config::parse_cpu_list(&list)))
+        })
+        .collect();
+    irqs.sort();
+    for (irq, affinity) in irqs {
+        let on: Vec<usize> = affinity.iter().filter(|c| cpus.contains(c)).copied().collect();
+        if on.is_empty() {
+            continue;
+        }
+        check.warnings.push(format!("IRQ {} can be delivered to CPUs {:?}", irq, on));
+        if !others.is_empty() {
+            check.fixes.push(Fix::new(
+                format!("/proc/irq/{}/smp_affinity_list", irq),
+                &format_cpu_list(&others),
+            ));
+        }
+    }
+    check
+}
+
+/// (CPU, "name (tid)") for every task in the runnable state, except us.
+fn runnable_tasks() -> Vec<(usize, String)> {
+    let me = std::process::id().to_string();
+    let mut tasks = Vec::new();
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return tasks;
+    };
+    for proc in procs.flatten() {
+        let pid = proc.file_name().to_string_lossy().to_string();
+        if pid == me || !pid.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let Ok(threads) = std::fs::read_dir(proc.path().join("task")) else {
+            continue;
+        };
+        for thread in threads.flatten() {
+            let stat = std::fs::read_to_string(thread.path().join("stat")).unwrap_or_default();
+            if let Some((state, cpu, comm)) = parse_task_stat(&stat) {
+                if state == 'R' {
+                    tasks.push((cpu, format!("{} ({})", comm, thread.file_name().to_string_lossy())));
+                }
+            }
+        }
+    }
+    tasks
+}
+
+/// anything else running on the client or server CPU is measured too.
+pub fn check_runnable(cpus: &[usize]) -> Check {
+    let mut check = Check::new("runnable tasks");
+    for (cpu, task) in runnable_tasks() {
+        if cpus.contains(&cpu) {
+            check.warnings.push(format!("{} is runnable on CPU {}", task, cpu));
+        }
+    }
+    check
+}
+
+/// every check, for these client and server CPUs.
+pub fn check_all(client_cpu: usize, server_cpu: usize) -> Vec<Check> {
+    let mut cpus = vec![client_cpu, server_cpu];
+    cpus.dedup();
+    vec![
+        check_isolated(&cpus, &topology::isolated_cpus()),
+        check_smt_siblings(&cpus),
+        check_governors(&cpus),
+        check_turbo(),
+        check_irqs(&cpus),
+        check_runnable(&cpus),
+    ]
+}
@@ -0,0 +1,144 @@
+use crate::topology;
+use crate::CONFIG;
+use json::JsonValue;
+use std::path::{Path, PathBuf};
+
+// A snapshot of the machine the benchmarks ran on, so the
+// results say where they came from instead of relying on
+// someone remembering which box it was.
+
+/// where the snapshot for the current run goes.
+pub fn output_file() -> PathBuf {
+    Path::new("target").join("environment.json")
+}
+
+/// the first value of `key` in /proc/cpuinfo. The first CPU is
+/// enough, they're all the same model.
+pub fn cpuinfo_field(cpuinfo: &str, key: &str) -> Option<String> {
+    cpuinfo.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        if k.trim() == key {
+            Some(v.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// the value of a `name=value` kernel parameter, ex : isolcpus.
+pub fn cmdline_param(cmdline: &str, name: &str) -> Option<String> {
+    cmdline.split_whitespace().find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        if k == name {
+            Some(v.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+fn read(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let out = std::process::Command::new("git").args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// everything we can find out about the machine and the build.
+/// Anything that can't be read is null.
+pub fn capture() -> JsonValue {
+    let cpuinfo = read("/proc/cpuinfo").unwrap_or_default();
+    let cmdline = read("/proc/cmdline").unwrap_or_default();
+    let online = topology::online_cpus();
+
+    let mut governors = JsonValue::new_object();
+    for cpu in online.iter() {
+        if let Some(g) = topology::governor(*cpu) {
+            governors[cpu.to_string()] = g.into();
+        }
+    }
+
+    let target_features: Vec<&str> = env!("BUILD_TARGET_FEATURES")
+        .split(',')
+        .filter(|f| !f.is_empty())
+        .collect();
+
+    json::object! {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        hostname: read("/proc/sys/kernel/hostname"),
+        kernel: read("/proc/sys/kernel/osrelease"),
+        cpu: {
+            vendor: cpuinfo_field(&cpuinfo, "vendor_id"),
+            model: cpuinfo_field(&cpuinfo, "model name"),
+            microcode: cpuinfo_field(&cpuinfo, "microcode"),
+            invariant_tsc: crate::tsc::invariant_tsc(),
+        },
+        online_cpus: online,
+        isolated_cpus: topology::isolated_cpus(),
+        nohz_full_cpus: topology::nohz_full_cpus(),
+        governors: governors,
+        smt: {
+            control: topology::smt_control(),
+            active: topology::smt_active(),
+        },
+        cmdline: {
+            full: cmdline.as_str(),
+            isolcpus: cmdline_param(&cmdline, "isolcpus"),
+            nohz_full: cmdline_param(&cmdline, "nohz_full"),
+            rcu_nocbs: cmdline_param(&cmdline, "rcu_nocbs"),
+        },
+        build: {
+            rustc: env!("BUILD_RUSTC_VERSION"),
+            target: env!("BUILD_TARGET"),
+            target_features: target_features,
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+        },
+        git: {
+            commit: git(&["rev-parse", "HEAD"]),
+            dirty: git(&["status", "--porcelain", "--untracked-files=no"]).map(|s| !s.is_empty()),
+        },
+        config: {
+            client_cpu: CONFIG.client_cpu,
+            server_cpu: CONFIG.server_cpu,
+            sweep: CONFIG.sweep,
+        },
+        numa: {
+            nodes: topology::numa_nodes(),
+            client_node: topology::cpu_node(CONFIG.client_cpu),
+            server_node: topology::cpu_node(CONFIG.server_cpu),
+            policy: CONFIG.numa.name(),
+            cross_node: CONFIG.cross_node,
+        },
+    }
+}
+
+/// a short id for the machine a snapshot was taken on. The same host,
+/// CPU model and online CPUs is the same machine, the kernel and
+/// settings can change under it. FNV-1a, as 16 hex digits.
+pub fn fingerprint(env: &JsonValue) -> String {
+    let key = format!(
+        "{}|{}|{}|{}",
+        env["hostname"], env["cpu"]["vendor"], env["cpu"]["model"], env["online_cpus"]
+    );
+    let hash = key
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+/// take the snapshot and write it to output_file().
+pub fn save() -> std::io::Result<JsonValue> {
+    let env = capture();
+    let file = output_file();
+    std::fs::create_dir_all(file.parent().unwrap())?;
+    std::fs::write(&file, env.pretty(2))?;
+    Ok(env)
+}
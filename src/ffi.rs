@@ -0,0 +1,139 @@
+use crate::atomic_spin::{MappedAtomics, LAYOUT_VERSION};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::AtomicU64;
+
+// A C ABI around MappedAtomics, so the servers in other languages
+// map the segment w/ the same code as the Rust side instead of their
+// own copy of the layout. Built into the cdylib (libasync_bench.so).
+// The Kotlin servers load it through the JVM's foreign function API.
+//
+//   spin_open(NULL, 0)       open the segment the client made
+//   spin_check_header(seg)   0 if its layout matches this library's
+//   spin_server_ready(seg)   tell the client we're about to echo
+//   spin_client_word(seg)    the sequence word to spin on
+//   spin_server_word(seg)    where to echo it
+//   spin_close(seg)
+
+/// what spin_open() hands out.
+pub struct SpinSegment {
+    atomics: MappedAtomics,
+    created: bool,
+}
+
+fn word(w: &AtomicU64) -> *mut u64 {
+    w.as_ptr()
+}
+
+/// open the segment named `name`, or create it if `create` isn't 0.
+/// A null `name` is the memfd in SHM_FD, or SHM_NAME, or the default. Returns null if it can't
+/// be opened, or was made w/ a different layout.
+///
+/// # Safety
+/// `name` has to be null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn spin_open(name: *const c_char, create: c_int) -> *mut SpinSegment {
+    let name = (!name.is_null()).then(|| CString::from(CStr::from_ptr(name)));
+    let created = create != 0;
+    // panics can't cross into the caller. The reason's already been printed.
+    let open = || match name {
+        Some(name) => MappedAtomics::new_named(name, created),
+        None => MappedAtomics::new(created),
+    };
+    match std::panic::catch_unwind(open) {
+        Ok(atomics) => Box::into_raw(Box::new(SpinSegment { atomics, created })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// unmap the segment, and remove the shm if spin_open() made it.
+///
+/// # Safety
+/// `seg` has to be from spin_open(), and not used after this.
+#[no_mangle]
+pub unsafe extern "C" fn spin_close(seg: *mut SpinSegment) {
+    if seg.is_null() {
+        return;
+    }
+    let seg = Box::from_raw(seg);
+    if seg.created {
+        seg.atomics.close();
+    } else {
+        seg.atomics.unmap();
+    }
+}
+
+/// 0 if the segment's layout matches this library's, -1 if not.
+///
+/// # Safety
+/// `seg` has to be from spin_open().
+#[no_mangle]
+pub unsafe extern "C" fn spin_check_header(seg: *const SpinSegment) -> c_int {
+    match (*seg).atomics.check_header() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            -1
+        }
+    }
+}
+
+/// the layout this library was built w/.
+#[no_mangle]
+pub extern "C" fn spin_layout_version() -> u32 {
+    LAYOUT_VERSION
+}
+
+/// the server's half of the handshake. Call right before the echo loop.
+///
+/// # Safety
+/// `seg` has to be from spin_open().
+#[no_mangle]
+pub unsafe extern "C" fn spin_server_ready(seg: *const SpinSegment) {
+    (*seg).atomics.mark_server_ready();
+}
+
+/// the sequence word the client bumps, and the server spins on.
+///
+/// # Safety
+/// `seg` has to be from spin_open(). The word is only good until spin_close().
+#[no_mangle]
+pub unsafe extern "C" fn spin_client_word(seg: *const SpinSegment) -> *mut u64 {
+    word((*seg).atomics.client_write)
+}
+
+/// the word the server echoes the sequence number to.
+///
+/// # Safety
+/// `seg` has to be from spin_open(). The word is only good until spin_close().
+#[no_mangle]
+pub unsafe extern "C" fn spin_server_word(seg: *const SpinSegment) -> *mut u64 {
+    word((*seg).atomics.server_write)
+}
+
+/// the client's payload, next to the client word.
+///
+/// # Safety
+/// `seg` has to be from spin_open(). The word is only good until spin_close().
+#[no_mangle]
+pub unsafe extern "C" fn spin_client_payload(seg: *const SpinSegment) -> *mut u64 {
+    word((*seg).atomics.client_payload)
+}
+
+/// where the server copies the payload to, before echoing the sequence number.
+///
+/// # Safety
+/// `seg` has to be from spin_open(). The word is only good until spin_close().
+#[no_mangle]
+pub unsafe extern "C" fn spin_server_payload(seg: *const SpinSegment) -> *mut u64 {
+    word((*seg).atomics.server_payload)
+}
+
+/// 1 once the client has asked the server to stop.
+///
+/// # Safety
+/// `seg` has to be from spin_open().
+#[no_mangle]
+pub unsafe extern "C" fn spin_stop_requested(seg: *const SpinSegment) -> c_int {
+    (*seg).atomics.stop_requested() as c_int
+}
@@ -0,0 +1,135 @@
+use crate::atomic_spin::MappedAtomics;
+use crate::svg::Svg;
+use crate::tsc;
+use hdrhistogram::Histogram;
+use rand::RngCore;
+use std::fmt::Write as FmtWrite;
+use std::path::{Path, PathBuf};
+
+// Times every round trip on its own w/ the TSC, and keeps
+// them all in a HDR histogram. Criterion only sees the mean
+// of each batch, so it can't tell you the p99.9 or the max.
+
+/// 3 significant figures, from 1ns up to 1 second.
+pub fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, 1_000_000_000, 3).expect("bad histogram bounds")
+}
+
+/// time `samples` round trips. Values are in ns.
+pub fn record_round_trips(client: &MappedAtomics, samples: usize) -> Histogram<u64> {
+    let mut hist = new_histogram();
+    let mut rng = rand::thread_rng();
+    if !tsc::invariant_tsc() {
+        eprintln!("warning : the TSC isn't invariant, histogram times may be off");
+    }
+    // work out the TSC frequency before we start.
+    tsc::ticks_per_ns();
+
+    for _ in 0..samples {
+        let payload = rng.next_u64();
+        let start = tsc::start();
+        client.client_run_once(payload);
+        let end = tsc::stop();
+        hist.saturating_record(tsc::ticks_to_ns(end.wrapping_sub(start)).round() as u64);
+    }
+    hist
+}
+
+/// where the histogram output for a benchmark goes.
+pub fn output_dir(group_name: &str, bench_name: &str) -> PathBuf {
+    Path::new("target").join("hdr").join(group_name).join(bench_name)
+}
+
+/// the percentiles worth looking at, w/ the names we print them under.
+pub static SUMMARY_PERCENTILES: [(&str, f64); 7] = [
+    ("p50", 50.0),
+    ("p90", 90.0),
+    ("p99", 99.0),
+    ("p99.9", 99.9),
+    ("p99.99", 99.99),
+    ("p99.999", 99.999),
+    ("max", 100.0),
+];
+
+pub fn summary(bench_name: &str, hist: &Histogram<u64>) -> String {
+    let mut out = format!("{} : {} round trips, ns :", bench_name, hist.len());
+    for (name, pct) in SUMMARY_PERCENTILES.iter() {
+        let _ = write!(out, " {} {}", name, hist.value_at_percentile(*pct));
+    }
+    out
+}
+
+/// the same layout as HdrHistogram's outputPercentileDistribution,
+/// so the usual HdrHistogram plotting tools can read it.
+pub fn percentile_distribution(hist: &Histogram<u64>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{:>12} {:>14} {:>10} {:>14}\n", "Value", "Percentile", "TotalCount", "1/(1-Percentile)");
+    for v in hist.iter_quantiles(5) {
+        let q = v.quantile_iterated_to();
+        let inverse = if q < 1.0 { format!("{:14.2}", 1.0 / (1.0 - q)) } else { String::new() };
+        let _ = writeln!(
+            out,
+            "{:12.3} {:14.12} {:10} {}",
+            v.value_iterated_to() as f64,
+            q,
+            v.count_since_last_iteration(),
+            inverse
+        );
+    }
+    let _ = writeln!(out, "#[Mean    = {:12.3}, StdDeviation   = {:12.3}]", hist.mean(), hist.stdev());
+    let _ = writeln!(out, "#[Max     = {:12.3}, Total count    = {:12}]", hist.max() as f64, hist.len());
+    out
+}
+
+/// latency by percentile, w/ the x axis in "nines" so the tail is spread out.
+pub fn tail_plot(bench_name: &str, hist: &Histogram<u64>) -> Svg {
+    let (width, height) = (800.0, 500.0);
+    let (left, right, top, bottom) = (70.0, 20.0, 40.0, 50.0);
+    let mut svg = Svg::new(width, height);
+
+    // x is log10(1/(1-q)), 0 is p0, 1 is p90, 2 is p99...
+    let max_nines = ((hist.len() as f64).log10().floor()).clamp(1.0, 6.0);
+    let points: Vec<(f64, f64)> = hist
+        .iter_quantiles(5)
+        .map(|v| (v.quantile_iterated_to(), v.value_iterated_to() as f64))
+        .filter(|(q, _)| *q < 1.0)
+        .map(|(q, value)| (((1.0 / (1.0 - q)).log10()).min(max_nines), value))
+        .collect();
+    let max_value = hist.max().max(1) as f64;
+
+    let x = |nines: f64| left + nines / max_nines * (width - left - right);
+    let y = |value: f64| height - bottom - value / max_value * (height - top - bottom);
+
+    svg.line(left, height - bottom, width - right, height - bottom, "black", 1.0);
+    svg.line(left, top, left, height - bottom, "black", 1.0);
+    for n in 0..=(max_nines as usize) {
+        let label = match n {
+            0 => "0%".to_string(),
+            _ => format!("{}%", 100.0 - 100.0 / 10f64.powi(n as i32)),
+        };
+        svg.line(x(n as f64), height - bottom, x(n as f64), top, "#dddddd", 1.0);
+        svg.text(x(n as f64), height - bottom + 18.0, &label, 11.0, "middle");
+    }
+    for i in 0..=4 {
+        let value = max_value * i as f64 / 4.0;
+        svg.text(left - 6.0, y(value) + 4.0, &format!("{:.0}", value), 11.0, "end");
+    }
+    svg.polyline(
+        &points.iter().map(|(n, v)| (x(*n), y(*v))).collect::<Vec<_>>(),
+        "steelblue",
+        2.0,
+    );
+    svg.text(width / 2.0, 24.0, &format!("{} latency by percentile", bench_name), 14.0, "middle");
+    svg.text(width / 2.0, height - 12.0, "percentile", 12.0, "middle");
+    svg.vertical_text(18.0, height / 2.0, "round trip (ns)", 12.0);
+    svg
+}
+
+/// print the summary, and write the percentile table and tail plot.
+pub fn report(group_name: &str, bench_name: &str, hist: &Histogram<u64>) -> std::io::Result<()> {
+    println!("{}", summary(bench_name, hist));
+    let dir = output_dir(group_name, bench_name);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("percentiles.hgrm"), percentile_distribution(hist))?;
+    tail_plot(bench_name, hist).save(&dir.join("tail.svg"))
+}
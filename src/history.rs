@@ -0,0 +1,185 @@
+use crate::aggregate::Run;
+use crate::compare::{compare, Comparison, Thresholds, Verdict};
+use crate::stats::Summary;
+use json::JsonValue;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Results over time. Every set of runs is appended to a JSONL file,
+// one line per benchmark, keyed by the git commit, the machine
+// fingerprint (see environment.rs) and the benchmark id. Nothing in
+// it is ever rewritten. See the history binary.
+
+/// the store, in the directory the benchmarks are run from.
+pub fn default_file() -> PathBuf {
+    PathBuf::from("history.jsonl")
+}
+
+/// one benchmark's best run at one commit on one machine.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// seconds since the epoch, when the runs were taken.
+    pub timestamp: u64,
+    pub commit: String,
+    /// uncommitted changes in the tree.
+    pub dirty: bool,
+    pub fingerprint: String,
+    pub machine: String,
+    pub id: String,
+    pub unit: String,
+    pub summary: Summary,
+    /// kept so later commits can be tested against it.
+    pub samples: Vec<f64>,
+}
+
+impl Record {
+    /// `run` w/ the commit and machine from an environment snapshot.
+    pub fn new(id: &str, run: &Run, env: &JsonValue) -> Record {
+        Record {
+            timestamp: env["timestamp"].as_u64().unwrap_or(0),
+            commit: env["git"]["commit"].as_str().unwrap_or("unknown").to_string(),
+            dirty: env["git"]["dirty"].as_bool().unwrap_or(false),
+            fingerprint: crate::environment::fingerprint(env),
+            machine: env["cpu"]["model"].as_str().unwrap_or("unknown").to_string(),
+            id: id.to_string(),
+            unit: run.unit.clone(),
+            summary: run.summary,
+            samples: run.samples.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json::object! {
+            timestamp: self.timestamp,
+            commit: self.commit.as_str(),
+            dirty: self.dirty,
+            fingerprint: self.fingerprint.as_str(),
+            machine: self.machine.as_str(),
+            id: self.id.as_str(),
+            unit: self.unit.as_str(),
+            summary: self.summary.to_json(),
+            samples: self.samples.clone(),
+        }
+    }
+
+    pub fn from_json(j: &JsonValue) -> Option<Record> {
+        let samples: Vec<f64> = j["samples"].members().filter_map(|s| s.as_f64()).collect();
+        if samples.is_empty() {
+            return None;
+        }
+        Some(Record {
+            timestamp: j["timestamp"].as_u64()?,
+            commit: j["commit"].as_str()?.to_string(),
+            dirty: j["dirty"].as_bool().unwrap_or(false),
+            fingerprint: j["fingerprint"].as_str()?.to_string(),
+            machine: j["machine"].as_str().unwrap_or("unknown").to_string(),
+            id: j["id"].as_str()?.to_string(),
+            unit: j["unit"].as_str()?.to_string(),
+            // worked out again rather than trusted, so old records
+            // get any new fields.
+            summary: Summary::of(&samples),
+            samples,
+        })
+    }
+
+    /// as a Run, for compare().
+    pub fn to_run(&self) -> Run {
+        Run {
+            file: default_file(),
+            run: 0,
+            unit: self.unit.clone(),
+            samples: self.samples.clone(),
+            summary: self.summary,
+        }
+    }
+
+    /// "1a2b3c4d", or "1a2b3c4d+" w/ uncommitted changes.
+    pub fn short_commit(&self) -> String {
+        let short: String = self.commit.chars().take(8).collect();
+        if self.dirty {
+            short + "+"
+        } else {
+            short
+        }
+    }
+}
+
+pub fn append(file: &Path, records: &[Record]) -> std::io::Result<()> {
+    let mut out = std::fs::OpenOptions::new().create(true).append(true).open(file)?;
+    let mut lines = String::new();
+    for r in records.iter() {
+        lines.push_str(&r.to_json().dump());
+        lines.push('\n');
+    }
+    // one write, so a crash doesn't leave half a set.
+    out.write_all(lines.as_bytes())
+}
+
+/// every record in the store, oldest first. Lines that
+/// don't parse are skipped w/ a warning.
+pub fn load(file: &Path) -> std::io::Result<Vec<Record>> {
+    let text = match std::fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        match json::parse(line).ok().as_ref().and_then(Record::from_json) {
+            Some(r) => records.push(r),
+            None => eprintln!("warning : {} line {} isn't a record, skipped", file.display(), i + 1),
+        }
+    }
+    Ok(records)
+}
+
+/// one benchmark on one machine, a record per commit in the order
+/// they were first added. A commit that was run again keeps its
+/// place, w/ its last record.
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub fingerprint: String,
+    pub machine: String,
+    pub id: String,
+    pub records: Vec<Record>,
+}
+
+pub fn series(records: &[Record]) -> Vec<Series> {
+    let mut by_key: BTreeMap<(String, String), Series> = BTreeMap::new();
+    for r in records.iter() {
+        let s = by_key
+            .entry((r.fingerprint.clone(), r.id.clone()))
+            .or_insert_with(|| Series {
+                fingerprint: r.fingerprint.clone(),
+                machine: r.machine.clone(),
+                id: r.id.clone(),
+                records: Vec::new(),
+            });
+        // only the same build counts as a re-run.
+        match s.records.iter().position(|old| old.commit == r.commit && !old.dirty && !r.dirty) {
+            Some(i) => s.records[i] = r.clone(),
+            None => s.records.push(r.clone()),
+        }
+    }
+    by_key.into_values().collect()
+}
+
+impl Series {
+    /// each record against the one before it. None for the first.
+    pub fn steps(&self, resamples: usize) -> Vec<Option<Comparison>> {
+        let mut steps = vec![None];
+        for pair in self.records.windows(2) {
+            steps.push(Some(compare(&self.id, &pair[0].to_run(), &pair[1].to_run(), resamples)));
+        }
+        steps
+    }
+
+    /// the index of the first record that got slower than the one
+    /// before it, by compare's rules.
+    pub fn first_regression(&self, steps: &[Option<Comparison>], t: &Thresholds) -> Option<usize> {
+        steps
+            .iter()
+            .position(|s| s.as_ref().is_some_and(|c| c.verdict(t) == Verdict::Slower))
+    }
+}
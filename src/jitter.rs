@@ -0,0 +1,249 @@
+use crate::bench_utils::pin_to;
+use crate::hdr;
+use crate::tsc;
+use hdrhistogram::Histogram;
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Measures how often something else takes a CPU away from us, like
+// sysjitter. A thread on each CPU spins reading the TSC, and any gap
+// between two reads longer than the threshold is time the CPU was off
+// doing something else : an interrupt, a kernel thread, SMM...
+// /proc/interrupts before and after says which interrupts it likely was.
+
+/// interrupt name -> (CPU -> count), from /proc/interrupts.
+pub type InterruptCounts = BTreeMap<String, BTreeMap<usize, u64>>;
+
+/// parse /proc/interrupts. Names are the IRQ and its description,
+/// ex : "LOC Local timer interrupts", "31 virtio0-stats".
+pub fn parse_interrupts(text: &str) -> InterruptCounts {
+    let mut counts = InterruptCounts::new();
+    let mut lines = text.lines();
+    let cpus: Vec<usize> = match lines.next() {
+        Some(header) => header
+            .split_whitespace()
+            .filter_map(|c| c.strip_prefix("CPU")?.parse().ok())
+            .collect(),
+        None => return counts,
+    };
+    for line in lines {
+        let Some((irq, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let mut fields = rest.split_whitespace().peekable();
+        let mut per_cpu = BTreeMap::new();
+        for cpu in cpus.iter() {
+            match fields.peek().and_then(|f| f.parse::<u64>().ok()) {
+                Some(count) => {
+                    per_cpu.insert(*cpu, count);
+                    fields.next();
+                }
+                None => break,
+            }
+        }
+        // the IRQ numbers have the chip and trigger type before the name.
+        let irq = irq.trim();
+        let desc: Vec<&str> = fields.collect();
+        let label = if irq.parse::<usize>().is_ok() {
+            format!("{} {}", irq, desc.last().copied().unwrap_or(""))
+        } else {
+            format!("{} {}", irq, desc.join(" "))
+        };
+        counts.insert(label.trim().to_string(), per_cpu);
+    }
+    counts
+}
+
+pub fn read_interrupts() -> InterruptCounts {
+    parse_interrupts(&std::fs::read_to_string("/proc/interrupts").unwrap_or_default())
+}
+
+/// the interrupts that went to `cpu` between the two, most first.
+pub fn interrupt_deltas(before: &InterruptCounts, after: &InterruptCounts, cpu: usize) -> Vec<(String, u64)> {
+    let mut deltas: Vec<(String, u64)> = after
+        .iter()
+        .filter_map(|(name, counts)| {
+            let a = *counts.get(&cpu)?;
+            let b = before.get(name).and_then(|c| c.get(&cpu)).copied().unwrap_or(0);
+            Some((name.clone(), a.saturating_sub(b)))
+        })
+        .filter(|(_, d)| *d > 0)
+        .collect();
+    deltas.sort_by_key(|d| std::cmp::Reverse(d.1));
+    deltas
+}
+
+/// what one CPU saw.
+pub struct CpuJitter {
+    pub cpu: usize,
+    pub duration_ns: f64,
+    /// every gap over the threshold, in ns.
+    pub gaps: Histogram<u64>,
+    pub interrupts: Vec<(String, u64)>,
+}
+
+impl CpuJitter {
+    pub fn lost_ns(&self) -> f64 {
+        self.gaps.iter_recorded().map(|v| v.value_iterated_to() as f64 * v.count_at_value() as f64).sum()
+    }
+
+    /// how much of the time the CPU was away, in percent.
+    pub fn lost_pct(&self) -> f64 {
+        100.0 * self.lost_ns() / self.duration_ns.max(1.0)
+    }
+}
+
+/// spin on the calling thread for `duration`, and record every gap over `threshold_ns`.
+pub fn spin(duration: Duration, threshold_ns: u64) -> (Histogram<u64>, f64) {
+    let mut gaps = hdr::new_histogram();
+    let threshold = (threshold_ns as f64 * tsc::ticks_per_ns()) as u64;
+    let total = (duration.as_nanos() as f64 * tsc::ticks_per_ns()) as u64;
+
+    let start = tsc::start();
+    let mut last = start;
+    while last.wrapping_sub(start) < total {
+        let now = tsc::start();
+        let gap = now.wrapping_sub(last);
+        if gap > threshold {
+            gaps.saturating_record(tsc::ticks_to_ns(gap).round() as u64);
+        }
+        last = now;
+    }
+    (gaps, tsc::ticks_to_ns(last.wrapping_sub(start)))
+}
+
+/// spin on all the `cpus` at once.
+pub fn measure(cpus: &[usize], duration: Duration, threshold_ns: u64) -> Vec<CpuJitter> {
+    // calibrate now, not on every thread.
+    tsc::ticks_per_ns();
+    let before = read_interrupts();
+    let results: Vec<(usize, Histogram<u64>, f64)> = std::thread::scope(|scope| {
+        let threads: Vec<_> = cpus
+            .iter()
+            .map(|cpu| {
+                scope.spawn(move || {
+                    pin_to(*cpu);
+                    let (gaps, duration_ns) = spin(duration, threshold_ns);
+                    (*cpu, gaps, duration_ns)
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().expect("jitter thread died")).collect()
+    });
+    let after = read_interrupts();
+
+    results
+        .into_iter()
+        .map(|(cpu, gaps, duration_ns)| CpuJitter {
+            cpu,
+            duration_ns,
+            gaps,
+            interrupts: interrupt_deltas(&before, &after, cpu),
+        })
+        .collect()
+}
+
+/// a few lines per CPU.
+pub fn summary(results: &[CpuJitter]) -> String {
+    let mut out = String::new();
+    for r in results.iter() {
+        let _ = write!(
+            out,
+            "CPU {:3} : {} interruptions, {:.4}% lost",
+            r.cpu,
+            r.gaps.len(),
+            r.lost_pct()
+        );
+        if !r.gaps.is_empty() {
+            let _ = write!(
+                out,
+                ", ns p50 {} p99 {} max {}",
+                r.gaps.value_at_percentile(50.0),
+                r.gaps.value_at_percentile(99.0),
+                r.gaps.max()
+            );
+        }
+        let _ = writeln!(out);
+        for (name, count) in r.interrupts.iter().take(5) {
+            let _ = writeln!(out, "          {:8} x {}", count, name);
+        }
+    }
+    out
+}
+
+/// true if any CPU was away more than `noisy_pct` of the time.
+pub fn is_noisy(results: &[CpuJitter], noisy_pct: f64) -> bool {
+    results.iter().any(|r| r.lost_pct() > noisy_pct)
+}
+
+pub fn to_json(results: &[CpuJitter]) -> json::JsonValue {
+    let mut out = json::JsonValue::new_array();
+    for r in results.iter() {
+        let mut interrupts = json::JsonValue::new_object();
+        for (name, count) in r.interrupts.iter() {
+            interrupts[name.as_str()] = (*count).into();
+        }
+        // power of 2 buckets, keyed by the bottom of the bucket.
+        let mut buckets = BTreeMap::new();
+        for v in r.gaps.iter_recorded() {
+            let bottom = 1u64 << (63 - v.value_iterated_to().max(1).leading_zeros());
+            *buckets.entry(bottom).or_insert(0) += v.count_at_value();
+        }
+        let mut histogram = json::JsonValue::new_object();
+        for (bottom, count) in buckets {
+            histogram[bottom.to_string()] = count.into();
+        }
+        let _ = out.push(json::object! {
+            cpu: r.cpu,
+            duration_ns: r.duration_ns,
+            interruptions: r.gaps.len(),
+            lost_pct: r.lost_pct(),
+            max_ns: r.gaps.max(),
+            gap_histogram_ns: histogram,
+            interrupts: interrupts,
+        });
+    }
+    out
+}
+
+/// where the before/after measurements for a benchmark go.
+pub fn output_file(group_name: &str, bench_name: &str) -> PathBuf {
+    Path::new("target")
+        .join("jitter")
+        .join(group_name)
+        .join(format!("{}.json", bench_name))
+}
+
+/// print the before and after, and write them out w/ the verdict.
+/// Returns true if the run was noisy.
+pub fn report(
+    group_name: &str,
+    bench_name: &str,
+    before: &[CpuJitter],
+    after: &[CpuJitter],
+    noisy_pct: f64,
+) -> std::io::Result<bool> {
+    let noisy = is_noisy(before, noisy_pct) || is_noisy(after, noisy_pct);
+    println!("{} : system noise before\n{}", bench_name, summary(before));
+    println!("{} : system noise after\n{}", bench_name, summary(after));
+    if noisy {
+        println!(
+            "{} : NOISY, a CPU lost more than {}% of its time. The results will be discarded",
+            bench_name, noisy_pct
+        );
+    }
+    let out = json::object! {
+        group: group_name,
+        name: bench_name,
+        noisy: noisy,
+        noisy_pct: noisy_pct,
+        before: to_json(before),
+        after: to_json(after),
+    };
+    let file = output_file(group_name, bench_name);
+    std::fs::create_dir_all(file.parent().unwrap())?;
+    std::fs::write(file, out.pretty(2))?;
+    Ok(noisy)
+}
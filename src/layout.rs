@@ -0,0 +1,221 @@
+use std::mem::{offset_of, size_of};
+use std::sync::atomic::AtomicU64;
+
+// The one definition of the shared memory segment. atomic_spin.rs maps
+// it, and the gen_header binary turns it into include/spin_layout.h for
+// the C++ and zig servers. The kotlin servers get their addresses from
+// the C ABI in ffi.rs, so they follow along too. Change it here, bump
+// LAYOUT_VERSION, run gen_header and rebuild.
+
+/// "SPIN"
+pub const LAYOUT_MAGIC: u32 = 0x5350_494e;
+/// bump when the layout changes.
+pub const LAYOUT_VERSION: u32 = 4;
+/// what goes in the control word to ask the server to stop.
+pub const STOP: u64 = 1;
+
+/// a sequence word and its payload, in the same cache line.
+/// Only the Rust servers echo the payload, w/ --checked.
+#[repr(C)]
+pub struct Channel {
+    pub seq: AtomicU64,
+    pub payload: AtomicU64,
+}
+
+/// the handshake. Whoever creates the segment writes LAYOUT_MAGIC and
+/// LAYOUT_VERSION in `magic_version`, the LayoutMode in `mode` and how
+/// to prefault it in `prefault` (see backing.rs). Servers check them
+/// and write their pid in `server_pid`.
+#[repr(C)]
+pub struct Header {
+    pub magic_version: AtomicU64,
+    pub server_pid: AtomicU64,
+    pub mode: AtomicU64,
+    pub prefault: AtomicU64,
+}
+
+/// the page the client and server share. The client only writes `client`
+/// and `control`, the server only `server` and `header.server_pid`.
+/// This is the Default LayoutMode, the others move `server`.
+#[repr(C, align(4096))]
+pub struct Segment {
+    pub client: Channel,
+    _pad0: [u8; 2048 - size_of::<Channel>()],
+    /// a few cache lines down from the client's.
+    pub server: Channel,
+    _pad1: [u8; 1024 - size_of::<Channel>()],
+    pub control: AtomicU64,
+    _pad2: [u8; 512 - size_of::<AtomicU64>()],
+    pub header: Header,
+    _pad3: [u8; 512 - size_of::<Header>()],
+}
+
+pub const SEGMENT_SIZE: usize = size_of::<Segment>();
+pub const CLIENT_OFFSET: usize = offset_of!(Segment, client);
+pub const SERVER_OFFSET: usize = offset_of!(Segment, server);
+/// from the start of either channel.
+pub const PAYLOAD_OFFSET: usize = offset_of!(Channel, payload);
+pub const CONTROL_OFFSET: usize = offset_of!(Segment, control);
+pub const HEADER_OFFSET: usize = offset_of!(Segment, header);
+pub const SERVER_PID_OFFSET: usize = HEADER_OFFSET + offset_of!(Header, server_pid);
+pub const MODE_OFFSET: usize = HEADER_OFFSET + offset_of!(Header, mode);
+pub const PREFAULT_OFFSET: usize = HEADER_OFFSET + offset_of!(Header, prefault);
+
+// it has to fit in the smallest page we'd map.
+const _: () = assert!(SEGMENT_SIZE == 4096);
+
+/// what goes in the header word.
+pub fn layout_header() -> u64 {
+    ((LAYOUT_MAGIC as u64) << 32) | LAYOUT_VERSION as u64
+}
+
+/// where the server's channel goes. The client's channel, the control
+/// word and the header stay put in the first page. Picked by whoever
+/// creates the segment, and written in the header, so the Rust servers
+/// (and the kotlin ones, through ffi.rs) follow along. The C++ and zig
+/// servers only do Default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayoutMode {
+    /// 2048 bytes down, the Segment above.
+    #[default]
+    Default,
+    /// both channels in the one cache line, so every write bounces it.
+    SameLine,
+    /// the next line down. The spatial prefetcher pulls in lines a 128 byte
+    /// pair at a time, so this can still act like the same line.
+    AdjacentLines,
+    /// 128 bytes down, the next pair of lines.
+    Spaced128,
+    /// the server's channel at the start of the second page.
+    SeparatePages,
+    /// the second page, but mapped on its own, so it has its own
+    /// virtual address range, and likely its own TLB entry.
+    SeparateMappings,
+}
+
+impl LayoutMode {
+    pub const ALL: [LayoutMode; 6] = [
+        LayoutMode::SameLine,
+        LayoutMode::AdjacentLines,
+        LayoutMode::Spaced128,
+        LayoutMode::Default,
+        LayoutMode::SeparatePages,
+        LayoutMode::SeparateMappings,
+    ];
+
+    /// what goes in the header's mode word.
+    pub fn id(self) -> u64 {
+        match self {
+            LayoutMode::Default => 0,
+            LayoutMode::SameLine => 1,
+            LayoutMode::AdjacentLines => 2,
+            LayoutMode::Spaced128 => 3,
+            LayoutMode::SeparatePages => 4,
+            LayoutMode::SeparateMappings => 5,
+        }
+    }
+
+    pub fn from_id(id: u64) -> Option<LayoutMode> {
+        LayoutMode::ALL.into_iter().find(|m| m.id() == id)
+    }
+
+    /// ex : "same_line"
+    pub fn name(self) -> &'static str {
+        match self {
+            LayoutMode::Default => "default",
+            LayoutMode::SameLine => "same_line",
+            LayoutMode::AdjacentLines => "adjacent_lines",
+            LayoutMode::Spaced128 => "spaced_128",
+            LayoutMode::SeparatePages => "separate_pages",
+            LayoutMode::SeparateMappings => "separate_mappings",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<LayoutMode> {
+        LayoutMode::ALL.into_iter().find(|m| m.name() == name.trim())
+    }
+
+    /// where the server's channel starts, from the start of the shm.
+    pub fn server_offset(self, page_size: usize) -> usize {
+        match self {
+            LayoutMode::Default => SERVER_OFFSET,
+            LayoutMode::SameLine => 32,
+            LayoutMode::AdjacentLines => 64,
+            LayoutMode::Spaced128 => 128,
+            LayoutMode::SeparatePages | LayoutMode::SeparateMappings => page_size.max(SEGMENT_SIZE),
+        }
+    }
+
+    /// how many pages the shm needs.
+    pub fn pages(self) -> usize {
+        match self {
+            LayoutMode::SeparatePages | LayoutMode::SeparateMappings => 2,
+            _ => 1,
+        }
+    }
+
+    /// the server's page is mapped on its own.
+    pub fn separate_mapping(self) -> bool {
+        self == LayoutMode::SeparateMappings
+    }
+}
+
+/// include/spin_layout.h, what gen_header writes.
+pub fn c_header() -> String {
+    let defines = [
+        ("SPIN_SEGMENT_SIZE", SEGMENT_SIZE.to_string()),
+        ("SPIN_CLIENT_OFFSET", CLIENT_OFFSET.to_string()),
+        ("SPIN_SERVER_OFFSET", SERVER_OFFSET.to_string()),
+        ("SPIN_PAYLOAD_OFFSET", PAYLOAD_OFFSET.to_string()),
+        ("SPIN_CONTROL_OFFSET", CONTROL_OFFSET.to_string()),
+        ("SPIN_HEADER_OFFSET", HEADER_OFFSET.to_string()),
+        ("SPIN_SERVER_PID_OFFSET", SERVER_PID_OFFSET.to_string()),
+        ("SPIN_MODE_OFFSET", MODE_OFFSET.to_string()),
+        ("SPIN_PREFAULT_OFFSET", PREFAULT_OFFSET.to_string()),
+        ("SPIN_MODE_DEFAULT", format!("{}u", LayoutMode::Default.id())),
+        ("SPIN_LAYOUT_MAGIC", format!("{:#x}u", LAYOUT_MAGIC)),
+        ("SPIN_LAYOUT_VERSION", format!("{}u", LAYOUT_VERSION)),
+        ("SPIN_STOP", format!("{}u", STOP)),
+    ];
+    let mut h = String::new();
+    h.push_str("// generated by gen_header from src/layout.rs, don't edit.\n");
+    h.push_str("// change the Rust, bump LAYOUT_VERSION, and cargo run --bin gen_header.\n\n");
+    h.push_str("#ifndef ASYNC_BENCH_SPIN_LAYOUT_H\n#define ASYNC_BENCH_SPIN_LAYOUT_H\n\n");
+    h.push_str("#include <stdint.h>\n\n");
+    for (name, value) in defines {
+        h.push_str(&format!("#define {} {}\n", name, value));
+    }
+    h.push_str(&format!(
+        r#"
+struct spin_channel {{
+    uint64_t seq;
+    uint64_t payload;
+}};
+
+struct spin_header {{
+    uint64_t magic_version;
+    uint64_t server_pid;
+    uint64_t mode;
+    uint64_t prefault;
+}};
+
+struct spin_segment {{
+    struct spin_channel client;
+    uint8_t pad0[{}];
+    struct spin_channel server;
+    uint8_t pad1[{}];
+    uint64_t control;
+    uint8_t pad2[{}];
+    struct spin_header header;
+    uint8_t pad3[{}];
+}};
+
+#endif // ASYNC_BENCH_SPIN_LAYOUT_H
+"#,
+        SERVER_OFFSET - CLIENT_OFFSET - size_of::<Channel>(),
+        CONTROL_OFFSET - SERVER_OFFSET - size_of::<Channel>(),
+        HEADER_OFFSET - CONTROL_OFFSET - size_of::<AtomicU64>(),
+        SEGMENT_SIZE - HEADER_OFFSET - size_of::<Header>(),
+    ));
+    h
+}
@@ -1,25 +1,67 @@
 #[macro_use]
 extern crate lazy_static;
 
-use std::ffi::CStr;
+use std::ffi::CString;
 use std::time::Duration;
 
-/// here are general utility functions and some global constants.
-/// They are used by both the benchmarking code and the servers
-/// so they are in a library
+// here are general utility functions and some global constants.
+// They are used by both the benchmarking code and the servers
+// so they are in a library
 
 pub mod atomic_spin;
+pub mod backing;
+pub mod numa;
+pub mod layout;
+pub mod registry;
+pub mod conformance;
 pub mod bench_utils;
 pub mod async_impl;
+pub mod config;
+pub mod topology;
+pub mod servers;
+pub mod tsc;
+pub mod svg;
+pub mod hdr;
+pub mod perf;
+pub mod open_loop;
+pub mod environment;
+pub mod env_check;
+pub mod jitter;
+pub mod stats;
+pub mod aggregate;
+pub mod plot;
+pub mod compare;
+pub mod history;
+pub mod results;
+pub mod adaptive;
+pub mod warmup;
+pub mod ffi;
 
 pub static SAMPLE_SIZE: usize = 1000;
 pub static WARMUP_TIME: Duration = Duration::from_secs(10);
 pub static RUN_TIME: Duration = Duration::from_secs(30);
-pub static CLIENT_CPU: usize = 4;
-pub static SERVER_CPU: &str = "5";
 
 lazy_static! {
-    static ref SH_MEM_NAME: &'static CStr =
-        unsafe { CStr::from_bytes_with_nul_unchecked("/spinnmem\0".as_bytes()) };
+    /// which CPUs to run on. See config::BenchConfig for where it comes from.
+    pub static ref CONFIG: config::BenchConfig = config::BenchConfig::load();
+
+}
+
+/// the shm name everyone uses, unless SHM_NAME says otherwise.
+/// Only the Rust and kotlin servers look at SHM_NAME, the others always use this.
+pub static DEFAULT_SHM_NAME: &str = "/spinnmem";
+
+/// the name of the shared memory. Servers launched from
+/// the benchmarks inherit the env, so both sides agree.
+pub fn shm_name() -> CString {
+    let name = std::env::var("SHM_NAME").unwrap_or_else(|_| DEFAULT_SHM_NAME.to_string());
+    CString::new(name).expect("SHM_NAME can't have a nul in it")
 }
 
+
+/// the memfd the client passed down, when the segment isn't in shm.
+/// See backing.rs. Takes precedence over SHM_NAME when opening.
+pub fn shm_fd() -> Option<std::os::raw::c_int> {
+    let fd = std::env::var(backing::SHM_FD_ENV).ok()?;
+    Some(fd.trim().parse().unwrap_or_else(|_| panic!("SHM_FD must be a fd number, not {}", fd)))
+}
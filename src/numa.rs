@@ -0,0 +1,220 @@
+use crate::topology;
+use std::ffi::c_void;
+use std::os::raw::{c_int, c_long, c_uint, c_ulong};
+
+// Which NUMA node the segment's pages live on. On a multi-socket box a
+// round trip where the page is on the other socket from the client (or
+// the server) pays for the interconnect, on top of the cache line moving.
+// The policy is applied w/ set_mempolicy() while the segment is mapped and
+// faulted in, then mbind() on the mappings, which for shm sets the policy
+// on the shm itself, so the server's faults follow it too.
+// With one node (or none) all of this does nothing.
+
+const MPOL_DEFAULT: c_int = 0;
+const MPOL_BIND: c_int = 2;
+
+/// move any pages already faulted in somewhere else.
+const MPOL_MF_MOVE: c_uint = 2;
+
+/// get_mempolicy() flags, for the node a page is on.
+const MPOL_F_NODE: c_ulong = 1;
+const MPOL_F_ADDR: c_ulong = 2;
+
+/// bits in a node mask. get_mempolicy() wants at least as many as the kernel has nodes.
+const MAX_NODES: usize = 1024;
+type NodeMask = [c_ulong; MAX_NODES / 64];
+
+/// where the segment's pages go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumaPolicy {
+    /// wherever the first touch puts them, the creator's node.
+    #[default]
+    Default,
+    /// this node.
+    Node(usize),
+    /// the client CPU's node. See resolve()
+    Client,
+    /// the server CPU's node.
+    Server,
+}
+
+impl NumaPolicy {
+    /// ex : "client", "server", "default", or a node number.
+    pub fn parse(name: &str) -> Option<NumaPolicy> {
+        match name.trim() {
+            "default" => Some(NumaPolicy::Default),
+            "client" => Some(NumaPolicy::Client),
+            "server" => Some(NumaPolicy::Server),
+            n => n.parse().ok().map(NumaPolicy::Node),
+        }
+    }
+
+    /// ex : "client", "node1"
+    pub fn name(self) -> String {
+        match self {
+            NumaPolicy::Default => "default".to_string(),
+            NumaPolicy::Node(node) => format!("node{}", node),
+            NumaPolicy::Client => "client".to_string(),
+            NumaPolicy::Server => "server".to_string(),
+        }
+    }
+
+    /// Client and Server turned into the node of that CPU. Default if
+    /// the kernel doesn't say what node it's on.
+    pub fn resolve(self, client_cpu: usize, server_cpu: usize) -> NumaPolicy {
+        let node_of = |cpu| topology::cpu_node(cpu).map(NumaPolicy::Node).unwrap_or(NumaPolicy::Default);
+        match self {
+            NumaPolicy::Client => node_of(client_cpu),
+            NumaPolicy::Server => node_of(server_cpu),
+            policy => policy,
+        }
+    }
+
+    /// the node to bind to, if there's more than one to pick from. A Client
+    /// that hasn't been resolved is the node the calling thread is on, a
+    /// Server that hasn't is left alone.
+    fn node(self) -> Option<usize> {
+        if !is_numa() {
+            return None;
+        }
+        let node = match self {
+            NumaPolicy::Node(node) => node,
+            NumaPolicy::Client => topology::cpu_node(unsafe { libc::sched_getcpu() } as usize)?,
+            NumaPolicy::Default | NumaPolicy::Server => return None,
+        };
+        let nodes = topology::numa_nodes();
+        if !nodes.contains(&node) {
+            panic!("there's no NUMA node {}, there's {:?}", node, nodes);
+        }
+        Some(node)
+    }
+}
+
+/// more than one NUMA node online.
+pub fn is_numa() -> bool {
+    topology::numa_nodes().len() > 1
+}
+
+fn node_mask(node: usize) -> NodeMask {
+    let mut mask: NodeMask = [0; MAX_NODES / 64];
+    mask[node / 64] |= 1 << (node % 64);
+    mask
+}
+
+/// a policy we couldn't set isn't worth stopping the run for, ex : a
+/// container w/o the syscalls. The pages just land where they land.
+fn warn(what: &str) {
+    eprintln!(
+        "warning : {} failed : {}. The segment's pages go wherever the kernel puts them",
+        what,
+        std::io::Error::last_os_error()
+    );
+}
+
+/// run `map` w/ this thread's memory bound to `policy`'s node, then bind each
+/// of the mappings it returns there too. Whatever the thread's policy was
+/// before is put back.
+pub(crate) fn map_on<F>(policy: NumaPolicy, map: F) -> Vec<(*mut c_void, usize)>
+where
+    F: FnOnce() -> Vec<(*mut c_void, usize)>,
+{
+    let Some(node) = policy.node() else {
+        return map();
+    };
+    let mask = node_mask(node);
+    unsafe {
+        let mut old_mode: c_int = MPOL_DEFAULT;
+        let mut old_mask: NodeMask = [0; MAX_NODES / 64];
+        let saved = libc::syscall(
+            libc::SYS_get_mempolicy,
+            &mut old_mode as *mut c_int,
+            old_mask.as_mut_ptr(),
+            MAX_NODES as c_ulong,
+            std::ptr::null_mut::<c_void>(),
+            0 as c_ulong,
+        ) == 0;
+        let set = set_mempolicy(MPOL_BIND, &mask) == 0;
+        if !set {
+            warn("set_mempolicy");
+        }
+
+        let mappings = map();
+
+        for (ptr, len) in mappings.iter() {
+            let bound = libc::syscall(
+                libc::SYS_mbind,
+                *ptr,
+                *len as c_ulong,
+                MPOL_BIND as c_ulong,
+                mask.as_ptr(),
+                (MAX_NODES + 1) as c_ulong,
+                MPOL_MF_MOVE as c_ulong,
+            );
+            if bound != 0 {
+                warn("mbind");
+            }
+        }
+        if set {
+            if saved {
+                set_mempolicy(old_mode, &old_mask);
+            } else {
+                set_mempolicy(MPOL_DEFAULT, &[0; MAX_NODES / 64]);
+            }
+        }
+        mappings
+    }
+}
+
+unsafe fn set_mempolicy(mode: c_int, mask: &NodeMask) -> c_long {
+    let (mask, max_node) = match mode {
+        MPOL_DEFAULT => (std::ptr::null(), 0),
+        _ => (mask.as_ptr(), (MAX_NODES + 1) as c_ulong),
+    };
+    libc::syscall(libc::SYS_set_mempolicy, mode as c_ulong, mask, max_node)
+}
+
+/// the node the page at `addr` is on. None if it hasn't been faulted
+/// in, or the kernel won't say.
+pub fn page_node(addr: usize) -> Option<usize> {
+    let mut node: c_int = -1;
+    let got = unsafe {
+        libc::syscall(
+            libc::SYS_get_mempolicy,
+            &mut node as *mut c_int,
+            std::ptr::null_mut::<c_ulong>(),
+            0 as c_ulong,
+            addr,
+            MPOL_F_NODE | MPOL_F_ADDR,
+        )
+    };
+    (got == 0 && node >= 0).then_some(node as usize)
+}
+
+/// a CPU from `cpus` on another node from `client_cpu`, for the cross-node mode.
+/// None if they're all on the same one.
+pub fn cross_node_cpu(client_cpu: usize, cpus: &[usize]) -> Option<usize> {
+    let client_node = topology::cpu_node(client_cpu)?;
+    cpus.iter()
+        .copied()
+        .find(|cpu| topology::cpu_node(*cpu).is_some_and(|node| node != client_node))
+}
+
+/// ex : "node 0" or "node ?"
+fn node_name(node: Option<usize>) -> String {
+    node.map(|n| format!("node {}", n)).unwrap_or_else(|| "node ?".to_string())
+}
+
+/// print which node the client, the server and each side of the segment are on.
+/// `client` and `server` are the addresses of their words.
+pub fn report_placement(bench_name: &str, client_cpu: usize, server_cpu: usize, client: usize, server: usize) {
+    println!(
+        "{} : client cpu {} on {}, server cpu {} on {}, segment on {} / {}",
+        bench_name,
+        client_cpu,
+        node_name(topology::cpu_node(client_cpu)),
+        server_cpu,
+        node_name(topology::cpu_node(server_cpu)),
+        node_name(page_node(client)),
+        node_name(page_node(server)),
+    );
+}
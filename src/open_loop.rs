@@ -0,0 +1,271 @@
+use crate::atomic_spin::MappedAtomics;
+use crate::bench_utils::available_cpus;
+use crate::hdr;
+use crate::tsc;
+use hdrhistogram::Histogram;
+use rand::RngCore;
+use std::cell::UnsafeCell;
+use std::fmt::Write as FmtWrite;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Open loop load. The normal client is closed loop, it only sends
+// once the last echo is back, so a slow echo delays everything after
+// it and never shows up in the times (coordinated omission).
+//
+// Here a load generator thread pushes messages into a ring on a fixed
+// schedule, whether or not the server's keeping up. The client pops
+// them and sends each one through the shared memory as soon as the
+// last one's echoed. A message's latency is from when it was due
+// to its echo, so the time it sat in the ring behind a slow echo
+// is counted.
+
+/// messages the ring holds. If the server falls this far behind the
+/// load generator waits, and the wait is still counted.
+const RING_SIZE: usize = 1 << 16;
+
+/// a message waiting to go out.
+#[derive(Debug, Clone, Copy, Default)]
+struct Message {
+    /// when it was due, in TSC ticks.
+    intended: u64,
+    payload: u64,
+}
+
+/// on its own cache line, so the two sides don't
+/// bounce each other's cursor around.
+#[repr(align(128))]
+struct Cursor(AtomicUsize);
+
+/// single producer, single consumer. Each side only writes its own
+/// cursor, and they only ever go up. A slot's index is the cursor mod
+/// RING_SIZE.
+struct Ring {
+    slots: Box<[UnsafeCell<Message>]>,
+    /// the next slot the load generator writes.
+    send: Cursor,
+    /// the next slot the client reads.
+    receive: Cursor,
+}
+
+// a slot is only written by the producer before it moves `send` past
+// it, and only read by the consumer before it moves `receive` past it.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new() -> Ring {
+        Ring {
+            slots: (0..RING_SIZE).map(|_| UnsafeCell::new(Message::default())).collect(),
+            send: Cursor(AtomicUsize::new(0)),
+            receive: Cursor(AtomicUsize::new(0)),
+        }
+    }
+
+    /// false if it's full. Only call from the producer.
+    fn push(&self, message: Message) -> bool {
+        let send = self.send.0.load(Ordering::Relaxed);
+        if send.wrapping_sub(self.receive.0.load(Ordering::Acquire)) == RING_SIZE {
+            return false;
+        }
+        unsafe { *self.slots[send % RING_SIZE].get() = message };
+        self.send.0.store(send.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// None if it's empty. Only call from the consumer.
+    fn pop(&self) -> Option<Message> {
+        let receive = self.receive.0.load(Ordering::Relaxed);
+        if receive == self.send.0.load(Ordering::Acquire) {
+            return None;
+        }
+        let message = unsafe { *self.slots[receive % RING_SIZE].get() };
+        self.receive.0.store(receive.wrapping_add(1), Ordering::Release);
+        Some(message)
+    }
+}
+
+/// what came out of running at one offered rate.
+pub struct LoadPoint {
+    /// messages per second we tried to send. None is flat out.
+    pub offered_rate: Option<f64>,
+    /// messages per second that were echoed.
+    pub achieved_rate: f64,
+    /// intended send time to echo, in ns.
+    pub corrected: Histogram<u64>,
+    /// actual send time to echo, in ns. What the closed loop client sees.
+    pub uncorrected: Histogram<u64>,
+}
+
+impl LoadPoint {
+    /// true if the server couldn't keep up w/ the offered rate.
+    pub fn saturated(&self) -> bool {
+        match self.offered_rate {
+            Some(rate) => self.achieved_rate < rate * 0.95,
+            None => true,
+        }
+    }
+}
+
+/// the CPUs we can have but the client's and the server's, for the
+/// load generator. Sharing either one would time slice it w/ a spinning
+/// thread, and every message would wait a scheduler tick.
+pub fn spare_cpus(client_cpu: usize, server_cpu: usize) -> Vec<usize> {
+    available_cpus().iter().copied().filter(|c| *c != client_cpu && *c != server_cpu).collect()
+}
+
+/// move the calling thread onto `cpus`. A thread starts out w/ the
+/// affinity of the one that spawned it, ex : pinned to the client's CPU.
+fn move_to(cpus: &[usize]) {
+    if cpus.is_empty() {
+        return;
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in cpus.iter() {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+/// push `messages` into `ring`, one every `interval` ticks from `start`.
+/// Waits w/ yield_now(), so it doesn't starve the client or server if
+/// it ends up sharing a CPU w/ them.
+fn generate_load(ring: &Ring, start: u64, interval: f64, messages: usize) {
+    let mut rng = rand::thread_rng();
+    for i in 0..messages {
+        let intended = start + (i as f64 * interval) as u64;
+        while tsc::start() < intended {
+            std::thread::yield_now();
+        }
+        let message = Message { intended, payload: rng.next_u64() };
+        while !ring.push(message) {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// send `messages` at `rate` per second, or flat out if None.
+/// Flat out every message is due as soon as the last is echoed,
+/// so there's nothing to queue, and no load generator. The load
+/// generator keeps off this thread's CPU and `server_cpu`, unless
+/// there's nowhere else for it, see spare_cpus().
+pub fn run_at_rate(client: &MappedAtomics, rate: Option<f64>, messages: usize, server_cpu: usize) -> LoadPoint {
+    let mut corrected = hdr::new_histogram();
+    let mut uncorrected = hdr::new_histogram();
+    let ring = Ring::new();
+    let mut rng = rand::thread_rng();
+
+    let start = tsc::start();
+    std::thread::scope(|scope| {
+        if let Some(rate) = rate {
+            let interval = tsc::ticks_per_ns() * 1e9 / rate;
+            let (ring, client_cpu) = (&ring, unsafe { libc::sched_getcpu() } as usize);
+            scope.spawn(move || {
+                move_to(&spare_cpus(client_cpu, server_cpu));
+                generate_load(ring, start, interval, messages)
+            });
+        }
+        for _ in 0..messages {
+            let message = match rate {
+                Some(_) => loop {
+                    match ring.pop() {
+                        Some(message) => break message,
+                        None => core::hint::spin_loop(),
+                    }
+                },
+                None => Message { intended: tsc::start(), payload: rng.next_u64() },
+            };
+            let sent = tsc::start();
+            client.client_run_once(message.payload);
+            let done = tsc::stop();
+            corrected.saturating_record(tsc::ticks_to_ns(done.wrapping_sub(message.intended)).round() as u64);
+            uncorrected.saturating_record(tsc::ticks_to_ns(done.wrapping_sub(sent)).round() as u64);
+        }
+    });
+    let elapsed_ns = tsc::ticks_to_ns(tsc::stop().wrapping_sub(start));
+
+    LoadPoint {
+        offered_rate: rate,
+        achieved_rate: messages as f64 / (elapsed_ns / 1e9),
+        corrected,
+        uncorrected,
+    }
+}
+
+/// flat out first, for the saturation throughput, then each of `rates`
+/// for `secs` seconds. None if there's no spare CPU for the load
+/// generator besides this thread's and `server_cpu`.
+pub fn sweep(client: &MappedAtomics, rates: &[u64], secs: f64, server_cpu: usize) -> Option<Vec<LoadPoint>> {
+    let client_cpu = unsafe { libc::sched_getcpu() } as usize;
+    if spare_cpus(client_cpu, server_cpu).is_empty() {
+        return None;
+    }
+    let mut points = Vec::with_capacity(rates.len() + 1);
+    // a short run, to see how many messages `secs` is flat out.
+    let flat_out = run_at_rate(client, None, 10_000, server_cpu);
+    let flat_out_messages = (flat_out.achieved_rate * secs).max(1.0) as usize;
+    points.push(run_at_rate(client, None, flat_out_messages, server_cpu));
+    for rate in rates.iter() {
+        let messages = ((*rate as f64 * secs) as usize).max(1);
+        points.push(run_at_rate(client, Some(*rate as f64), messages, server_cpu));
+    }
+    Some(points)
+}
+
+/// where the open loop output for a benchmark goes.
+pub fn output_dir(group_name: &str, bench_name: &str) -> PathBuf {
+    Path::new("target").join("open_loop").join(group_name).join(bench_name)
+}
+
+fn rate_name(rate: Option<f64>) -> String {
+    match rate {
+        Some(rate) => format!("{:.0}", rate),
+        None => "max".to_string(),
+    }
+}
+
+/// a row per offered rate. Corrected percentiles, and the
+/// uncorrected p50/p99 to show how much the closed loop hides.
+pub fn table(points: &[LoadPoint]) -> String {
+    let mut out = String::new();
+    let _ = write!(out, "offered,achieved,saturated");
+    for (name, _) in hdr::SUMMARY_PERCENTILES.iter() {
+        let _ = write!(out, ",{}", name);
+    }
+    let _ = writeln!(out, ",uncorrected_p50,uncorrected_p99");
+    for p in points.iter() {
+        let _ = write!(out, "{},{:.0},{}", rate_name(p.offered_rate), p.achieved_rate, p.saturated());
+        for (_, pct) in hdr::SUMMARY_PERCENTILES.iter() {
+            let _ = write!(out, ",{}", p.corrected.value_at_percentile(*pct));
+        }
+        let _ = writeln!(
+            out,
+            ",{},{}",
+            p.uncorrected.value_at_percentile(50.0),
+            p.uncorrected.value_at_percentile(99.0)
+        );
+    }
+    out
+}
+
+/// print the table, and write it and a percentile table per rate.
+pub fn report(group_name: &str, bench_name: &str, points: &[LoadPoint]) -> std::io::Result<()> {
+    let table = table(points);
+    println!("{} : open loop, corrected latency in ns", bench_name);
+    print!("{}", table);
+    if let Some(max) = points.iter().find(|p| p.offered_rate.is_none()) {
+        println!("{} : saturation throughput {:.0} msgs/s", bench_name, max.achieved_rate);
+    }
+
+    let dir = output_dir(group_name, bench_name);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("open_loop.csv"), table)?;
+    for p in points.iter() {
+        std::fs::write(
+            dir.join(format!("corrected_{}.hgrm", rate_name(p.offered_rate))),
+            hdr::percentile_distribution(&p.corrected),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use std::os::raw::{c_int, c_ulong};
+
+// Hardware performance counters, via perf_event_open(2).
+// Latency alone can't say why callbacks and resume differ
+// between Intel and AMD, these might. Counters that can't be
+// opened (not supported, perf_event_paranoid, VMs) are skipped.
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_TYPE_HW_CACHE: u32 = 3;
+const PERF_TYPE_RAW: u32 = 4;
+
+const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+
+const PERF_COUNT_HW_CACHE_L1D: u64 = 0;
+const PERF_COUNT_HW_CACHE_LL: u64 = 2;
+const PERF_COUNT_HW_CACHE_DTLB: u64 = 3;
+const PERF_COUNT_HW_CACHE_OP_READ: u64 = 0;
+const PERF_COUNT_HW_CACHE_RESULT_MISS: u64 = 1;
+
+/// for comparing the backings. See backing.rs
+const PERF_COUNT_SW_PAGE_FAULTS: u64 = 2;
+
+/// MACHINE_CLEARS.MEMORY_ORDERING, event 0xC3 umask 0x02. Intel only.
+/// These are the "nukes" when another core writes a line we've
+/// speculatively read, ex : the spin loop seeing the client write.
+const INTEL_MACHINE_CLEARS_MEMORY_ORDERING: u64 = 0x02C3;
+
+const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1;
+const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 2;
+
+const PERF_FLAG_FD_CLOEXEC: c_ulong = 8;
+
+/// _IO('$', 0) and _IO('$', 1)
+const PERF_EVENT_IOC_ENABLE: c_ulong = 0x2400;
+const PERF_EVENT_IOC_DISABLE: c_ulong = 0x2401;
+
+// perf_event_attr flag bits
+const FLAG_INHERIT: u64 = 1 << 1;
+/// user space only. The round trip never goes into the kernel, and it
+/// lets perf_event_paranoid 2 (the default) count our own processes.
+const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+/// struct perf_event_attr, up to PERF_ATTR_SIZE_VER5.
+/// libc doesn't have it.
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved_2: u16,
+}
+
+fn cache_event(cache: u64) -> u64 {
+    cache | (PERF_COUNT_HW_CACHE_OP_READ << 8) | (PERF_COUNT_HW_CACHE_RESULT_MISS << 16)
+}
+
+/// name, type, config
+fn events() -> Vec<(&'static str, u32, u64)> {
+    let mut events = vec![
+        ("cycles", PERF_TYPE_HARDWARE, PERF_COUNT_HW_CPU_CYCLES),
+        ("instructions", PERF_TYPE_HARDWARE, PERF_COUNT_HW_INSTRUCTIONS),
+        ("branch-misses", PERF_TYPE_HARDWARE, PERF_COUNT_HW_BRANCH_MISSES),
+        ("L1d-misses", PERF_TYPE_HW_CACHE, cache_event(PERF_COUNT_HW_CACHE_L1D)),
+        ("LLC-misses", PERF_TYPE_HW_CACHE, cache_event(PERF_COUNT_HW_CACHE_LL)),
+        ("dTLB-misses", PERF_TYPE_HW_CACHE, cache_event(PERF_COUNT_HW_CACHE_DTLB)),
+        ("page-faults", PERF_TYPE_SOFTWARE, PERF_COUNT_SW_PAGE_FAULTS),
+    ];
+    if is_intel() {
+        events.push(("machine-clears", PERF_TYPE_RAW, INTEL_MACHINE_CLEARS_MEMORY_ORDERING));
+    }
+    events
+}
+
+#[cfg(target_arch = "x86_64")]
+fn is_intel() -> bool {
+    let id = std::arch::x86_64::__cpuid(0);
+    let mut vendor = Vec::with_capacity(12);
+    for reg in [id.ebx, id.edx, id.ecx] {
+        vendor.extend_from_slice(&reg.to_le_bytes());
+    }
+    vendor == b"GenuineIntel"
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn is_intel() -> bool {
+    false
+}
+
+struct Counter {
+    name: &'static str,
+    fd: c_int,
+}
+
+/// open each of events() on thread `pid`, and add the ones that opened.
+fn open_on(pid: libc::pid_t, inherit: bool, counters: &mut Vec<Counter>) {
+    for (name, type_, config) in events() {
+        let attr = PerfEventAttr {
+            type_,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            read_format: PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING,
+            flags: FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV | if inherit { FLAG_INHERIT } else { 0 },
+            ..Default::default()
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                pid,
+                -1 as c_int,
+                -1 as c_int,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        } as c_int;
+        if fd >= 0 {
+            counters.push(Counter { name, fd });
+        }
+    }
+}
+
+/// a set of counters on a thread, or on each thread of a process.
+pub struct PerfCounters {
+    counters: Vec<Counter>,
+}
+
+/// the count for each counter that opened, in the order they were opened.
+pub type CounterValues = Vec<(&'static str, u64)>;
+
+impl PerfCounters {
+    /// count thread `pid`. 0 is the calling thread. With `inherit` the
+    /// threads it starts later are counted too, but not the ones that
+    /// were already running, see open_process().
+    pub fn open(pid: libc::pid_t, inherit: bool) -> PerfCounters {
+        let mut counters = Vec::new();
+        open_on(pid, inherit, &mut counters);
+        PerfCounters { counters }
+    }
+
+    /// count every thread of a running process, like `perf stat -p`.
+    /// A set per thread in /proc/<pid>/task, each w/ inherit for the
+    /// threads it starts later, ex : the JVM's JIT and GC threads.
+    pub fn open_process(pid: libc::pid_t) -> PerfCounters {
+        let mut counters = Vec::new();
+        let tasks = std::fs::read_dir(format!("/proc/{}/task", pid));
+        for task in tasks.into_iter().flatten().flatten() {
+            if let Some(tid) = task.file_name().to_str().and_then(|t| t.parse().ok()) {
+                open_on(tid, true, &mut counters);
+            }
+        }
+        PerfCounters { counters }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// start counting again after disable(). With `inherit` the
+    /// threads the process started are turned on too.
+    pub fn enable(&self) {
+        self.ioctl(PERF_EVENT_IOC_ENABLE);
+    }
+
+    /// stop counting, ex : while criterion works out its stats.
+    /// read() still has what was counted so far.
+    pub fn disable(&self) {
+        self.ioctl(PERF_EVENT_IOC_DISABLE);
+    }
+
+    fn ioctl(&self, request: c_ulong) {
+        for counter in self.counters.iter() {
+            unsafe {
+                libc::ioctl(counter.fd, request as _, 0);
+            }
+        }
+    }
+
+    /// current counts, summed over the threads. Scaled up
+    /// if the kernel had to multiplex the counters.
+    pub fn read(&self) -> CounterValues {
+        let mut values: CounterValues = Vec::with_capacity(self.counters.len());
+        for counter in self.counters.iter() {
+            // value, time enabled, time running
+            let mut buf = [0u64; 3];
+            let n = unsafe {
+                libc::read(counter.fd, buf.as_mut_ptr() as *mut libc::c_void, std::mem::size_of_val(&buf))
+            };
+            if n as usize != std::mem::size_of_val(&buf) {
+                continue;
+            }
+            let value = if buf[2] > 0 && buf[2] < buf[1] {
+                (buf[0] as f64 * buf[1] as f64 / buf[2] as f64) as u64
+            } else {
+                buf[0]
+            };
+            match values.iter_mut().find(|(name, _)| *name == counter.name) {
+                Some((_, total)) => *total += value,
+                None => values.push((counter.name, value)),
+            }
+        }
+        values
+    }
+}
+
+impl Drop for PerfCounters {
+    fn drop(&mut self) {
+        for counter in self.counters.iter() {
+            unsafe {
+                libc::close(counter.fd);
+            }
+        }
+    }
+}
+
+/// after - before, for each counter in both.
+pub fn delta(before: &CounterValues, after: &CounterValues) -> CounterValues {
+    after
+        .iter()
+        .filter_map(|(name, a)| {
+            before
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, b)| (*name, a.wrapping_sub(*b)))
+        })
+        .collect()
+}
+
+/// one line per side, counts per round trip, and the IPC.
+pub fn summary(label: &str, deltas: &CounterValues, round_trips: u64) -> String {
+    let mut out = format!("{:>8} :", label);
+    for (name, value) in deltas.iter() {
+        out.push_str(&format!(" {} {:.2}", name, *value as f64 / round_trips.max(1) as f64));
+    }
+    let get = |n: &str| deltas.iter().find(|(name, _)| *name == n).map(|(_, v)| *v);
+    if let (Some(cycles), Some(instructions)) = (get("cycles"), get("instructions")) {
+        if cycles > 0 {
+            out.push_str(&format!(" IPC {:.2}", instructions as f64 / cycles as f64));
+        }
+    }
+    out
+}
+
+/// the deltas as json, for the result files.
+pub fn to_json(deltas: &CounterValues, round_trips: u64) -> json::JsonValue {
+    let mut out = json::JsonValue::new_object();
+    for (name, value) in deltas.iter() {
+        out[*name] = json::object! {
+            total: *value,
+            per_round_trip: *value as f64 / round_trips.max(1) as f64,
+        };
+    }
+    out
+}
+
+/// where the counts for a benchmark go.
+pub fn output_file(group_name: &str, bench_name: &str) -> std::path::PathBuf {
+    std::path::Path::new("target")
+        .join("perf")
+        .join(group_name)
+        .join(format!("{}.json", bench_name))
+}
+
+/// print the counts per round trip for both sides, and write them out.
+pub fn report(
+    group_name: &str,
+    bench_name: &str,
+    client: &CounterValues,
+    server: &CounterValues,
+    round_trips: u64,
+) -> std::io::Result<()> {
+    println!("{} : perf counters per round trip ({} round trips)", bench_name, round_trips);
+    println!("{}", summary("client", client, round_trips));
+    println!("{}", summary("server", server, round_trips));
+
+    let out = json::object! {
+        group: group_name,
+        name: bench_name,
+        round_trips: round_trips,
+        client: to_json(client, round_trips),
+        server: to_json(server, round_trips),
+    };
+    let file = output_file(group_name, bench_name);
+    std::fs::create_dir_all(file.parent().unwrap())?;
+    std::fs::write(file, out.pretty(2))
+}
@@ -0,0 +1,164 @@
+use crate::stats;
+use crate::svg::Svg;
+
+// Violin and box plots of round trip times, one row per benchmark,
+// like the graphs the R code made. See the plot binary.
+
+/// the colors the R graphs used, RColorBrewer's Dark2.
+pub static PALETTE: [&str; 8] = [
+    "#1b9e77", "#d95f02", "#7570b3", "#e7298a", "#66a61e", "#e6ab02", "#a6761d", "#666666",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Violin,
+    Box,
+}
+
+impl Style {
+    pub fn parse(name: &str) -> Style {
+        match name {
+            "violin" => Style::Violin,
+            "box" => Style::Box,
+            _ => panic!("style must be 'violin' or 'box', got '{}'", name),
+        }
+    }
+}
+
+/// one violin or box.
+pub struct Row {
+    pub label: String,
+    pub color: String,
+    pub samples: Vec<f64>,
+}
+
+/// what goes around the rows.
+pub struct Labels<'a> {
+    pub title: &'a str,
+    pub subtitle: &'a str,
+    pub unit: &'a str,
+    /// color key, when the colors mean something (ex : machines).
+    pub legend: &'a [(String, String)],
+}
+
+/// a round number step, so there are about `count` ticks over `span`.
+fn tick_step(span: f64, count: f64) -> f64 {
+    let raw = span / count;
+    let mag = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * mag)
+        .find(|s| *s >= raw)
+        .unwrap_or(10.0 * mag)
+}
+
+/// the x range : from the fastest sample, to the `clip` percentile
+/// of the slowest row. Anything past that isn't drawn.
+pub fn x_range(rows: &[Row], clip: f64) -> (f64, f64) {
+    let mut lo = f64::MAX;
+    let mut hi = f64::MIN;
+    for row in rows.iter() {
+        let sorted = stats::sorted(&row.samples);
+        lo = lo.min(sorted.first().copied().unwrap_or(lo));
+        hi = hi.max(stats::percentile(&sorted, clip));
+    }
+    let pad = ((hi - lo) * 0.01).max(f64::EPSILON);
+    (lo - pad, hi + pad)
+}
+
+pub fn plot(rows: &[Row], labels: &Labels, clip: f64, style: Style) -> Svg {
+    let row_height = 70.0;
+    let (left, right, top, bottom) = (170.0, 30.0, 70.0, 80.0);
+    let width = 900.0;
+    let height = top + bottom + row_height * rows.len() as f64;
+    let mut svg = Svg::new(width, height);
+
+    let (x_min, x_max) = x_range(rows, clip);
+    let x = |v: f64| left + (v - x_min) / (x_max - x_min) * (width - left - right);
+
+    // axis and grid
+    let step = tick_step(x_max - x_min, 6.0);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let mut tick = (x_min / step).ceil() * step;
+    while tick <= x_max {
+        svg.line(x(tick), top, x(tick), height - bottom, "#e5e5e5", 1.0);
+        svg.text(x(tick), height - bottom + 18.0, &format!("{:.*}", decimals, tick), 11.0, "middle");
+        tick += step;
+    }
+    svg.line(left, height - bottom, width - right, height - bottom, "black", 1.0);
+
+    for (i, row) in rows.iter().enumerate() {
+        let y = top + row_height * (i as f64 + 0.5);
+        let sorted: Vec<f64> = stats::sorted(&row.samples)
+            .into_iter()
+            .filter(|v| *v <= x_max)
+            .collect();
+        svg.text(left - 10.0, y + 4.0, &row.label, 12.0, "end");
+        if sorted.is_empty() {
+            continue;
+        }
+        let (p2_5, p97_5) = (stats::percentile(&sorted, 2.5), stats::percentile(&sorted, 97.5));
+
+        match style {
+            Style::Violin => {
+                let (lo, hi) = (sorted[0], sorted[sorted.len() - 1]);
+                let grid: Vec<f64> = (0..=100).map(|g| lo + (hi - lo) * g as f64 / 100.0).collect();
+                let density = stats::kde(&sorted, &grid);
+                let max_density = density.iter().cloned().fold(f64::MIN, f64::max).max(f64::EPSILON);
+                let half = row_height * 0.45;
+                let mut outline: Vec<(f64, f64)> = grid
+                    .iter()
+                    .zip(density.iter())
+                    .map(|(g, d)| (x(*g), y - d / max_density * half))
+                    .collect();
+                outline.extend(
+                    grid.iter()
+                        .zip(density.iter())
+                        .rev()
+                        .map(|(g, d)| (x(*g), y + d / max_density * half)),
+                );
+                svg.polygon(&outline, &row.color, 0.8, &row.color);
+
+                // p2.5 to p97.5, like median_hilow in the R code, and the mean.
+                svg.line(x(p2_5), y, x(p97_5), y, "black", 1.5);
+                svg.line(x(p2_5), y - 8.0, x(p2_5), y + 8.0, "black", 1.5);
+                svg.line(x(p97_5), y - 8.0, x(p97_5), y + 8.0, "black", 1.5);
+                let m = x(stats::mean(&sorted));
+                svg.polygon(
+                    &[(m, y - 5.0), (m + 5.0, y), (m, y + 5.0), (m - 5.0, y)],
+                    "white",
+                    1.0,
+                    "black",
+                );
+            }
+            Style::Box => {
+                let (q1, q3) = (stats::percentile(&sorted, 25.0), stats::percentile(&sorted, 75.0));
+                let half = row_height * 0.25;
+                svg.line(x(p2_5), y, x(q1), y, "black", 1.0);
+                svg.line(x(q3), y, x(p97_5), y, "black", 1.0);
+                svg.line(x(p2_5), y - half / 2.0, x(p2_5), y + half / 2.0, "black", 1.0);
+                svg.line(x(p97_5), y - half / 2.0, x(p97_5), y + half / 2.0, "black", 1.0);
+                svg.rect(x(q1), y - half, x(q3) - x(q1), half * 2.0, &row.color, Some("black"));
+                let med = x(stats::median(&sorted));
+                svg.line(med, y - half, med, y + half, "black", 2.0);
+            }
+        }
+    }
+
+    svg.text(width / 2.0, 26.0, labels.title, 16.0, "middle");
+    svg.text(width / 2.0, 46.0, labels.subtitle, 12.0, "middle");
+    let unit = if labels.unit == "ns" { "Nanoseconds" } else { labels.unit };
+    svg.text((left + width - right) / 2.0, height - bottom + 40.0, unit, 12.0, "middle");
+    let caption = match style {
+        Style::Violin => format!("whiskers are p2.5 to p97.5. white diamonds are mean. clipped at p{}", clip),
+        Style::Box => format!("boxes are p25 to p75, whiskers p2.5 to p97.5. clipped at p{}", clip),
+    };
+    svg.text(width - right, height - 12.0, &caption, 10.0, "end");
+
+    for (i, (name, color)) in labels.legend.iter().enumerate() {
+        let lx = left + i as f64 * 150.0;
+        svg.rect(lx, height - 24.0, 12.0, 12.0, color, None);
+        svg.text(lx + 16.0, height - 14.0, name, 11.0, "start");
+    }
+    svg
+}
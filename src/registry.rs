@@ -0,0 +1,123 @@
+use crate::backing::MapOptions;
+use crate::bench_utils::{launch_local, launch_local_java, JAVA_OPTS};
+use std::path::Path;
+use std::process::{Child, Command};
+
+// Every out of process server the benchmarks run, and how to start it.
+// The atomic_spin group benchmarks these in order, and the conformance
+// checker runs each of them. Add a new server here and both pick it up.
+
+/// the kotlin servers are all in here.
+pub static KOTLIN_JAR: &str = "kotlin/servers.jar";
+
+pub enum Launch {
+    /// a binary, relative to the repo root.
+    Native(&'static str),
+    /// a main class in KOTLIN_JAR.
+    Java(&'static str),
+}
+
+pub struct ServerSpec {
+    /// what the benchmarks call it, ex : "cpp_resume"
+    pub name: &'static str,
+    pub launch: Launch,
+    /// copies the payload before echoing the sequence number.
+    pub echoes_payload: bool,
+    /// exits when the client calls request_stop().
+    pub stops: bool,
+    /// maps the segment the way its header says, and takes a memfd from
+    /// SHM_FD. The C++ and zig servers only know the default layout on shm,
+    /// and exit on anything else, or open whatever shm's left by that name.
+    pub follows_header: bool,
+}
+
+const fn native(name: &'static str, path: &'static str) -> ServerSpec {
+    ServerSpec { name, launch: Launch::Native(path), echoes_payload: false, stops: false, follows_header: false }
+}
+
+/// the benchmarked Rust loops do what the others do. They echo the
+/// payload and stop w/ servers::CHECKED_FLAG, see checked_command().
+const fn rust(name: &'static str, path: &'static str) -> ServerSpec {
+    ServerSpec { name, launch: Launch::Native(path), echoes_payload: false, stops: false, follows_header: true }
+}
+
+/// they map the segment through libasync_bench.so, so they follow the header too.
+const fn kotlin(name: &'static str, class: &'static str) -> ServerSpec {
+    ServerSpec { name, launch: Launch::Java(class), echoes_payload: false, stops: false, follows_header: true }
+}
+
+pub static SERVERS: [ServerSpec; 16] = [
+    native("cpp_atomic", "cpp/out/atomicSpin"),
+    native("cpp_resume", "cpp/out/asyncResume"),
+    native("cpp_suspend", "cpp/out/asyncSuspend"),
+    native("cpp_callback", "cpp/out/atomicCallback"),
+    rust("rust_atomic", "target/release/atomic_spin_server"),
+    rust("rust_async_resume", "target/release/atomic_async_resume"),
+    rust("rust_async_suspend", "target/release/atomic_async_suspend"),
+    rust("rust_callback", "target/release/atomic_callback_server"),
+    native("zig_atomic", "zig/zig-out/bin/atomicSpin"),
+    native("zig_resume", "zig/zig-out/bin/atomicAsyncResume"),
+    native("zig_suspend", "zig/zig-out/bin/atomicAsyncSuspend"),
+    native("zig_callback", "zig/zig-out/bin/atomicCallback"),
+    kotlin("kotlin_atomic", "kotlin_servers.AtomicSpinKt"),
+    kotlin("kotlin_resume", "kotlin_servers.AsyncResumeKt"),
+    kotlin("kotlin_suspend", "kotlin_servers.AsyncSuspendKt"),
+    kotlin("kotlin_callback", "kotlin_servers.AtomicCallbackKt"),
+];
+
+pub fn find(name: &str) -> Option<&'static ServerSpec> {
+    SERVERS.iter().find(|s| s.name == name)
+}
+
+impl ServerSpec {
+    /// false if the binary or jar hasn't been built.
+    pub fn is_built(&self) -> bool {
+        match self.launch {
+            Launch::Native(path) => Path::new(path).exists(),
+            Launch::Java(_) => Path::new(KOTLIN_JAR).exists(),
+        }
+    }
+
+    /// start it pinned to `server_cpu`, the way the benchmarks do.
+    pub fn launch(&self, server_cpu: usize) -> Child {
+        match self.launch {
+            Launch::Native(path) => launch_local(path, server_cpu, &vec![]),
+            Launch::Java(class) => {
+                launch_local_java(KOTLIN_JAR, class, server_cpu, Some(JAVA_OPTS.as_ref()), &vec![])
+            }
+        }
+    }
+
+    /// false if the client maps the segment w/ `options` and this server
+    /// would refuse it, ex : the C++ ones w/ another layout.
+    pub fn supports(&self, options: &MapOptions) -> bool {
+        self.follows_header || !options.needs_header()
+    }
+
+    /// true for the Rust servers, which have a Checked mode.
+    pub fn has_checked_mode(&self) -> bool {
+        crate::servers::ALL_SERVERS.iter().any(|(name, _)| *name == self.name)
+    }
+
+    /// the bare command, w/ servers::CHECKED_FLAG if it has a Checked mode.
+    /// Then it echoes the payload and stops.
+    pub fn checked_command(&self) -> Option<Command> {
+        self.has_checked_mode().then(|| {
+            let mut cmd = self.command();
+            cmd.arg(crate::servers::CHECKED_FLAG);
+            cmd
+        })
+    }
+
+    /// the bare command, not pinned or niced.
+    pub fn command(&self) -> Command {
+        match self.launch {
+            Launch::Native(path) => Command::new(path),
+            Launch::Java(class) => {
+                let mut cmd = Command::new("java");
+                cmd.args(JAVA_OPTS.iter()).arg("-cp").arg(KOTLIN_JAR).arg(class);
+                cmd
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::aggregate::{load_run, split_bench_id};
+use crate::stats::Summary;
+use crate::CONFIG;
+use json::JsonValue;
+use std::path::{Path, PathBuf};
+
+// A JSON record of each benchmark's criterion run, so scripts and
+// dashboards don't have to know where criterion keeps its raw.csv.
+
+lazy_static! {
+    /// the machine the benchmarks are running on. From the
+    /// snapshot taken at the start, or a new one if there isn't one.
+    static ref FINGERPRINT: String = {
+        let env = std::fs::read_to_string(crate::environment::output_file())
+            .ok()
+            .and_then(|text| json::parse(&text).ok())
+            .unwrap_or_else(crate::environment::capture);
+        crate::environment::fingerprint(&env)
+    };
+}
+
+pub fn output_file(group_name: &str, bench_name: &str) -> PathBuf {
+    Path::new("target")
+        .join("results")
+        .join(group_name)
+        .join(format!("{}.json", bench_name))
+}
+
+/// where criterion put the group's results. The TSC groups have their own
+/// directory, the rest go where criterion puts them by default.
+pub fn criterion_dir(group_name: &str) -> PathBuf {
+    if CONFIG.uses_tsc(group_name) {
+        return PathBuf::from(crate::tsc::TSC_OUTPUT_DIR);
+    }
+    if let Ok(home) = std::env::var("CRITERION_HOME") {
+        return PathBuf::from(home);
+    }
+    let target = std::env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| "target".to_string());
+    Path::new(&target).join("criterion")
+}
+
+/// "rust_atomic_c4_s5" -> ("rust_atomic", Some((4, 5))), the names
+/// pair_bench_name() gives the sweep.
+pub fn split_cpu_pair(bench_name: &str) -> (&str, Option<(usize, usize)>) {
+    let pair = bench_name.rsplit_once("_c").and_then(|(name, cpus)| {
+        let (client, server) = cpus.split_once("_s")?;
+        Some((name, (client.parse().ok()?, server.parse().ok()?)))
+    });
+    match pair {
+        Some((name, cpus)) => (name, Some(cpus)),
+        None => (bench_name, None),
+    }
+}
+
+pub fn record(group_name: &str, bench_name: &str, unit: &str, samples: &[f64], fingerprint: &str) -> JsonValue {
+    let (name, cpus) = split_cpu_pair(bench_name);
+    let (language, variant) = split_bench_id(name);
+    let s = Summary::of(samples);
+    json::object! {
+        group: group_name,
+        name: bench_name,
+        language: language,
+        variant: variant,
+        client_cpu: cpus.map(|c| c.0),
+        server_cpu: cpus.map(|c| c.1),
+        unit: unit,
+        samples: s.count,
+        min: s.min,
+        median: s.median,
+        median_ci_low: s.median_ci_low,
+        median_ci_high: s.median_ci_high,
+        mean: s.mean,
+        p90: s.p90,
+        p99: s.p99,
+        max: s.max,
+        fingerprint: fingerprint,
+    }
+}
+
+/// read the run criterion just finished and write its record.
+/// Returns its summary, if criterion wrote one.
+pub fn report(group_name: &str, bench_name: &str) -> std::io::Result<Option<Summary>> {
+    let raw = criterion_dir(group_name)
+        .join(group_name)
+        .join(bench_name)
+        .join("new")
+        .join("raw.csv");
+    let Some(run) = load_run(&raw, 0) else {
+        eprintln!("no criterion results in {}, no JSON record for {}", raw.display(), bench_name);
+        return Ok(None);
+    };
+    let file = output_file(group_name, bench_name);
+    std::fs::create_dir_all(file.parent().unwrap())?;
+    std::fs::write(
+        &file,
+        record(group_name, bench_name, &run.unit, &run.samples, &FINGERPRINT).pretty(2),
+    )?;
+    Ok(Some(run.summary))
+}
@@ -0,0 +1,237 @@
+use crate::async_impl::{RuntimeState, SpinFuture, Task};
+use crate::atomic_spin::{Bench, Checked, MappedAtomics, ServerMode, Stoppable};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+// The Rust server loops. The server binaries just map the shared
+// memory and call one of these. They can also be run on a thread
+// in the same process as the client, see bench_utils::bench_in_process.
+// Each comes in a ServerMode. Bench is what's timed, and never returns.
+// Stoppable and Checked run until MappedAtomics::request_stop() is called.
+
+/// a Rust server loop.
+pub type ServerFn = fn(&MappedAtomics);
+
+/// every Rust server in mode M, and the name the benchmarks know it by.
+pub const fn servers<M: ServerMode>() -> [(&'static str, ServerFn); 4] {
+    [
+        ("rust_atomic", spin_server::<M>),
+        ("rust_async_resume", async_resume_server::<M>),
+        ("rust_async_suspend", async_suspend_server::<M>),
+        ("rust_callback", callback_server::<M>),
+    ]
+}
+
+/// what the server binaries run, and the benchmarks time.
+pub static ALL_SERVERS: [(&str, ServerFn); 4] = servers::<Bench>();
+
+/// for a server on a thread, in the in_process group.
+pub static STOPPABLE_SERVERS: [(&str, ServerFn); 4] = servers::<Stoppable>();
+
+/// echo the payload too, for the tests.
+pub static CHECKED_SERVERS: [(&str, ServerFn); 4] = servers::<Checked>();
+
+/// the server binaries run the Checked loops w/ this flag.
+pub static CHECKED_FLAG: &str = "--checked";
+
+/// run server `name` in the mode the command line asks for.
+pub fn run_from_args(name: &str, atomics: &MappedAtomics) {
+    let table = if std::env::args().any(|a| a == CHECKED_FLAG) { &CHECKED_SERVERS } else { &ALL_SERVERS };
+    let (_, server) = table.iter().find(|(n, _)| *n == name).unwrap_or_else(|| panic!("no Rust server {}", name));
+    server(atomics);
+}
+
+/// the plain spin loop.
+pub fn spin_server<M: ServerMode>(atomics: &MappedAtomics) {
+    atomics.do_server_loop::<M>();
+}
+
+struct Worker<'a, M> {
+    atomcis: &'a MappedAtomics,
+    some_state: u64,
+    mode: PhantomData<M>,
+}
+
+impl<M: ServerMode> Worker<'_, M> {
+    fn do_work(&mut self, value: u64) {
+        self.atomcis.server_echo::<M>(value);
+        self.some_state = value;
+    }
+}
+
+type Callback<'a, T> = &'a dyn Fn(&mut T, u64);
+
+struct EventLoop<'a, T> {
+    context: T,
+    callback: Option<Callback<'a, T>>,
+    atomics: &'a MappedAtomics,
+}
+
+impl<'a, T> EventLoop<'a, T> {
+    fn set_callback(&mut self, cb: &'a impl Fn(&mut T, u64)) {
+        self.callback = Some(cb);
+    }
+
+    fn run<M: ServerMode>(&mut self) {
+        let mut last_value: u64 = 0;
+        loop {
+            last_value = self.atomics.server_spin_until_change::<M>(last_value);
+            if let Some(cb) = &self.callback {
+                cb(&mut self.context, last_value);
+            }
+            if self.atomics.should_stop::<M>() {
+                return;
+            }
+        }
+    }
+}
+
+/// the spin loop calls a callback, which writes the value.
+pub fn callback_server<M: ServerMode>(atomics: &MappedAtomics) {
+    let wk = Worker::<M> {
+        atomcis: atomics,
+        some_state: 0,
+        mode: PhantomData,
+    };
+
+    let mut ev = EventLoop {
+        context: wk,
+        callback: None,
+        atomics,
+    };
+
+    ev.set_callback(&Worker::do_work);
+
+    ev.run::<M>();
+}
+
+/// the main server loop. Async this time.
+/// it suspends until the client memory has changed.
+/// it then copies the client memory to the server memory.
+async fn async_loop_resume<M: ServerMode>(state: Rc<RefCell<RuntimeState<'_>>>) {
+    let mut fut = SpinFuture::new(Rc::clone(&state));
+    let mut value: u64 = 0;
+
+    loop {
+        // wait fot the client memory to change.
+        // this is a suspending call.
+        value = fut.suspend_to_eventloop(value).await;
+
+        // write the new value to the server memory.
+        let atomics = state.borrow().atomics;
+        atomics.server_echo::<M>(value);
+
+        if atomics.should_stop::<M>() {
+            return;
+        }
+    }
+}
+
+/// the event loop spins, and resumes the async code
+/// to write the value. This times resume.
+pub fn async_resume_server<M: ServerMode>(atomics: &MappedAtomics) {
+    let state = Rc::new(RefCell::new(RuntimeState::new(atomics)));
+
+    let spin_code = async_loop_resume::<M>(Rc::clone(&state));
+
+    let mut task = Task::init(spin_code);
+    task.advance();
+
+    event_loop_resume::<M>(Rc::clone(&state));
+}
+
+// this loop assumes it's starting state is that
+// the async client loop is already running, and it's
+// already suspended waiting for the client memory to change.
+fn event_loop_resume<M: ServerMode>(state: Rc<RefCell<RuntimeState<'_>>>) {
+    loop {
+        // can't keep the mut barrow outstanding when we call wake()
+        let wk = {
+            let mut s = state.borrow_mut();
+
+            // get the last client value. We'll spin until
+            // the memory changes from this value.
+            let last = s.to_event_loop.take().unwrap();
+
+            // this is the spin loop.
+            let next = s.atomics.server_spin_until_change::<M>(last);
+
+            // record to new value for the Future to pick up
+            // on next poll
+            s.to_async_loop = Some(next);
+
+            // poll the future.
+            s.waker.take()
+        };
+        assert!(wk.is_some());
+        if let Some(w) = wk {
+            // when we return from this, the async code will be locked
+            // on the next iteration.
+            w.wake();
+        }
+        if state.borrow().atomics.should_stop::<M>() {
+            return;
+        }
+    }
+}
+
+// this other way around. we spin in the async code
+// and write the value in the event_loop. This way
+// we'll be timing suspend instead of resume.
+async fn async_loop_suspend<M: ServerMode>(state: Rc<RefCell<RuntimeState<'_>>>) {
+    let mut fut = SpinFuture::new(Rc::clone(&state));
+    let mut value: u64 = 0;
+
+    loop {
+        // wait for the memory to change.
+        let atomics = state.borrow().atomics;
+        value = atomics.server_spin_until_change::<M>(value);
+
+        // tell the event loop to write the value.
+        fut.suspend_to_eventloop(value).await;
+
+        if atomics.should_stop::<M>() {
+            return;
+        }
+    }
+}
+
+/// the async code spins, and suspends to the event
+/// loop to write the value. This times suspend.
+pub fn async_suspend_server<M: ServerMode>(atomics: &MappedAtomics) {
+    let state = Rc::new(RefCell::new(RuntimeState::new(atomics)));
+
+    let spin_code = async_loop_suspend::<M>(Rc::clone(&state));
+
+    // start the async code running.
+    // I don't think I need the return value for anything.
+    let mut task = Task::init(spin_code);
+    task.advance();
+
+    event_loop_suspend::<M>(Rc::clone(&state));
+}
+
+fn event_loop_suspend<M: ServerMode>(state: Rc<RefCell<RuntimeState<'_>>>) {
+    loop {
+        let wk = {
+            let mut s = state.borrow_mut();
+            // write the value.
+            let v = s.to_event_loop.unwrap();
+            s.atomics.server_echo::<M>(v);
+            s.to_async_loop = Some(v);
+
+            s.waker.take()
+        };
+
+        assert!(wk.is_some());
+        if let Some(w) = wk {
+            // when we return from this, the async code will be locked
+            // on the next iteration.
+            w.wake();
+        }
+        if state.borrow().atomics.should_stop::<M>() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,260 @@
+// The few statistics the result tools need, so they don't need R.
+
+/// the `p`th percentile (0 to 100) of sorted values, interpolated
+/// between the two nearest. The same as R's default quantile().
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+pub fn median(sorted: &[f64]) -> f64 {
+    percentile(sorted, 50.0)
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+pub fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+/// 95% confidence interval of the median, from the order statistics.
+/// Doesn't assume anything about the distribution.
+pub fn median_ci(sorted: &[f64]) -> (f64, f64) {
+    let n = sorted.len() as f64;
+    if sorted.is_empty() {
+        return (f64::NAN, f64::NAN);
+    }
+    let half = 1.96 * n.sqrt() / 2.0;
+    let lo = ((n / 2.0 - half).floor().max(0.0)) as usize;
+    let hi = ((n / 2.0 + half).ceil() as usize).min(sorted.len() - 1);
+    (sorted[lo], sorted[hi])
+}
+
+/// gaussian kernel density of `sorted` at each point of `grid`,
+/// w/ Silverman's rule of thumb for the bandwidth. For violin plots.
+pub fn kde(sorted: &[f64], grid: &[f64]) -> Vec<f64> {
+    let n = sorted.len() as f64;
+    let m = mean(sorted);
+    let sd = (sorted.iter().map(|v| (v - m) * (v - m)).sum::<f64>() / (n - 1.0).max(1.0)).sqrt();
+    let iqr = percentile(sorted, 75.0) - percentile(sorted, 25.0);
+    let spread = if iqr > 0.0 { sd.min(iqr / 1.34) } else { sd };
+    let bandwidth = (0.9 * spread * n.powf(-0.2)).max(f64::EPSILON);
+    let norm = 1.0 / (n * bandwidth * (2.0 * std::f64::consts::PI).sqrt());
+    grid.iter()
+        .map(|x| {
+            sorted
+                .iter()
+                .map(|v| {
+                    let u = (x - v) / bandwidth;
+                    (-0.5 * u * u).exp()
+                })
+                .sum::<f64>()
+                * norm
+        })
+        .collect()
+}
+
+/// a summary of one set of samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub median_ci_low: f64,
+    pub median_ci_high: f64,
+    /// R's median_hilow(), the whiskers on the README graphs.
+    pub p2_5: f64,
+    pub p97_5: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn of(values: &[f64]) -> Summary {
+        let sorted = sorted(values);
+        let (median_ci_low, median_ci_high) = median_ci(&sorted);
+        Summary {
+            count: sorted.len(),
+            min: sorted.first().copied().unwrap_or(f64::NAN),
+            mean: mean(&sorted),
+            median: median(&sorted),
+            median_ci_low,
+            median_ci_high,
+            p2_5: percentile(&sorted, 2.5),
+            p97_5: percentile(&sorted, 97.5),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+            max: sorted.last().copied().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// the names to_values() comes out in, for csv headers.
+    pub const FIELDS: [&'static str; 11] = [
+        "samples",
+        "min",
+        "mean",
+        "median",
+        "median_ci_low",
+        "median_ci_high",
+        "p2_5",
+        "p97_5",
+        "p90",
+        "p99",
+        "max",
+    ];
+
+    pub fn to_values(&self) -> [f64; 11] {
+        [
+            self.count as f64,
+            self.min,
+            self.mean,
+            self.median,
+            self.median_ci_low,
+            self.median_ci_high,
+            self.p2_5,
+            self.p97_5,
+            self.p90,
+            self.p99,
+            self.max,
+        ]
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        let mut out = json::JsonValue::new_object();
+        for (name, value) in Summary::FIELDS.iter().zip(self.to_values()) {
+            out[*name] = value.into();
+        }
+        out
+    }
+}
+
+/// ranks of `values` from 1, ties get the average of their ranks.
+/// Also returns sum(t^3 - t) over the tied groups, for the tie correction.
+fn ranks(values: &[f64]) -> (Vec<f64>, f64) {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut ties = 0.0;
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && values[order[j + 1]] == values[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for idx in order[i..=j].iter() {
+            ranks[*idx] = rank;
+        }
+        let t = (j - i + 1) as f64;
+        ties += t * t * t - t;
+        i = j + 1;
+    }
+    (ranks, ties)
+}
+
+/// the standard normal CDF. Abramowitz and Stegun 7.1.26, good to ~1e-7.
+pub fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+    if z >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Mann-Whitney U test of `a` against `b`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MannWhitney {
+    /// pairs where a is bigger, ties count half.
+    pub u: f64,
+    /// two sided, from the normal approximation w/ tie and
+    /// continuity corrections. Fine for criterion's 100 samples.
+    pub p: f64,
+    /// Cliff's delta, -1 to 1. How much more often a is bigger than b
+    /// than smaller. The effect size that goes w/ U.
+    pub delta: f64,
+}
+
+pub fn mann_whitney(a: &[f64], b: &[f64]) -> MannWhitney {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    if a.is_empty() || b.is_empty() {
+        return MannWhitney {
+            u: f64::NAN,
+            p: f64::NAN,
+            delta: f64::NAN,
+        };
+    }
+    let both: Vec<f64> = a.iter().chain(b.iter()).copied().collect();
+    let (ranks, ties) = ranks(&both);
+    let n = n1 + n2;
+    let u = ranks[..a.len()].iter().sum::<f64>() - n1 * (n1 + 1.0) / 2.0;
+    let mean_u = n1 * n2 / 2.0;
+    let sd = (n1 * n2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)))).sqrt();
+    let p = if sd > 0.0 {
+        let z = ((u - mean_u).abs() - 0.5).max(0.0) / sd;
+        (2.0 * (1.0 - normal_cdf(z))).min(1.0)
+    } else {
+        // every value the same
+        1.0
+    };
+    MannWhitney {
+        u,
+        p,
+        delta: 2.0 * u / (n1 * n2) - 1.0,
+    }
+}
+
+/// "negligible", "small", "medium" or "large", the usual
+/// thresholds for Cliff's delta (Romano et al.)
+pub fn effect_size(delta: f64) -> &'static str {
+    match delta.abs() {
+        d if d < 0.147 => "negligible",
+        d if d < 0.33 => "small",
+        d if d < 0.474 => "medium",
+        _ => "large",
+    }
+}
+
+/// percent change of the `p`th percentile from `base` to `new`, and its
+/// 95% bootstrap confidence interval from `resamples` resamples of each.
+/// Seeded, so the same results give the same interval.
+pub fn bootstrap_change(base: &[f64], new: &[f64], p: f64, resamples: usize, seed: u64) -> (f64, f64, f64) {
+    use rand::{Rng, SeedableRng};
+    let change = |b: f64, n: f64| (n - b) / b * 100.0;
+    let estimate = change(percentile(&sorted(base), p), percentile(&sorted(new), p));
+    if base.is_empty() || new.is_empty() || resamples == 0 {
+        return (estimate, f64::NAN, f64::NAN);
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut resample = |values: &[f64], buf: &mut Vec<f64>| {
+        buf.clear();
+        buf.extend((0..values.len()).map(|_| values[rng.gen_range(0..values.len())]));
+        buf.sort_by(f64::total_cmp);
+        percentile(buf, p)
+    };
+    let (mut b_buf, mut n_buf) = (Vec::with_capacity(base.len()), Vec::with_capacity(new.len()));
+    let changes: Vec<f64> = (0..resamples)
+        .map(|_| {
+            let b = resample(base, &mut b_buf);
+            let n = resample(new, &mut n_buf);
+            change(b, n)
+        })
+        .collect();
+    let changes = sorted(&changes);
+    (estimate, percentile(&changes, 2.5), percentile(&changes, 97.5))
+}
@@ -0,0 +1,108 @@
+use std::fmt::Write;
+
+/// Just enough SVG to draw simple plots, w/o needing
+/// anything outside of Rust installed.
+pub struct Svg {
+    pub width: f64,
+    pub height: f64,
+    body: String,
+}
+
+impl Svg {
+    pub fn new(width: f64, height: f64) -> Svg {
+        let mut svg = Svg {
+            width,
+            height,
+            body: String::new(),
+        };
+        svg.rect(0.0, 0.0, width, height, "white", None);
+        svg
+    }
+
+    pub fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, color: &str, width: f64) {
+        let _ = writeln!(
+            self.body,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{}"/>"#,
+            x1, y1, x2, y2, color, width
+        );
+    }
+
+    pub fn polyline(&mut self, points: &[(f64, f64)], color: &str, width: f64) {
+        let pts: Vec<String> = points.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+        let _ = writeln!(
+            self.body,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+            pts.join(" "),
+            color,
+            width
+        );
+    }
+
+    /// a closed shape, ex : the outline of a violin.
+    pub fn polygon(&mut self, points: &[(f64, f64)], fill: &str, opacity: f64, stroke: &str) {
+        let pts: Vec<String> = points.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+        let _ = writeln!(
+            self.body,
+            r#"<polygon points="{}" fill="{}" fill-opacity="{}" stroke="{}"/>"#,
+            pts.join(" "),
+            fill,
+            opacity,
+            stroke
+        );
+    }
+
+    pub fn rect(&mut self, x: f64, y: f64, w: f64, h: f64, fill: &str, stroke: Option<&str>) {
+        let _ = writeln!(
+            self.body,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="{}"/>"#,
+            x,
+            y,
+            w,
+            h,
+            fill,
+            stroke.unwrap_or("none")
+        );
+    }
+
+    /// anchor is "start", "middle" or "end"
+    pub fn text(&mut self, x: f64, y: f64, text: &str, size: f64, anchor: &str) {
+        let _ = writeln!(
+            self.body,
+            r#"<text x="{:.1}" y="{:.1}" font-family="sans-serif" font-size="{}" text-anchor="{}">{}</text>"#,
+            x,
+            y,
+            size,
+            anchor,
+            escape(text)
+        );
+    }
+
+    /// text turned 90 degrees, for y axis labels.
+    pub fn vertical_text(&mut self, x: f64, y: f64, text: &str, size: f64) {
+        let _ = writeln!(
+            self.body,
+            r#"<text x="{:.1}" y="{:.1}" font-family="sans-serif" font-size="{}" text-anchor="middle" transform="rotate(-90 {:.1} {:.1})">{}</text>"#,
+            x,
+            y,
+            size,
+            x,
+            y,
+            escape(text)
+        );
+    }
+
+    pub fn finish(&self) -> String {
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n{}</svg>\n",
+            self.width, self.height, self.width, self.height, self.body
+        )
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::write(path, self.finish())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
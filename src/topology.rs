@@ -0,0 +1,118 @@
+use crate::config::parse_cpu_list;
+
+// What the kernel says about how the CPUs are laid out.
+// Everything here reads /sys/devices/system/cpu, and returns
+// empty lists if a file isn't there (VMs, containers, etc.)
+
+static SYS_CPU: &str = "/sys/devices/system/cpu";
+
+pub(crate) fn read_sys(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_cpu_list(path: &str) -> Vec<usize> {
+    read_sys(path).map(|l| parse_cpu_list(&l)).unwrap_or_default()
+}
+
+/// the CPUs that are currently online.
+pub fn online_cpus() -> Vec<usize> {
+    read_cpu_list(&format!("{}/online", SYS_CPU))
+}
+
+/// the CPUs taken away from the scheduler w/ isolcpus.
+pub fn isolated_cpus() -> Vec<usize> {
+    read_cpu_list(&format!("{}/isolated", SYS_CPU))
+}
+
+/// the CPUs running w/o the scheduler tick, nohz_full.
+pub fn nohz_full_cpus() -> Vec<usize> {
+    read_cpu_list(&format!("{}/nohz_full", SYS_CPU))
+}
+
+/// the cpufreq governor for `cpu`. None if there's no cpufreq.
+pub fn governor(cpu: usize) -> Option<String> {
+    read_sys(&format!("{}/cpu{}/cpufreq/scaling_governor", SYS_CPU, cpu))
+}
+
+/// "on", "off", "forceoff", "notsupported"...
+pub fn smt_control() -> Option<String> {
+    read_sys(&format!("{}/smt/control", SYS_CPU))
+}
+
+/// true if any core is running more than one hyper-thread.
+pub fn smt_active() -> Option<bool> {
+    read_sys(&format!("{}/smt/active", SYS_CPU)).map(|a| a == "1")
+}
+
+/// the hyper-threads that share a core with `cpu`, including `cpu`.
+pub fn smt_siblings(cpu: usize) -> Vec<usize> {
+    read_cpu_list(&format!("{}/cpu{}/topology/thread_siblings_list", SYS_CPU, cpu))
+}
+
+/// the CPUs that share a L3 with `cpu`, including `cpu`.
+pub fn l3_siblings(cpu: usize) -> Vec<usize> {
+    let mut idx = 0;
+    while let Some(level) = read_sys(&format!("{}/cpu{}/cache/index{}/level", SYS_CPU, cpu, idx)) {
+        if level == "3" {
+            return read_cpu_list(&format!(
+                "{}/cpu{}/cache/index{}/shared_cpu_list",
+                SYS_CPU, cpu, idx
+            ));
+        }
+        idx += 1;
+    }
+    Vec::new()
+}
+
+/// how close two CPUs are to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuRelation {
+    Same,
+    SmtSibling,
+    SharedL3,
+    Other,
+}
+
+impl CpuRelation {
+    pub fn of(a: usize, b: usize) -> CpuRelation {
+        if a == b {
+            CpuRelation::Same
+        } else if smt_siblings(a).contains(&b) {
+            CpuRelation::SmtSibling
+        } else if l3_siblings(a).contains(&b) {
+            CpuRelation::SharedL3
+        } else {
+            CpuRelation::Other
+        }
+    }
+
+    /// a short tag for tables.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            CpuRelation::Same => "self",
+            CpuRelation::SmtSibling => "smt",
+            CpuRelation::SharedL3 => "l3",
+            CpuRelation::Other => "-",
+        }
+    }
+}
+
+static SYS_NODE: &str = "/sys/devices/system/node";
+
+/// the NUMA nodes that are online. Empty w/o NUMA support.
+pub fn numa_nodes() -> Vec<usize> {
+    read_cpu_list(&format!("{}/online", SYS_NODE))
+}
+
+/// the CPUs on NUMA node `node`.
+pub fn node_cpus(node: usize) -> Vec<usize> {
+    read_cpu_list(&format!("{}/node{}/cpulist", SYS_NODE, node))
+}
+
+/// the NUMA node `cpu` is on. There's a nodeN link in its directory.
+pub fn cpu_node(cpu: usize) -> Option<usize> {
+    std::fs::read_dir(format!("{}/cpu{}", SYS_CPU, cpu))
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find_map(|e| e.file_name().to_str()?.strip_prefix("node")?.parse().ok())
+}
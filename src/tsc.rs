@@ -0,0 +1,231 @@
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::Throughput;
+use std::time::{Duration, Instant};
+
+// Timing w/ the CPU's time stamp counter. Much cheaper than
+// Instant::now(), and fine grained enough to time a single
+// round trip. Only x86_64 has one. Everywhere else falls back
+// to Instant, so ticks are ns.
+
+/// where criterion puts the results of the TSC groups, so
+/// it doesn't compare them w/ wall clock ones.
+pub static TSC_OUTPUT_DIR: &str = "target/criterion-tsc";
+
+/// how long to spin for when working out the TSC frequency.
+static CALIBRATE_TIME: Duration = Duration::from_millis(200);
+
+lazy_static! {
+    static ref TICKS_PER_NS: f64 = calibrate();
+}
+
+/// read the TSC before the code being timed. The fences keep
+/// earlier instructions from finishing after the read, and
+/// later ones from starting before it.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn start() -> u64 {
+    use std::arch::x86_64::{_mm_lfence, _rdtsc};
+    unsafe {
+        _mm_lfence();
+        let t = _rdtsc();
+        _mm_lfence();
+        t
+    }
+}
+
+/// read the TSC after the code being timed. rdtscp waits for
+/// everything before it, the fence stops later code starting early.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn stop() -> u64 {
+    use std::arch::x86_64::{__rdtscp, _mm_lfence};
+    unsafe {
+        let mut aux = 0u32;
+        let t = __rdtscp(&mut aux);
+        _mm_lfence();
+        t
+    }
+}
+
+/// true if the TSC ticks at the same rate regardless of
+/// frequency scaling and sleep states. If not, ticks aren't time.
+#[cfg(target_arch = "x86_64")]
+pub fn invariant_tsc() -> bool {
+    use std::arch::x86_64::__cpuid;
+    if __cpuid(0x8000_0000).eax < 0x8000_0007 {
+        return false;
+    }
+    (__cpuid(0x8000_0007).edx & (1 << 8)) != 0
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+lazy_static! {
+    static ref EPOCH: Instant = Instant::now();
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+#[inline(always)]
+pub fn start() -> u64 {
+    EPOCH.elapsed().as_nanos() as u64
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+#[inline(always)]
+pub fn stop() -> u64 {
+    EPOCH.elapsed().as_nanos() as u64
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn invariant_tsc() -> bool {
+    false
+}
+
+/// spin for a while, and see how many ticks go by.
+fn calibrate() -> f64 {
+    let wall_start = Instant::now();
+    let tsc_start = start();
+    while wall_start.elapsed() < CALIBRATE_TIME {
+        core::hint::spin_loop();
+    }
+    let tsc_end = stop();
+    let wall = wall_start.elapsed();
+    tsc_end.wrapping_sub(tsc_start) as f64 / wall.as_nanos() as f64
+}
+
+/// TSC ticks per ns. Worked out the first time it's asked for.
+pub fn ticks_per_ns() -> f64 {
+    *TICKS_PER_NS
+}
+
+pub fn ticks_to_ns(ticks: u64) -> f64 {
+    ticks as f64 / ticks_per_ns()
+}
+
+/// what the criterion results from RdtscMeasurement are in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TscUnit {
+    /// TSC reference ticks. Not core cycles, the TSC ticks at a fixed
+    /// rate, the core doesn't under turbo or frequency scaling.
+    TscTicks,
+    Nanos,
+}
+
+/// A criterion Measurement that reads the TSC instead of
+/// Instant::now(). Cheaper to read, so less overhead in
+/// each batch. Use it for a group w/ Criterion::with_measurement().
+pub struct RdtscMeasurement {
+    unit: TscUnit,
+}
+
+impl RdtscMeasurement {
+    /// warns if the TSC isn't invariant, and calibrates
+    /// it now, rather than in the middle of a benchmark.
+    pub fn new(unit: TscUnit) -> RdtscMeasurement {
+        if !invariant_tsc() {
+            eprintln!("warning : the TSC isn't invariant, TSC measurements may be off");
+        }
+        ticks_per_ns();
+        RdtscMeasurement { unit }
+    }
+}
+
+impl Measurement for RdtscMeasurement {
+    type Intermediate = u64;
+    type Value = u64;
+
+    fn start(&self) -> Self::Intermediate {
+        start()
+    }
+
+    fn end(&self, i: Self::Intermediate) -> Self::Value {
+        stop().wrapping_sub(i)
+    }
+
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
+    }
+
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        match self.unit {
+            TscUnit::TscTicks => *value as f64,
+            TscUnit::Nanos => ticks_to_ns(*value),
+        }
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        match self.unit {
+            TscUnit::TscTicks => &TicksFormatter,
+            TscUnit::Nanos => &NanosFormatter,
+        }
+    }
+}
+
+struct TicksFormatter;
+
+impl ValueFormatter for TicksFormatter {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = if typical_value < 1e3 {
+            (1.0, "ticks")
+        } else if typical_value < 1e6 {
+            (1e-3, "Kticks")
+        } else {
+            (1e-6, "Mticks")
+        };
+        for v in values {
+            *v *= factor;
+        }
+        unit
+    }
+
+    fn scale_throughputs(&self, _typical_value: f64, throughput: &Throughput, values: &mut [f64]) -> &'static str {
+        let (count, unit) = match throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (*n as f64, "B/tick"),
+            Throughput::Elements(n) => (*n as f64, "elem/tick"),
+        };
+        for v in values {
+            *v = count / *v;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "ticks"
+    }
+}
+
+struct NanosFormatter;
+
+impl ValueFormatter for NanosFormatter {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = if typical_value < 1e3 {
+            (1.0, "ns")
+        } else if typical_value < 1e6 {
+            (1e-3, "µs")
+        } else {
+            (1e-6, "ms")
+        };
+        for v in values {
+            *v *= factor;
+        }
+        unit
+    }
+
+    fn scale_throughputs(&self, _typical_value: f64, throughput: &Throughput, values: &mut [f64]) -> &'static str {
+        let (count, unit) = match throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (*n as f64, "B/s"),
+            Throughput::Elements(n) => (*n as f64, "elem/s"),
+        };
+        for v in values {
+            *v = count * 1e9 / *v;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "ns"
+    }
+}
@@ -0,0 +1,157 @@
+use crate::atomic_spin::MappedAtomics;
+use crate::stats;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Waiting for the server to be warmed up before criterion starts. The
+// JVM servers run interpreted, then C1 compiled, then C2 compiled, and
+// criterion's fixed warm up time doesn't know when that's done. So keep
+// pinging the server until the median round trip stops moving, and if
+// the JVM is telling us what it compiles, until it stops compiling too.
+
+/// this many window medians in a row have to agree.
+pub static SETTLED_WINDOWS: usize = 3;
+
+/// the JIT's activity, from a JVM run w/ -XX:+PrintCompilation.
+#[derive(Debug, Clone, Default)]
+pub struct CompilationLog {
+    events: Arc<AtomicUsize>,
+    last: Arc<Mutex<String>>,
+}
+
+/// a line of -XX:+PrintCompilation output. ex :
+///   "    123   45       3       java.lang.String::hashCode (55 bytes)"
+///   "    130   46 %     4       Foo::run @ 12 (80 bytes)   made not entrant"
+/// The ms since the JVM started, then the compile id.
+pub fn is_compilation_line(line: &str) -> bool {
+    let mut fields = line.split_whitespace();
+    let is_number = |f: Option<&str>| f.is_some_and(|f| f.parse::<u64>().is_ok());
+    is_number(fields.next()) && is_number(fields.next())
+}
+
+impl CompilationLog {
+    /// read the server's stdout on a thread, counting compilations. Everything
+    /// else is passed on to our stdout, like it would be w/o the pipe.
+    pub fn watch<R: Read + Send + 'static>(out: R) -> CompilationLog {
+        let log = CompilationLog::default();
+        let (events, last) = (log.events.clone(), log.last.clone());
+        std::thread::spawn(move || {
+            for line in BufReader::new(out).lines().map_while(Result::ok) {
+                if is_compilation_line(&line) {
+                    events.fetch_add(1, Ordering::Relaxed);
+                    *last.lock().unwrap() = line.trim().to_string();
+                } else {
+                    println!("{}", line);
+                }
+            }
+        });
+        log
+    }
+
+    pub fn events(&self) -> usize {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// the most recent compilation.
+    pub fn last(&self) -> String {
+        self.last.lock().unwrap().clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warmup {
+    pub round_trips: u64,
+    pub elapsed: Duration,
+    /// ns, the median of each window.
+    pub medians: Vec<f64>,
+    /// false if it gave up.
+    pub stable: bool,
+    /// compilations seen, if we were watching.
+    pub compilations: Option<usize>,
+}
+
+/// the last SETTLED_WINDOWS medians are all within `tolerance_pct` of the smallest.
+pub fn settled(medians: &[f64], tolerance_pct: f64) -> bool {
+    if medians.len() < SETTLED_WINDOWS {
+        return false;
+    }
+    let last = &medians[medians.len() - SETTLED_WINDOWS..];
+    let lo = last.iter().cloned().fold(f64::MAX, f64::min);
+    let hi = last.iter().cloned().fold(f64::MIN, f64::max);
+    (hi - lo) / lo * 100.0 <= tolerance_pct
+}
+
+/// ping the server a window at a time until the medians settle, and the
+/// JIT (if there's a log) hasn't compiled anything over those windows.
+/// Gives up after `max`.
+pub fn wait_until_stable(
+    client: &MappedAtomics,
+    window: usize,
+    tolerance_pct: f64,
+    max: Duration,
+    log: Option<&CompilationLog>,
+) -> Warmup {
+    let start = Instant::now();
+    let mut medians = Vec::new();
+    // compilations so far, at the start and after each window.
+    let mut compiled = vec![log.map(|l| l.events())];
+    let mut times = Vec::with_capacity(window);
+    let mut round_trips = 0u64;
+    let stable = loop {
+        times.clear();
+        for _ in 0..window.max(1) {
+            round_trips += 1;
+            let sent = Instant::now();
+            client.client_run_once(round_trips);
+            times.push(sent.elapsed().as_nanos() as f64);
+        }
+        times.sort_by(f64::total_cmp);
+        medians.push(stats::median(&times));
+        compiled.push(log.map(|l| l.events()));
+
+        let jit_quiet = compiled.len() > SETTLED_WINDOWS
+            && compiled[compiled.len() - 1] == compiled[compiled.len() - 1 - SETTLED_WINDOWS];
+        if settled(&medians, tolerance_pct) && jit_quiet {
+            break true;
+        }
+        if start.elapsed() >= max {
+            break false;
+        }
+    };
+    Warmup {
+        round_trips,
+        elapsed: start.elapsed(),
+        medians,
+        stable,
+        compilations: log.map(|l| l.events()),
+    }
+}
+
+/// print how the warm up went.
+pub fn report(bench_name: &str, warmup: &Warmup, log: Option<&CompilationLog>) {
+    let median = warmup.medians.last().copied().unwrap_or(f64::NAN);
+    if warmup.stable {
+        println!(
+            "{} : warmed up, median round trip settled at {:.0} ns after {} round trips ({:.1}s)",
+            bench_name,
+            median,
+            warmup.round_trips,
+            warmup.elapsed.as_secs_f64()
+        );
+    } else {
+        println!(
+            "{} : warning, round trips hadn't settled after {:.1}s (last medians {:?} ns)",
+            bench_name,
+            warmup.elapsed.as_secs_f64(),
+            warmup.medians[warmup.medians.len().saturating_sub(SETTLED_WINDOWS)..]
+                .iter()
+                .map(|m| m.round() as u64)
+                .collect::<Vec<_>>()
+        );
+    }
+    if let (Some(count), Some(log)) = (warmup.compilations, log) {
+        println!("{} : {} JIT compilations so far, last : {}", bench_name, count, log.last());
+    }
+}
@@ -0,0 +1,39 @@
+use async_bench::atomic_spin::{Checked, MappedAtomics};
+use async_bench::servers::spin_server;
+use std::time::Duration;
+
+// Picking how long to measure for from the noise.
+
+#[test]
+fn adaptive_pilot_times_round_trips() {
+    use async_bench::adaptive::{measurement_time, pilot_samples};
+    use async_bench::config::Timing;
+    let atomics = MappedAtomics::new_anonymous();
+    let mut timing = Timing {
+        measurement: Duration::from_millis(50),
+        max_measurement: Duration::from_millis(100),
+        sample_size: 10,
+        ..Timing::default()
+    };
+    let (samples, fixed, wide, (narrow, narrow_took)) = std::thread::scope(|scope| {
+        scope.spawn(|| spin_server::<Checked>(&atomics));
+        let samples = pilot_samples(&atomics, 10, Duration::from_millis(50));
+        let fixed = measurement_time(&atomics, &timing, "fixed");
+        timing.target_ci_pct = Some(1e9);
+        let wide = measurement_time(&atomics, &timing, "wide");
+        // can't be met, so it stops at the limit.
+        timing.target_ci_pct = Some(1e-9);
+        let start = std::time::Instant::now();
+        let narrow = (measurement_time(&atomics, &timing, "narrow"), start.elapsed());
+        atomics.request_stop();
+        (samples, fixed, wide, narrow)
+    });
+    atomics.close();
+    assert_eq!(samples.len(), 10);
+    assert!(samples[0] > 0.0 && samples.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(fixed, Duration::from_millis(50));
+    assert_eq!(wide, Duration::from_millis(50));
+    assert_eq!(narrow, Duration::from_millis(100));
+    // after checking it, not just predicting it.
+    assert!(narrow_took >= Duration::from_millis(100), "{:?}", narrow_took);
+}
@@ -0,0 +1,65 @@
+use async_bench::atomic_spin::MappedAtomics;
+use async_bench::backing::{self, Backing, MapOptions, Prefault};
+use async_bench::layout::LayoutMode;
+use async_bench::numa::{self, NumaPolicy};
+use async_bench::topology;
+use std::time::Duration;
+
+// Where the segment ends up, and what touching it costs.
+// That it's echoed in each of these is in in_process.rs.
+
+/// true if mlock()ing a few pages won't hit ulimit -l.
+fn can_lock() -> bool {
+    let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) == 0 && limit.rlim_cur >= 1 << 20 }
+}
+
+#[test]
+fn every_layout_puts_the_server_at_its_offset() {
+    for layout in LayoutMode::ALL {
+        let atomics = MappedAtomics::new_anonymous_with(MapOptions { layout, ..Default::default() });
+        assert_eq!(atomics.layout(), layout);
+        let gap = atomics.server_write as *const _ as isize - atomics.client_write as *const _ as isize;
+        if !layout.separate_mapping() {
+            assert_eq!(gap as usize, layout.server_offset(page_size::get()), "{:?}", layout);
+        }
+        atomics.close();
+    }
+}
+
+#[test]
+fn first_touch_is_timed_for_every_backing() {
+    let mut prefaults = vec![Prefault::None, Prefault::Populate];
+    if can_lock() {
+        prefaults.push(Prefault::Lock);
+    }
+    let mut backings = vec![Backing::Shm, Backing::Memfd];
+    if backing::huge_pages_free() >= 4 {
+        backings.push(Backing::HugePages);
+    }
+    for backing in backings {
+        for prefault in prefaults.iter().copied() {
+            let touch = backing::first_touch(backing, prefault);
+            assert!(touch.page_size >= page_size::get(), "{:?}", touch);
+            assert!(touch.first_write.iter().all(|d| *d < Duration::from_secs(1)), "{:?}", touch);
+        }
+    }
+}
+
+#[test]
+fn numa_policy_places_the_segment() {
+    let cpu = unsafe { libc::sched_getcpu() } as usize;
+    let node = topology::cpu_node(cpu);
+    for numa in [NumaPolicy::Client, NumaPolicy::Server, NumaPolicy::Node(node.unwrap_or(0))] {
+        let options = MapOptions { numa: numa.resolve(cpu, cpu), ..Default::default() };
+        let atomics = MappedAtomics::new_anonymous_with(options);
+        // w/ one node it's a no-op, but the page is still on that node.
+        if let (Some(node), Some(on)) = (node, numa::page_node(atomics.client_write as *const _ as usize)) {
+            assert_eq!(on, node, "{:?}", numa);
+        }
+        atomics.close();
+    }
+    if !numa::is_numa() {
+        assert_eq!(numa::cross_node_cpu(cpu, &topology::online_cpus()), None);
+    }
+}
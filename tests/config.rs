@@ -0,0 +1,70 @@
+use async_bench::backing::{Backing, MapOptions, Prefault};
+use async_bench::config::{self, BenchConfig};
+use async_bench::layout::LayoutMode;
+use async_bench::numa::NumaPolicy;
+use std::time::Duration;
+
+// What the config file, the environment and the command line parse to.
+
+#[test]
+fn timing_overrides_are_most_specific() {
+    let mut config = BenchConfig::default();
+    config.apply_json(
+        &json::parse(
+            r#"{ "warmup_secs" : 3, "measurement_secs" : 5,
+                 "timing" : { "in_process" : { "sample_size" : 100, "warmup_secs" : 1 },
+                              "rust_atomic" : { "measurement_secs" : 20, "target_ci_pct" : 0.5 } } }"#,
+        )
+        .unwrap(),
+    );
+    let secs = |d: Duration| d.as_secs_f64();
+    let t = config.timing_for("atomic_spin", "cpp_atomic");
+    assert_eq!((secs(t.warmup), secs(t.measurement), t.sample_size), (3.0, 5.0, 1000));
+    assert_eq!(t.target_ci_pct, None);
+    let t = config.timing_for("in_process", "rust_atomic_c4_s5");
+    assert_eq!((secs(t.warmup), secs(t.measurement), t.sample_size), (1.0, 20.0, 100));
+    assert_eq!(t.target_ci_pct, Some(0.5));
+
+    // but what was asked for on the command line wins over the file.
+    config.env_timing.measurement = Some(Duration::from_secs(7));
+    let t = config.timing_for("in_process", "rust_atomic_c4_s5");
+    assert_eq!((secs(t.warmup), secs(t.measurement), t.sample_size), (1.0, 7.0, 100));
+}
+
+#[test]
+fn layouts_parse() {
+    assert_eq!(LayoutMode::parse("spaced_128"), Some(LayoutMode::Spaced128));
+    assert_eq!(config::parse_layout_list("same_line,default").len(), 2);
+    let options = MapOptions { layout: LayoutMode::SeparatePages, ..Default::default() };
+    assert_eq!(options.bench_name("rust_atomic"), "rust_atomic_separate_pages");
+}
+
+#[test]
+fn backings_parse() {
+    assert_eq!(MapOptions::parse_backing("memfd"), Some((Backing::Memfd, Prefault::None)));
+    assert_eq!(MapOptions::parse_backing("hugetlb+mlock"), Some((Backing::HugePages, Prefault::Lock)));
+    assert_eq!(MapOptions::parse_backing("shm+nope"), None);
+    assert_eq!(config::parse_backing_list("all").len(), 9);
+    let options = MapOptions { backing: Backing::Memfd, prefault: Prefault::Populate, ..Default::default() };
+    assert_eq!(options.bench_name("rust_atomic"), "rust_atomic_memfd_populate");
+    assert_eq!(MapOptions::default().bench_name("rust_atomic"), "rust_atomic");
+}
+
+#[test]
+fn numa_policies_parse() {
+    assert_eq!(NumaPolicy::parse("client"), Some(NumaPolicy::Client));
+    assert_eq!(NumaPolicy::parse("1"), Some(NumaPolicy::Node(1)));
+    assert_eq!(NumaPolicy::parse("nope"), None);
+    let options = MapOptions { numa: NumaPolicy::Server, ..Default::default() };
+    assert_eq!(options.bench_name("rust_atomic"), "rust_atomic_numa_server");
+}
+
+#[test]
+fn criterion_filter_skips_the_option_values() {
+    use async_bench::bench_utils::criterion_filter;
+    let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<_>>();
+    assert_eq!(criterion_filter(&args("bench_atomic --bench")), None);
+    assert_eq!(criterion_filter(&args("bench_atomic --bench in_process")), Some("in_process"));
+    assert_eq!(criterion_filter(&args("bench_atomic --noplot -s base --sample-size 10 rust_")), Some("rust_"));
+    assert_eq!(criterion_filter(&args("bench_atomic --save-baseline new")), None);
+}
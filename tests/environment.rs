@@ -0,0 +1,86 @@
+use async_bench::environment::{capture, cmdline_param, cpuinfo_field};
+
+// The environment snapshot, env_check and jitter. Mostly the parsing,
+// what /sys and /proc say depends on the machine.
+
+#[test]
+fn reads_cpuinfo_and_cmdline() {
+    let cpuinfo = "processor\t: 0\nvendor_id\t: GenuineIntel\nmodel name\t: Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz\nmicrocode\t: 0xf0\n\nprocessor\t: 1\nmodel name\t: ignored\n";
+    assert_eq!(
+        cpuinfo_field(cpuinfo, "model name").as_deref(),
+        Some("Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz")
+    );
+    assert_eq!(cpuinfo_field(cpuinfo, "microcode").as_deref(), Some("0xf0"));
+    assert_eq!(cpuinfo_field(cpuinfo, "flags"), None);
+
+    let cmdline = "BOOT_IMAGE=/vmlinuz ro quiet isolcpus=4,5,10,11 nohz_full=4-5";
+    assert_eq!(cmdline_param(cmdline, "isolcpus").as_deref(), Some("4,5,10,11"));
+    assert_eq!(cmdline_param(cmdline, "nohz_full").as_deref(), Some("4-5"));
+    assert_eq!(cmdline_param(cmdline, "rcu_nocbs"), None);
+}
+
+#[test]
+fn snapshot_has_the_build() {
+    let env = capture();
+    assert!(env["build"]["rustc"].as_str().unwrap().starts_with("rustc "));
+    assert!(env["build"]["target_features"].is_array());
+    assert!(env["online_cpus"].is_array());
+    assert!(env["config"]["client_cpu"].is_number());
+}
+
+#[test]
+fn reads_task_stat() {
+    use async_bench::env_check::parse_task_stat;
+    let stat = "1234 (my (odd) task) R 1 1234 1234 0 -1 4194304 100 0 0 0 5 1 0 0 20 0 1 0 100 1000 10 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 5 0 0 0 0 0";
+    assert_eq!(parse_task_stat(stat), Some(('R', 5, "my (odd) task".to_string())));
+    assert_eq!(parse_task_stat("1234 (short) S 1"), None);
+}
+
+#[test]
+fn isolated_check_names_the_missing_cpus() {
+    use async_bench::env_check::check_isolated;
+    assert!(check_isolated(&[4, 5], &[4, 5, 10, 11]).ok());
+    let check = check_isolated(&[4, 5], &[4]);
+    assert!(!check.ok());
+    assert!(check.warnings[0].contains("[5]"), "{:?}", check.warnings);
+    assert!(check.warnings[0].contains("isolcpus=4,5"), "{:?}", check.warnings);
+}
+
+#[test]
+fn only_a_busy_smt_sibling_is_a_warning() {
+    use async_bench::env_check::check_online_siblings;
+    let busy = vec![(3, "stress".to_string()), (0, "bash".to_string())];
+    let idle = check_online_siblings(&[2], &busy);
+    assert!(idle.ok());
+    assert_eq!(idle.notes.len(), 1);
+    assert_eq!(idle.fixes.len(), 1);
+    let check = check_online_siblings(&[2, 3], &busy);
+    assert!(!check.ok());
+    assert!(check.warnings[0].contains("CPU 3") && check.warnings[0].contains("stress"), "{:?}", check.warnings);
+    assert_eq!(check.fixes.len(), 2);
+}
+
+#[test]
+fn interrupt_deltas_per_cpu() {
+    use async_bench::jitter::{interrupt_deltas, parse_interrupts};
+    let before = parse_interrupts(
+        "           CPU0       CPU4\n  31:        358          2 PCI-MSIX-0000:00:01.0   3-edge      virtio0-stats\nLOC:       1000        500   Local timer interrupts\nERR:          0\n",
+    );
+    let after = parse_interrupts(
+        "           CPU0       CPU4\n  31:        358          9 PCI-MSIX-0000:00:01.0   3-edge      virtio0-stats\nLOC:       1100        503   Local timer interrupts\nERR:          0\n",
+    );
+    assert_eq!(before["31 virtio0-stats"][&4], 2);
+    assert_eq!(before["LOC Local timer interrupts"][&0], 1000);
+    assert_eq!(
+        interrupt_deltas(&before, &after, 4),
+        vec![("31 virtio0-stats".to_string(), 7), ("LOC Local timer interrupts".to_string(), 3)]
+    );
+    assert_eq!(interrupt_deltas(&before, &after, 0), vec![("LOC Local timer interrupts".to_string(), 100)]);
+}
+
+#[test]
+fn spin_runs_for_the_duration() {
+    let (gaps, duration_ns) = async_bench::jitter::spin(std::time::Duration::from_millis(20), 1000);
+    assert!(duration_ns >= 20e6, "{}", duration_ns);
+    assert!(gaps.iter_recorded().all(|v| v.value_iterated_to() >= 1000));
+}
@@ -0,0 +1,19 @@
+use async_bench::atomic_spin::{Checked, MappedAtomics};
+use async_bench::servers::spin_server;
+
+// Every round trip in a histogram, not criterion's sample means.
+
+#[test]
+fn histogram_has_every_round_trip() {
+    let atomics = MappedAtomics::new_anonymous();
+    let hist = std::thread::scope(|scope| {
+        scope.spawn(|| spin_server::<Checked>(&atomics));
+        let hist = async_bench::hdr::record_round_trips(&atomics, 50);
+        atomics.request_stop();
+        hist
+    });
+    atomics.close();
+    assert_eq!(hist.len(), 50);
+    assert!(hist.min() > 0);
+    assert!(async_bench::hdr::percentile_distribution(&hist).contains("Total count    =           50"));
+}
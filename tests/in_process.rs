@@ -0,0 +1,173 @@
+use async_bench::atomic_spin::MappedAtomics;
+use async_bench::backing::{self, Backing, MapOptions, Prefault};
+use async_bench::layout::LayoutMode;
+use async_bench::numa::NumaPolicy;
+use async_bench::topology;
+use async_bench::servers::{ServerFn, CHECKED_SERVERS};
+use rand::RngCore;
+use std::sync::mpsc;
+use std::time::Duration;
+
+// Run each Rust server on a thread, and check it echoes
+// everything the client sends. Fails rather than hangs
+// if a server stops echoing.
+
+static TIMEOUT: Duration = Duration::from_secs(60);
+
+fn values(count: usize) -> Vec<u64> {
+    let mut rng = rand::thread_rng();
+    (0..count).map(|_| rng.next_u64()).collect()
+}
+
+fn check_echo(name: &'static str, server: ServerFn, values: Vec<u64>) {
+    check_echo_in(name, server, values, MapOptions::default());
+}
+
+fn check_echo_in(name: &'static str, server: ServerFn, values: Vec<u64>, options: MapOptions) {
+    run_echo(name, server, values, options, true);
+}
+
+/// w/o `check_payload` only the sequence number has to come back.
+fn run_echo(name: &'static str, server: ServerFn, values: Vec<u64>, options: MapOptions, check_payload: bool) {
+    let atomics: &'static MappedAtomics = Box::leak(Box::new(MappedAtomics::new_anonymous_with(options)));
+
+    let (server_done, server_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        server(atomics);
+        server_done.send(()).unwrap();
+    });
+
+    let (client_done, client_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for v in values.iter() {
+            let echoed = atomics.client_run_once(*v);
+            assert!(!check_payload || echoed == *v, "{} echoed the wrong payload", name);
+        }
+        atomics.request_stop();
+        client_done.send(values.len()).unwrap();
+    });
+
+    let sent = client_rx
+        .recv_timeout(TIMEOUT)
+        .unwrap_or_else(|_| panic!("{} didn't echo everything in {:?}", name, TIMEOUT));
+    server_rx
+        .recv_timeout(TIMEOUT)
+        .unwrap_or_else(|_| panic!("{} didn't stop when asked", name));
+    assert!(sent > 0);
+}
+
+fn server(name: &str) -> ServerFn {
+    CHECKED_SERVERS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .unwrap_or_else(|| panic!("no server called {}", name))
+        .1
+}
+
+fn check_server(name: &'static str) {
+    check_echo(name, server(name), values(250));
+}
+
+#[test]
+fn spin_server_echoes() {
+    check_server("rust_atomic");
+}
+
+#[test]
+fn async_resume_server_echoes() {
+    check_server("rust_async_resume");
+}
+
+#[test]
+fn async_suspend_server_echoes() {
+    check_server("rust_async_suspend");
+}
+
+#[test]
+fn callback_server_echoes() {
+    check_server("rust_callback");
+}
+
+// the server starts out at 0. Before the sequence number
+// a 0 payload was never seen as a change, and the client
+// thought it was echoed before the server saw it.
+#[test]
+fn zero_payload_is_echoed() {
+    for (name, server) in CHECKED_SERVERS.iter() {
+        check_echo(name, *server, vec![0, 0, 7, 0]);
+    }
+}
+
+// sending the same payload twice in a row used to deadlock,
+// the server never saw it change.
+#[test]
+fn repeated_payloads_are_echoed() {
+    for (name, server) in CHECKED_SERVERS.iter() {
+        check_echo(name, *server, vec![42, 42, 42, u64::MAX, u64::MAX, 1, 1]);
+    }
+}
+
+#[test]
+fn stoppable_servers_echo_and_stop() {
+    // what the in_process benchmarks run. They don't copy the payload.
+    for (name, server) in async_bench::servers::STOPPABLE_SERVERS.iter() {
+        run_echo(name, *server, values(250), MapOptions::default(), false);
+    }
+}
+
+#[test]
+fn every_layout_is_echoed() {
+    for layout in LayoutMode::ALL {
+        for (name, server) in CHECKED_SERVERS.iter() {
+            check_echo_in(name, *server, vec![0, 0, u64::MAX, 1, 42], MapOptions { layout, ..Default::default() });
+        }
+    }
+}
+
+/// true if mlock()ing a few pages won't hit ulimit -l.
+fn can_lock() -> bool {
+    let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) == 0 && limit.rlim_cur >= 1 << 20 }
+}
+
+#[test]
+fn every_backing_is_echoed() {
+    let mut prefaults = vec![Prefault::None, Prefault::Populate];
+    if can_lock() {
+        prefaults.push(Prefault::Lock);
+    }
+    let mut backings = vec![Backing::Shm, Backing::Memfd];
+    if backing::huge_pages_free() >= 4 {
+        backings.push(Backing::HugePages);
+    }
+    for (backing, prefault) in backings.into_iter().flat_map(|b| prefaults.iter().map(move |p| (b, *p))) {
+        for layout in [LayoutMode::Default, LayoutMode::SeparateMappings] {
+            let options = MapOptions { layout, backing, prefault, ..Default::default() };
+            for (name, server) in CHECKED_SERVERS.iter().take(2) {
+                check_echo_in(name, *server, vec![0, u64::MAX, 1, 42], options);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_numa_policy_is_echoed() {
+    let cpu = unsafe { libc::sched_getcpu() } as usize;
+    let node = topology::cpu_node(cpu).unwrap_or(0);
+    for numa in [NumaPolicy::Client, NumaPolicy::Server, NumaPolicy::Node(node)] {
+        let options = MapOptions { numa: numa.resolve(cpu, cpu), ..Default::default() };
+        for (name, server) in CHECKED_SERVERS.iter().take(2) {
+            check_echo_in(name, *server, vec![0, u64::MAX, 7], options);
+        }
+    }
+}
+
+#[test]
+fn task_finishes_when_async_code_returns() {
+    let mut task = async_bench::async_impl::Task::init(async {});
+    task.advance();
+    assert!(task.is_done());
+    // polling a finished task is a no-op, not a panic.
+    task.advance();
+}
+
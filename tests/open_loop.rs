@@ -0,0 +1,115 @@
+use async_bench::atomic_spin::{Checked, MappedAtomics};
+use async_bench::bench_utils::{available_cpus, pin_to};
+use async_bench::open_loop::{spare_cpus, sweep};
+use async_bench::servers::spin_server;
+use std::time::Duration;
+
+// The open-loop generator, and the latency it adds back in.
+
+/// the server threads here aren't pinned, so there's no server CPU
+/// to keep off. This one's excluded anyway, it's the client's.
+fn this_cpu() -> usize {
+    unsafe { libc::sched_getcpu() as usize }
+}
+
+#[test]
+fn open_loop_counts_the_wait() {
+    let atomics = MappedAtomics::new_anonymous();
+    let (paced, flat_out) = std::thread::scope(|scope| {
+        scope.spawn(|| spin_server::<Checked>(&atomics));
+        let paced = async_bench::open_loop::run_at_rate(&atomics, Some(200.0), 20, this_cpu());
+        let flat_out = async_bench::open_loop::run_at_rate(&atomics, None, 20, this_cpu());
+        atomics.request_stop();
+        (paced, flat_out)
+    });
+    atomics.close();
+    assert_eq!(paced.corrected.len(), 20);
+    assert_eq!(paced.uncorrected.len(), 20);
+    // 20 messages 5ms apart keeps up easily, unless the
+    // client, server and load generator take turns on a CPU.
+    if enough_cpus_for_open_loop() {
+        assert!(!paced.saturated(), "{} msgs/s", paced.achieved_rate);
+    }
+    assert!(flat_out.saturated());
+    let table = async_bench::open_loop::table(&[flat_out, paced]);
+    assert!(table.starts_with("offered,achieved,saturated,p50"));
+    assert!(table.contains("\nmax,"));
+    assert!(table.contains("\n200,"));
+}
+
+/// a CPU each for the client, the server and the load generator. W/
+/// fewer they time slice, and every round trip is a scheduler tick.
+fn enough_cpus_for_open_loop() -> bool {
+    async_bench::bench_utils::available_cpus().len() >= 3
+}
+
+static STALL: Duration = Duration::from_millis(100);
+
+/// rust_atomic, but it sits on the 100th message for STALL.
+fn stalling_server(atomics: &MappedAtomics) {
+    let mut last_value = 0;
+    for count in 1.. {
+        last_value = atomics.server_spin_until_change::<Checked>(last_value);
+        if count == 100 {
+            std::thread::sleep(STALL);
+        }
+        atomics.server_echo::<Checked>(last_value);
+        if atomics.should_stop::<Checked>() {
+            return;
+        }
+    }
+}
+
+#[test]
+fn open_loop_counts_the_queue_behind_a_stall() {
+    if !enough_cpus_for_open_loop() {
+        return;
+    }
+    let atomics = MappedAtomics::new_anonymous();
+    let point = std::thread::scope(|scope| {
+        scope.spawn(|| stalling_server(&atomics));
+        let point = async_bench::open_loop::run_at_rate(&atomics, Some(1000.0), 600, this_cpu());
+        atomics.request_stop();
+        point
+    });
+    atomics.close();
+    assert_eq!(point.corrected.len(), 600);
+
+    // ~100 messages are due during the stall. Each one's echo is only
+    // slow by the one ahead of it, but it was due up to STALL before.
+    let stall = STALL.as_nanos() as f64;
+    let hidden = point.corrected.value_at_percentile(99.0) as f64 - point.uncorrected.value_at_percentile(99.0) as f64;
+    assert!(
+        hidden > 0.5 * stall && hidden < 1.5 * stall,
+        "corrected p99 {} ns, uncorrected p99 {} ns",
+        point.corrected.value_at_percentile(99.0),
+        point.uncorrected.value_at_percentile(99.0)
+    );
+}
+
+#[test]
+fn sweep_keeps_the_load_generator_off_the_client_and_server() {
+    let cpus = available_cpus();
+    let (client_cpu, server_cpu) = (cpus[0], cpus[cpus.len() - 1]);
+    let spare = spare_cpus(client_cpu, server_cpu);
+    assert!(!spare.contains(&client_cpu) && !spare.contains(&server_cpu), "{:?}", spare);
+    assert_eq!(spare.len() + if client_cpu == server_cpu { 1 } else { 2 }, cpus.len());
+
+    pin_to(client_cpu);
+    let atomics = MappedAtomics::new_anonymous();
+    let points = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            pin_to(server_cpu);
+            spin_server::<Checked>(&atomics)
+        });
+        let points = sweep(&atomics, &[100], 0.05, server_cpu);
+        atomics.request_stop();
+        points
+    });
+    atomics.close();
+    // nowhere to put it, so no sweep at all.
+    match points {
+        Some(points) => assert_eq!(points.len(), 2),
+        None => assert!(spare.is_empty()),
+    }
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+// The client and server perf counters.
+
+#[test]
+fn perf_counter_deltas() {
+    let before = vec![("cycles", 100), ("instructions", 50)];
+    let after = vec![("cycles", 300), ("instructions", 450), ("branch-misses", 3)];
+    let delta = async_bench::perf::delta(&before, &after);
+    assert_eq!(delta, vec![("cycles", 200), ("instructions", 400)]);
+    let line = async_bench::perf::summary("client", &delta, 100);
+    assert!(line.contains("cycles 2.00"), "{}", line);
+    assert!(line.contains("IPC 2.00"), "{}", line);
+
+    // and real ones, where the kernel lets us have them.
+    let counters = async_bench::perf::PerfCounters::open(0, false);
+    let work = || (0..100_000u64).map(std::hint::black_box).sum::<u64>();
+    let instructions = |d: &Vec<(&str, u64)>| d.iter().find(|(name, _)| *name == "instructions").map(|(_, v)| *v);
+    let start = counters.read();
+    work();
+    let delta = async_bench::perf::delta(&start, &counters.read());
+    assert_eq!(delta.len(), start.len());
+    if let Some(count) = instructions(&delta) {
+        assert!(count > 0, "{:?}", delta);
+
+        // and nothing while they're off.
+        counters.disable();
+        let start = counters.read();
+        work();
+        assert_eq!(instructions(&async_bench::perf::delta(&start, &counters.read())), Some(0));
+    }
+}
+
+/// set in the child busy_threads_child() runs as.
+static BUSY_CHILD_ENV: &str = "PERF_TEST_BUSY_CHILD";
+
+/// when this test binary is started again w/ BUSY_CHILD_ENV, be a server
+/// w/ a few threads doing all the work, and a main thread that does none.
+#[test]
+fn busy_threads_child() {
+    if std::env::var_os(BUSY_CHILD_ENV).is_none() {
+        return;
+    }
+    for _ in 0..3 {
+        std::thread::spawn(|| loop {
+            // a fresh page each time, for a count even w/o the hardware counters.
+            let size = page_size::get();
+            unsafe {
+                let page = libc::mmap(
+                    std::ptr::null_mut(),
+                    size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                std::ptr::write_volatile(page as *mut u8, 1);
+                libc::munmap(page, size);
+            }
+            (0..1000u64).map(std::hint::black_box).sum::<u64>();
+        });
+    }
+    println!("ready");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).unwrap();
+}
+
+#[test]
+fn server_counters_count_every_thread() {
+    use std::io::BufRead;
+    use std::process::{Command, Stdio};
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["busy_threads_child", "--exact", "--nocapture", "--test-threads=1"])
+        .env(BUSY_CHILD_ENV, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("ready") {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "the child exited before it was ready");
+    }
+
+    // its threads are already running, like the JVM's.
+    let pid = child.id() as libc::pid_t;
+    let process = async_bench::perf::PerfCounters::open_process(pid);
+    let main_thread = async_bench::perf::PerfCounters::open(pid, true);
+    let (process_start, main_start) = (process.read(), main_thread.read());
+    std::thread::sleep(Duration::from_millis(200));
+    let process_delta = async_bench::perf::delta(&process_start, &process.read());
+    let main_delta = async_bench::perf::delta(&main_start, &main_thread.read());
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    // instructions where there's a PMU, page faults where there isn't.
+    let count = |d: &Vec<(&str, u64)>, name: &str| d.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+    for name in ["instructions", "page-faults"] {
+        if let (Some(all), Some(main)) = (count(&process_delta, name), count(&main_delta, name)) {
+            assert!(all > 1000, "{} {} over every thread", all, name);
+            // just the main thread w/ inherit misses what's already running.
+            assert!(main < all / 10, "{} {} on the main thread, {} over all", main, name, all);
+        }
+    }
+}
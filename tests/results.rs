@@ -0,0 +1,252 @@
+use async_bench::aggregate::{load_best, load_dir, parse_raw_csv, parse_run_file_name, split_bench_id, to_csv, BestBy};
+use async_bench::history::{append, load, series, Record};
+use async_bench::compare::{compare_all, Thresholds, Verdict};
+use async_bench::plot::{plot, x_range, Labels, Row, Style, PALETTE};
+use async_bench::stats::{bootstrap_change, mann_whitney, median_ci, percentile, Summary};
+use std::path::PathBuf;
+
+// The tools that read the runs/ directory. Each test
+// writes its own fake runs into a directory under target/.
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// a criterion raw.csv w/ these per iteration times, 10 iterations a sample.
+fn raw_csv(times: &[f64]) -> String {
+    let mut out = "group,function,value,throughput_num,throughput_type,sample_measured_value,unit,iteration_count\n".to_string();
+    for t in times.iter() {
+        out.push_str(&format!("atomic_spin,rust_atomic,,,,{},ns,10\n", t * 10.0));
+    }
+    out
+}
+
+#[test]
+fn percentiles_match_r() {
+    // R : quantile(1:10, c(.025, .5, .9, .975))
+    let values: Vec<f64> = (1..=10).map(|v| v as f64).collect();
+    assert!((percentile(&values, 2.5) - 1.225).abs() < 1e-9);
+    assert!((percentile(&values, 50.0) - 5.5).abs() < 1e-9);
+    assert!((percentile(&values, 90.0) - 9.1).abs() < 1e-9);
+    assert!((percentile(&values, 97.5) - 9.775).abs() < 1e-9);
+
+    let s = Summary::of(&[3.0, 1.0, 2.0]);
+    assert_eq!((s.count, s.min, s.median, s.mean, s.max), (3, 1.0, 2.0, 2.0, 3.0));
+
+    let many: Vec<f64> = (0..1000).map(|v| v as f64).collect();
+    let (lo, hi) = median_ci(&many);
+    assert!(lo < 499.5 && hi > 499.5 && hi - lo < 80.0, "{} {}", lo, hi);
+}
+
+#[test]
+fn reads_run_names_and_raw_csv() {
+    assert_eq!(parse_run_file_name("rust_async_resume_r12.csv"), Some(("rust_async_resume".to_string(), 12)));
+    assert_eq!(parse_run_file_name("rust_atomic_tsc_r3.csv"), Some(("rust_atomic_tsc".to_string(), 3)));
+    assert_eq!(parse_run_file_name("environment_r3.json"), None);
+    assert_eq!(split_bench_id("rust_async_resume"), ("rust", "resume".to_string()));
+    assert_eq!(split_bench_id("kotlin_callback"), ("kotlin", "callback".to_string()));
+
+    let (unit, samples) = parse_raw_csv(&raw_csv(&[100.0, 120.0])).unwrap();
+    assert_eq!(unit, "ns");
+    assert_eq!(samples, vec![100.0, 120.0]);
+    assert!(parse_raw_csv("offered,achieved\nmax,1000\n").is_none());
+}
+
+#[test]
+fn picks_the_best_run() {
+    let dir = test_dir("picks_the_best_run");
+    // run 1 has the lower median, run 2 the shorter tail.
+    let run1: Vec<f64> = (0..100).map(|i| if i < 90 { 100.0 } else { 1000.0 }).collect();
+    let run2: Vec<f64> = (0..100).map(|_| 150.0).collect();
+    std::fs::write(dir.join("rust_atomic_r1.csv"), raw_csv(&run1)).unwrap();
+    std::fs::write(dir.join("rust_atomic_r2.csv"), raw_csv(&run2)).unwrap();
+    std::fs::write(dir.join("rust_atomic_open_loop_r1.csv"), "offered,achieved\n").unwrap();
+
+    let by_hi = load_dir(&dir, BestBy::Hi).unwrap();
+    assert_eq!(by_hi.len(), 1);
+    assert_eq!(by_hi[0].runs.len(), 2);
+    assert_eq!(by_hi[0].best_run().run, 2);
+
+    let by_median = load_dir(&dir, BestBy::Median).unwrap();
+    assert_eq!(by_median[0].best_run().run, 1);
+
+    let csv = to_csv(&by_hi);
+    assert!(csv.starts_with("id,language,variant,runs,best_run,unit,samples,min"));
+    assert!(csv.contains("\nrust_atomic,rust,atomic,2,2,ns,100,150,"), "{}", csv);
+}
+
+#[test]
+fn plots_clip_the_tail() {
+    let mut slow: Vec<f64> = (0..100).map(|v| 200.0 + v as f64).collect();
+    slow.push(100_000.0);
+    let rows = vec![
+        Row {
+            label: "Rust".to_string(),
+            color: PALETTE[0].to_string(),
+            samples: (0..100).map(|v| 100.0 + v as f64).collect(),
+        },
+        Row {
+            label: "Rust-Resume".to_string(),
+            color: PALETTE[1].to_string(),
+            samples: slow,
+        },
+    ];
+    let (lo, hi) = x_range(&rows, 99.0);
+    assert!(lo < 100.0 && lo > 90.0, "{}", lo);
+    assert!(hi < 1000.0, "the outlier should be clipped, {}", hi);
+
+    let legend = vec![("Intel".to_string(), PALETTE[0].to_string())];
+    let labels = Labels {
+        title: "Rust round-trip times",
+        subtitle: "Intel(R) Core(TM) i7-8700K",
+        unit: "ns",
+        legend: &legend,
+    };
+    for style in [Style::Violin, Style::Box] {
+        let svg = plot(&rows, &labels, 99.0, style).finish();
+        assert!(svg.contains("Rust-Resume") && svg.contains("Rust round-trip times"));
+        assert!(svg.contains(PALETTE[1]));
+        assert!(!svg.contains("NaN"), "{}", svg);
+    }
+}
+
+#[test]
+fn mann_whitney_matches_r() {
+    // R : wilcox.test(c(6:10), c(1:5), exact=FALSE) W = 25, p-value = 0.01219
+    let a: Vec<f64> = (6..=10).map(|v| v as f64).collect();
+    let b: Vec<f64> = (1..=5).map(|v| v as f64).collect();
+    let test = mann_whitney(&a, &b);
+    assert_eq!(test.u, 25.0);
+    assert!((test.p - 0.01219).abs() < 1e-4, "{}", test.p);
+    assert_eq!(test.delta, 1.0);
+
+    // R : wilcox.test(c(1,2,2,3,3,3), c(2,3,3,4,4,5), exact=FALSE) W = 7, p-value = 0.0784
+    let test = mann_whitney(&[1.0, 2.0, 2.0, 3.0, 3.0, 3.0], &[2.0, 3.0, 3.0, 4.0, 4.0, 5.0]);
+    assert_eq!(test.u, 7.0);
+    assert!((test.p - 0.0784).abs() < 1e-4, "{}", test.p);
+
+    let same = mann_whitney(&a, &a);
+    assert!(same.p > 0.999 && same.delta == 0.0, "{:?}", same);
+
+    let (change, lo, hi) = bootstrap_change(&b, &a, 50.0, 1000, 1);
+    assert_eq!(change, 166.66666666666669);
+    assert!(lo < change && change < hi, "{} {} {}", lo, change, hi);
+}
+
+#[test]
+fn compare_flags_regressions() {
+    let base = test_dir("compare_base");
+    let new = test_dir("compare_new");
+    let times: Vec<f64> = (0..100).map(|v| 100.0 + (v * 37 % 100) as f64 / 10.0).collect();
+    let slower: Vec<f64> = times.iter().map(|t| t * 1.2).collect();
+    for (dir, atomic) in [(&base, &times), (&new, &slower)] {
+        std::fs::write(dir.join("rust_atomic_r1.csv"), raw_csv(atomic)).unwrap();
+        std::fs::write(dir.join("rust_callback_r1.csv"), raw_csv(&times)).unwrap();
+    }
+    std::fs::write(new.join("zig_atomic_r1.csv"), raw_csv(&times)).unwrap();
+
+    let (comparisons, unmatched) = compare_all(
+        &load_best(&base, BestBy::Hi).unwrap(),
+        &load_best(&new, BestBy::Hi).unwrap(),
+        1000,
+    );
+    assert_eq!(unmatched, vec!["zig_atomic".to_string()]);
+    let t = Thresholds::default();
+    let atomic = comparisons.iter().find(|c| c.id == "rust_atomic").unwrap();
+    assert_eq!(atomic.verdict(&t), Verdict::Slower);
+    assert!((atomic.median_change.0 - 20.0).abs() < 0.5, "{:?}", atomic.median_change);
+    assert!(atomic.test.p < 0.001 && atomic.test.delta == 1.0);
+    let callback = comparisons.iter().find(|c| c.id == "rust_callback").unwrap();
+    assert_eq!(callback.verdict(&t), Verdict::Same);
+    assert_eq!(callback.median_change.0, 0.0);
+
+    // past the median, but not the 25% threshold
+    let loose = Thresholds {
+        median_pct: 25.0,
+        ..t
+    };
+    assert_eq!(atomic.verdict(&loose), Verdict::Same);
+}
+
+#[test]
+fn history_finds_the_first_regression() {
+    let dir = test_dir("history");
+    let store = dir.join("history.jsonl");
+    let times: Vec<f64> = (0..100).map(|v| 100.0 + (v * 37 % 100) as f64 / 10.0).collect();
+    let env = |commit: &str, timestamp: u64| {
+        json::object! {
+            timestamp: timestamp,
+            hostname: "bench1",
+            cpu: { vendor: "GenuineIntel", model: "Intel(R) Core(TM) i7-8700K" },
+            online_cpus: [0, 1, 2, 3],
+            git: { commit: commit, dirty: false },
+        }
+    };
+    // a, b, c the same speed, d 20% slower, e stays there.
+    let mut by_commit = Vec::new();
+    for (i, (commit, scale)) in [("a", 1.0), ("b", 1.0), ("c", 1.01), ("d", 1.2), ("e", 1.2)].iter().enumerate() {
+        let file = dir.join(format!("rust_atomic_r{}.csv", i));
+        let scaled: Vec<f64> = times.iter().map(|t| t * scale).collect();
+        std::fs::write(&file, raw_csv(&scaled)).unwrap();
+        let run = async_bench::aggregate::load_run(&file, i).unwrap();
+        by_commit.push(Record::new("rust_atomic", &run, &env(commit, 1_700_000_000 + i as u64)));
+    }
+    append(&store, &by_commit[..3]).unwrap();
+    append(&store, &by_commit[3..]).unwrap();
+    // c again, a re-run of the same build replaces it where it was
+    let mut rerun = by_commit[2].clone();
+    rerun.timestamp += 100;
+    append(&store, &[rerun]).unwrap();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&store)
+        .and_then(|mut f| std::io::Write::write_all(&mut f, b"not json\n"))
+        .unwrap();
+
+    let records = load(&store).unwrap();
+    assert_eq!(records.len(), 6);
+    assert_eq!(records[0].fingerprint, by_commit[0].fingerprint);
+    assert_eq!(records[0].fingerprint.len(), 16);
+    let all = series(&records);
+    assert_eq!(all.len(), 1);
+    let commits: Vec<&str> = all[0].records.iter().map(|r| r.commit.as_str()).collect();
+    assert_eq!(commits, vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(all[0].records[2].timestamp, by_commit[2].timestamp + 100);
+
+    // the same, in the order they were run
+    let all = series(&by_commit);
+    let steps = all[0].steps(500);
+    assert!(steps[0].is_none());
+    assert_eq!(all[0].first_regression(&steps, &Thresholds::default()), Some(3));
+
+    // another machine is another series
+    let mut other = by_commit[0].clone();
+    other.fingerprint = "0000000000000000".to_string();
+    assert_eq!(series(&[by_commit[0].clone(), other]).len(), 2);
+}
+
+#[test]
+fn results_record_has_the_summary() {
+    use async_bench::results::{record, split_cpu_pair};
+    assert_eq!(split_cpu_pair("rust_atomic"), ("rust_atomic", None));
+    assert_eq!(split_cpu_pair("rust_async_resume_c4_s5"), ("rust_async_resume", Some((4, 5))));
+    assert_eq!(split_cpu_pair("cpp_callback_cx_s1"), ("cpp_callback_cx_s1", None));
+
+    let samples: Vec<f64> = (1..=10).map(|v| v as f64).collect();
+    let r = record("atomic_spin", "rust_async_resume_c4_s5", "ns", &samples, "13e3634a60f7e483");
+    assert_eq!(r["group"], "atomic_spin");
+    assert_eq!(r["name"], "rust_async_resume_c4_s5");
+    assert_eq!((r["language"].as_str(), r["variant"].as_str()), (Some("rust"), Some("resume")));
+    assert_eq!((r["client_cpu"].as_usize(), r["server_cpu"].as_usize()), (Some(4), Some(5)));
+    assert_eq!(r["samples"], 10);
+    assert_eq!((r["min"].as_f64(), r["max"].as_f64()), (Some(1.0), Some(10.0)));
+    assert_eq!((r["median"].as_f64(), r["mean"].as_f64()), (Some(5.5), Some(5.5)));
+    assert!((r["p90"].as_f64().unwrap() - 9.1).abs() < 1e-9);
+    assert_eq!(r["fingerprint"], "13e3634a60f7e483");
+
+    let r = record("in_process", "rust_callback", "ns", &samples, "x");
+    assert!(r["client_cpu"].is_null());
+}
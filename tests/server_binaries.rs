@@ -0,0 +1,367 @@
+use async_bench::atomic_spin::MappedAtomics;
+use async_bench::backing::{Backing, MapOptions, Prefault, SHM_FD_ENV};
+use async_bench::ffi;
+use async_bench::{conformance, registry};
+use async_bench::layout;
+use async_bench::servers::CHECKED_FLAG;
+use rand::RngCore;
+use std::ffi::CString;
+use std::process::{Child, Command};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// Launch each Rust server binary on its own shm segment, and
+// check it echoes everything the client sends, in order.
+// Fails rather than hangs if a server stops echoing or dies.
+
+static TIMEOUT: Duration = Duration::from_secs(120);
+
+/// kills the server, and removes the shm, however the test ends.
+struct Server {
+    child: Child,
+    atomics: &'static MappedAtomics,
+    name: CString,
+    client: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        // the client may be spinning on an echo that's never coming. Echo
+        // for the server till it notices the test's over, it has to be off
+        // the mapping before it goes.
+        if let Some(client) = self.client.take() {
+            let start = Instant::now();
+            while !client.is_finished() && start.elapsed() < TIMEOUT {
+                let seq = self.atomics.client_write.load(std::sync::atomic::Ordering::Acquire);
+                self.atomics.server_write.store(seq, std::sync::atomic::Ordering::Release);
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            if !client.is_finished() {
+                // still on it, so leave it mapped, and only remove the name.
+                unsafe { libc::shm_unlink(self.name.as_ptr()) };
+                return;
+            }
+        }
+        self.atomics.close();
+    }
+}
+
+fn values(count: usize) -> Vec<u64> {
+    let mut rng = rand::thread_rng();
+    // the edge values first, including repeats.
+    let mut values = vec![0, 0, u64::MAX, u64::MAX, 1, 0];
+    while values.len() < count {
+        values.push(rng.next_u64());
+    }
+    values
+}
+
+fn check_binary(bin: &str, values: Vec<u64>) {
+    let bin_name = std::path::Path::new(bin).file_name().unwrap().to_str().unwrap();
+    let name = format!("/async_bench_test_{}_{}", std::process::id(), bin_name);
+    let atomics: &'static MappedAtomics = Box::leak(Box::new(MappedAtomics::new_named(
+        CString::new(name.clone()).unwrap(),
+        true,
+    )));
+
+    let mut command = Command::new(bin);
+    command.arg(CHECKED_FLAG).env("SHM_NAME", &name);
+    check_command(bin, command, atomics, CString::new(name).unwrap(), values);
+}
+
+/// start `command`, a Checked server on `atomics`, and check it echoes
+/// `values` and stops when asked, all within TIMEOUT. `name` is the
+/// segment's shm name, for the clean up.
+fn check_command(bin: &str, mut command: Command, atomics: &'static MappedAtomics, name: CString, values: Vec<u64>) {
+    let child = command.spawn().unwrap_or_else(|e| panic!("can't start {} : {}", bin, e));
+    let mut server = Server { child, atomics, name, client: None };
+
+    let count = values.len();
+    let (echoed, echoed_rx) = mpsc::channel();
+    server.client = Some(std::thread::spawn(move || {
+        for v in values.iter() {
+            // the receiver's gone if the test already failed.
+            if echoed.send((*v, atomics.client_run_once(*v))).is_err() {
+                return;
+            }
+        }
+    }));
+
+    // check each value comes back in the order it was sent,
+    // and the server hasn't died.
+    let start = Instant::now();
+    let mut received = 0;
+    while received < count {
+        match echoed_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok((sent, echo)) => {
+                assert_eq!(sent, echo, "{} echoed the wrong payload for value #{}", bin, received);
+                received += 1;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if let Some(status) = server.child.try_wait().unwrap() {
+                    panic!("{} exited early with {} after {} values", bin, status, received);
+                }
+                if start.elapsed() > TIMEOUT {
+                    panic!("{} only echoed {} of {} values in {:?}", bin, received, count, TIMEOUT);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("client thread died"),
+        }
+    }
+
+    assert_eq!(
+        atomics.server_ready(),
+        Some(server.child.id()),
+        "{} didn't mark itself ready",
+        bin
+    );
+
+    // the Checked servers exit when asked.
+    atomics.request_stop();
+    let stop_start = Instant::now();
+    loop {
+        if let Some(status) = server.child.try_wait().unwrap() {
+            assert!(status.success(), "{} exited with {}", bin, status);
+            break;
+        }
+        if stop_start.elapsed() > TIMEOUT {
+            panic!("{} didn't exit when asked to stop", bin);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+#[should_panic(expected = "exited early")]
+fn a_server_that_dies_fails_only_its_test() {
+    check_binary("/bin/true", values(100));
+}
+
+#[test]
+#[should_panic(expected = "exited w/")]
+fn bench_server_stops_waiting_on_a_dead_server() {
+    use async_bench::bench_utils::wait_for_server;
+    let atomics = MappedAtomics::new_anonymous();
+    let mut ready = Command::new("sleep").arg("10").spawn().unwrap();
+    atomics.mark_server_ready();
+    wait_for_server(&atomics, &mut ready, "ready");
+    let _ = ready.kill();
+    let _ = ready.wait();
+
+    let atomics = MappedAtomics::new_anonymous();
+    wait_for_server(&atomics, &mut Command::new("/bin/true").spawn().unwrap(), "true");
+}
+
+#[test]
+fn atomic_spin_server() {
+    check_binary(env!("CARGO_BIN_EXE_atomic_spin_server"), values(2000));
+}
+
+#[test]
+fn atomic_async_resume() {
+    check_binary(env!("CARGO_BIN_EXE_atomic_async_resume"), values(2000));
+}
+
+#[test]
+fn atomic_async_suspend() {
+    check_binary(env!("CARGO_BIN_EXE_atomic_async_suspend"), values(2000));
+}
+
+#[test]
+fn atomic_callback_server() {
+    check_binary(env!("CARGO_BIN_EXE_atomic_callback_server"), values(2000));
+}
+
+#[test]
+fn c_abi_shares_the_segment() {
+    let name = CString::new(format!("/async_bench_test_{}_ffi", std::process::id())).unwrap();
+    unsafe {
+        // the client makes it, like the bench does, and the "server" opens it through the C ABI.
+        let client = MappedAtomics::new_named(name.clone(), true);
+        let seg = ffi::spin_open(name.as_ptr(), 0);
+        assert!(!seg.is_null());
+        assert_eq!(ffi::spin_check_header(seg), 0);
+        assert_eq!(ffi::spin_layout_version(), async_bench::atomic_spin::LAYOUT_VERSION);
+
+        assert_eq!(client.server_ready(), None);
+        ffi::spin_server_ready(seg);
+        assert_eq!(client.server_ready(), Some(std::process::id()));
+
+        client.client_write.store(7, std::sync::atomic::Ordering::Release);
+        assert_eq!(*ffi::spin_client_word(seg), 7);
+        *ffi::spin_server_word(seg) = 9;
+        *ffi::spin_server_payload(seg) = 11;
+        assert_eq!(client.server_write.load(std::sync::atomic::Ordering::Acquire), 9);
+        assert_eq!(client.server_payload.load(std::sync::atomic::Ordering::Acquire), 11);
+
+        assert_eq!(ffi::spin_stop_requested(seg), 0);
+        client.request_stop();
+        assert_eq!(ffi::spin_stop_requested(seg), 1);
+
+        ffi::spin_close(seg);
+        client.close();
+    }
+}
+
+#[test]
+fn c_abi_refuses_a_segment_wo_a_header() {
+    let name = CString::new(format!("/async_bench_test_{}_noheader", std::process::id())).unwrap();
+    unsafe {
+        // made by something that doesn't know about the header, so it's all 0s.
+        let fd = libc::shm_open(name.as_ptr(), libc::O_CREAT | libc::O_RDWR, libc::S_IRUSR | libc::S_IWUSR);
+        assert!(fd >= 0);
+        libc::ftruncate(fd, 4096);
+        libc::close(fd);
+
+        let seg = ffi::spin_open(name.as_ptr(), 0);
+        libc::shm_unlink(name.as_ptr());
+        assert!(seg.is_null());
+    }
+}
+
+#[test]
+fn layout_header_matches_the_rust() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/include/spin_layout.h");
+    let header = std::fs::read_to_string(path).unwrap();
+    assert_eq!(header, layout::c_header(), "{} is stale, run cargo run --bin gen_header", path);
+    assert!(header.contains(&format!("#define SPIN_SERVER_OFFSET {}\n", layout::SERVER_OFFSET)));
+    assert!(header.contains(&format!("#define SPIN_PREFAULT_OFFSET {}\n", layout::PREFAULT_OFFSET)));
+
+    // the atomics are where the header says they are.
+    let atomics = MappedAtomics::new_anonymous();
+    let at = |w: &std::sync::atomic::AtomicU64| w as *const _ as usize;
+    let base = at(atomics.client_write) - layout::CLIENT_OFFSET;
+    assert_eq!(at(atomics.server_write) - base, layout::SERVER_OFFSET);
+    assert_eq!(at(atomics.client_payload) - base, layout::PAYLOAD_OFFSET);
+    assert_eq!(at(atomics.server_payload) - base, layout::SERVER_OFFSET + layout::PAYLOAD_OFFSET);
+    atomics.close();
+}
+
+#[test]
+fn conformance_passes_a_server_and_fails_a_hung_one() {
+    let options = conformance::Options {
+        startup_timeout: Duration::from_secs(10),
+        echo_timeout: Duration::from_secs(2),
+        values: 200,
+    };
+    let name = |what: &str| CString::new(format!("/async_bench_test_{}_{}", std::process::id(), what)).unwrap();
+
+    // the benchmarked loop only echoes, the Checked one does the rest.
+    let mut checked = Command::new(env!("CARGO_BIN_EXE_atomic_callback_server"));
+    checked.arg(CHECKED_FLAG);
+    for (cmd, expect) in [
+        (Command::new(env!("CARGO_BIN_EXE_atomic_callback_server")), conformance::Expect::default()),
+        (checked, conformance::Expect { echoes_payload: true, stops: true }),
+    ] {
+        let good = conformance::check("rust_callback", cmd, expect, name("conformance"), &options);
+        conformance::print_report(&good);
+        assert!(good.passed());
+        assert_eq!(good.checks.len(), conformance::CHECKS.len());
+    }
+
+    // starts, but never says it's ready, or echoes anything.
+    let options = conformance::Options { startup_timeout: Duration::from_millis(200), ..options };
+    let mut sleep = Command::new("sleep");
+    sleep.arg("60");
+    let hung = conformance::check("sleep", sleep, conformance::Expect::default(), name("hung"), &options);
+    conformance::print_report(&hung);
+    assert!(!hung.passed());
+    let check = |name: &str| {
+        hung.checks.iter().find(|c| c.name == name).unwrap_or_else(|| panic!("no {} check", name)).outcome
+    };
+    assert_eq!(check("handshake"), conformance::Outcome::Fail);
+    // we still notice when it's killed.
+    assert_eq!(check("crash"), conformance::Outcome::Pass);
+}
+
+#[test]
+fn registry_has_every_rust_server() {
+    for (name, _) in async_bench::servers::ALL_SERVERS.iter() {
+        let spec = registry::find(name).unwrap_or_else(|| panic!("{} isn't in the registry", name));
+        assert!(spec.has_checked_mode() && spec.checked_command().is_some());
+        // the benchmarked loop does what the other languages' do.
+        assert!(!spec.echoes_payload && !spec.stops);
+    }
+    let mut names: Vec<&str> = registry::SERVERS.iter().map(|s| s.name).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), registry::SERVERS.len(), "two servers w/ the same name");
+
+    // every server, and whether it can go along w/ other layouts and backings.
+    let expected = [
+        ("cpp_atomic", false),
+        ("cpp_resume", false),
+        ("cpp_suspend", false),
+        ("cpp_callback", false),
+        ("rust_atomic", true),
+        ("rust_async_resume", true),
+        ("rust_async_suspend", true),
+        ("rust_callback", true),
+        ("zig_atomic", false),
+        ("zig_resume", false),
+        ("zig_suspend", false),
+        ("zig_callback", false),
+        ("kotlin_atomic", true),
+        ("kotlin_resume", true),
+        ("kotlin_suspend", true),
+        ("kotlin_callback", true),
+    ];
+    assert_eq!(registry::SERVERS.len(), expected.len());
+    let others = [
+        MapOptions { layout: layout::LayoutMode::SameLine, ..Default::default() },
+        MapOptions { backing: Backing::Memfd, ..Default::default() },
+        MapOptions { backing: Backing::HugePages, ..Default::default() },
+        MapOptions { prefault: Prefault::Lock, ..Default::default() },
+    ];
+    let numa = MapOptions { numa: async_bench::numa::NumaPolicy::Client, ..Default::default() };
+    for (name, follows) in expected {
+        let spec = registry::find(name).unwrap_or_else(|| panic!("{} isn't in the registry", name));
+        assert_eq!(spec.follows_header, follows, "{}", name);
+        assert!(spec.supports(&MapOptions::default()) && spec.supports(&numa));
+        for options in others.iter() {
+            assert_eq!(spec.supports(options), follows, "{} w/ {:?}", name, options);
+        }
+    }
+}
+
+#[test]
+fn opener_follows_the_creators_layout() {
+    for layout in [layout::LayoutMode::SameLine, layout::LayoutMode::SeparateMappings] {
+        let name = CString::new(format!("/async_bench_test_{}_{}", std::process::id(), layout.name())).unwrap();
+        let options = MapOptions { layout, ..Default::default() };
+        let client: &'static MappedAtomics = Box::leak(Box::new(MappedAtomics::create(name.clone(), options)));
+        let server = MappedAtomics::new_named(name.clone(), false);
+        assert_eq!(server.layout(), layout);
+
+        // a real server on it too. check_command() closes the client.
+        let mut command = Command::new(env!("CARGO_BIN_EXE_atomic_spin_server"));
+        command.arg(CHECKED_FLAG).env("SHM_NAME", name.to_str().unwrap());
+        check_command(&format!("atomic_spin_server {:?}", layout), command, client, name, vec![0, u64::MAX, 3]);
+        // and this mapping saw it, the last payload echoed is where it says.
+        assert_eq!(server.server_payload.load(std::sync::atomic::Ordering::Acquire), 3);
+        server.unmap();
+    }
+}
+
+#[test]
+fn server_opens_a_memfd_passed_down() {
+    let options = MapOptions {
+        layout: layout::LayoutMode::SeparateMappings,
+        backing: Backing::Memfd,
+        prefault: Prefault::Populate,
+        ..Default::default()
+    };
+    let name = CString::new("async_bench_test_memfd").unwrap();
+    let client: &'static MappedAtomics = Box::leak(Box::new(MappedAtomics::create(name.clone(), options)));
+    let fd = client.shared_fd().expect("a memfd should stay open");
+
+    // no CLOEXEC on it, so the server has the same fd.
+    let mut command = Command::new(env!("CARGO_BIN_EXE_atomic_spin_server"));
+    command
+        .arg(CHECKED_FLAG)
+        .env(SHM_FD_ENV, fd.to_string())
+        .env("SHM_NAME", "/async_bench_test_not_this_one");
+    check_command("atomic_spin_server w/ a memfd", command, client, name, vec![0, u64::MAX, 3]);
+}
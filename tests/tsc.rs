@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+// The TSC clock criterion can time w/ instead of the wall clock.
+
+#[test]
+fn rdtsc_measurement_counts_ticks() {
+    use async_bench::tsc::{self, RdtscMeasurement, TscUnit};
+    use criterion::measurement::Measurement;
+
+    let ticks = RdtscMeasurement::new(TscUnit::TscTicks);
+    let nanos = RdtscMeasurement::new(TscUnit::Nanos);
+
+    // never goes backwards.
+    let mut last = ticks.start();
+    for _ in 0..1000 {
+        let now = ticks.start();
+        assert!(now >= last, "{} after {}", now, last);
+        last = now;
+    }
+
+    let wall = std::time::Instant::now();
+    let start = ticks.start();
+    std::thread::sleep(Duration::from_millis(20));
+    let value = ticks.end(start);
+    let wall = wall.elapsed().as_nanos() as f64;
+    assert!(value > 0);
+
+    // ticks are left as they are, ns go through the calibrated rate.
+    assert_eq!(ticks.to_f64(&value), value as f64);
+    let ns = nanos.to_f64(&value);
+    assert!((ns - value as f64 / tsc::ticks_per_ns()).abs() < 1e-6 * ns, "{} ns", ns);
+    assert!(ns > 0.5 * wall && ns < 1.5 * wall, "{} ns by the TSC, {} ns by the wall clock", ns, wall);
+}
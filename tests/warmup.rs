@@ -0,0 +1,44 @@
+use async_bench::atomic_spin::{Checked, MappedAtomics};
+use async_bench::servers::spin_server;
+use std::time::Duration;
+
+// Waiting for the round trip (and the JIT) to settle before timing it.
+
+static TIMEOUT: Duration = Duration::from_secs(60);
+
+#[test]
+fn warmup_waits_for_the_jit() {
+    use async_bench::warmup::{is_compilation_line, settled, wait_until_stable, CompilationLog};
+    assert!(is_compilation_line("    123   45       3       java.lang.String::hashCode (55 bytes)"));
+    assert!(is_compilation_line("    130   46 %     4       Foo::run @ 12 (80 bytes)   made not entrant"));
+    assert!(!is_compilation_line("server listening"));
+    assert!(!is_compilation_line(""));
+
+    assert!(!settled(&[100.0, 101.0], 2.0));
+    assert!(settled(&[500.0, 100.0, 101.0, 102.0], 2.0));
+    assert!(!settled(&[100.0, 101.0, 110.0], 2.0));
+
+    let log = CompilationLog::watch(std::io::Cursor::new(
+        "started\n  10    1       3       A::a (5 bytes)\n  12    2       4       A::a (5 bytes)\n".to_string(),
+    ));
+    let start = std::time::Instant::now();
+    while log.events() < 2 && start.elapsed() < TIMEOUT {
+        std::thread::yield_now();
+    }
+    assert_eq!(log.events(), 2);
+    assert_eq!(log.last(), "12    2       4       A::a (5 bytes)");
+
+    let atomics = MappedAtomics::new_anonymous();
+    let (settles, gives_up) = std::thread::scope(|scope| {
+        scope.spawn(|| spin_server::<Checked>(&atomics));
+        let settles = wait_until_stable(&atomics, 5, 1e9, TIMEOUT, Some(&log));
+        let gives_up = wait_until_stable(&atomics, 5, -1.0, Duration::from_millis(50), None);
+        atomics.request_stop();
+        (settles, gives_up)
+    });
+    atomics.close();
+    assert!(settles.stable);
+    assert_eq!((settles.round_trips, settles.medians.len()), (15, 3));
+    assert_eq!(settles.compilations, Some(2));
+    assert!(!gives_up.stable);
+}
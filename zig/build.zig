@@ -17,6 +17,7 @@ pub fn build(b: *std.build.Builder) void {
     exe.setBuildMode(std.builtin.Mode.ReleaseFast);
     // exe.setBuildMode(std.builtin.Mode.Debug);
     exe.linkLibC();
+    exe.addIncludePath("../include");
     exe.install();
 
     const exe2 = b.addExecutable("atomicAsyncResume", "src/atomicAsyncResume.zig");
@@ -24,6 +25,7 @@ pub fn build(b: *std.build.Builder) void {
     exe2.setBuildMode(std.builtin.Mode.ReleaseFast);
     // exe.setBuildMode(std.builtin.Mode.Debug);
     exe2.linkLibC();
+    exe2.addIncludePath("../include");
     exe2.install();
 
     const exe3 = b.addExecutable("atomicAsyncSuspend", "src/atomicAsyncSuspend.zig");
@@ -31,6 +33,7 @@ pub fn build(b: *std.build.Builder) void {
     exe3.setBuildMode(std.builtin.Mode.ReleaseFast);
     // exe.setBuildMode(std.builtin.Mode.Debug);
     exe3.linkLibC();
+    exe3.addIncludePath("../include");
     exe3.install();
 
     const exe4 = b.addExecutable("atomicCallback", "src/atomicCallback.zig");
@@ -38,13 +41,7 @@ pub fn build(b: *std.build.Builder) void {
     exe4.setBuildMode(std.builtin.Mode.ReleaseFast);
     // exe.setBuildMode(std.builtin.Mode.Debug);
     exe4.linkLibC();
+    exe4.addIncludePath("../include");
     exe4.install();
 
-    // make a .so file for the JVM code for
-    // maping memory via shm_open
-    const lib = b.addSharedLibrary("zigmap", "src/common.zig", b.version(0,0,1));
-    lib.setBuildMode(std.builtin.Mode.ReleaseFast);
-    lib.linkLibC();
-    lib.install();
-
 }
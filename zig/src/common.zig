@@ -1,6 +1,9 @@
 const c = @cImport({
     @cInclude("fcntl.h");
     @cInclude("sys/mman.h");
+    @cInclude("unistd.h");
+    // the layout, generated from the Rust side by cargo run --bin gen_header. See src/layout.rs
+    @cInclude("spin_layout.h");
 });
 
 const std = @import("std");
@@ -19,7 +22,8 @@ pub fn spinUntilChange( spinPtr:*const u64, lastValue:u64) callconv(.Inline) u64
 
 
 const SetupError = error {
-    C_Func_Error
+    C_Func_Error,
+    Layout_Mismatch
 };
 
 const SetupReturn = struct {
@@ -27,17 +31,9 @@ const SetupReturn = struct {
     serverPtr : *u64,
 };
 
-// export as a C lib for the JVM code to 
-// map the shared memory
-export fn mapSetup() *const anyopaque {
-    const mem = doSetup() catch  {
-        std.debug.panic("Can't setup shared memory. Fail",.{});
-    };
-    return mem.clientPtr;
-}
-
 pub fn doSetup() anyerror!SetupReturn {
 
+    // always shm, by name. A memfd backing (SHM_FD) is only for the Rust and kotlin servers.
     var memHandle : c_int = std.c.shm_open(
         "/spinnmem",
         c.O_RDWR,
@@ -48,21 +44,36 @@ pub fn doSetup() anyerror!SetupReturn {
         return SetupError.C_Func_Error;
     }
 
-    if( std.c.ftruncate(memHandle, 4096 ) < 0 ) return SetupError.C_Func_Error;
-
+    // no ftruncate, the client sized it. Some layouts are more than a page.
 
     const memPtr = try std.os.mmap(
         null,
-        4096,
+        c.SPIN_SEGMENT_SIZE,
         c.PROT_READ | c.PROT_WRITE,
         c.MAP_SHARED,
         memHandle,
         0
     );
 
+    const header = std.mem.bytesAsValue( u64, memPtr[c.SPIN_HEADER_OFFSET..(c.SPIN_HEADER_OFFSET+8)]);
+    const expected = (@as(u64, c.SPIN_LAYOUT_MAGIC) << 32) | c.SPIN_LAYOUT_VERSION;
+    if( @atomicLoad(u64, header, std.builtin.AtomicOrder.Acquire) != expected ) {
+        std.log.err("the shared memory has a different layout than this build. rebuild w/ the current spin_layout.h",.{});
+        return SetupError.Layout_Mismatch;
+    }
+    const mode = std.mem.bytesAsValue( u64, memPtr[c.SPIN_MODE_OFFSET..(c.SPIN_MODE_OFFSET+8)]);
+    if( @atomicLoad(u64, mode, std.builtin.AtomicOrder.Monotonic) != c.SPIN_MODE_DEFAULT ) {
+        std.log.err("the client picked a layout other than the default, only the Rust servers do those",.{});
+        return SetupError.Layout_Mismatch;
+    }
+
+    // the handshake, we're about to start echoing.
+    const serverPid = std.mem.bytesAsValue( u64, memPtr[c.SPIN_SERVER_PID_OFFSET..(c.SPIN_SERVER_PID_OFFSET+8)]);
+    @atomicStore(u64, serverPid, @intCast(u64, c.getpid()), std.builtin.AtomicOrder.Release);
+
     return SetupReturn{
-        .clientPtr = std.mem.bytesAsValue( u64, memPtr[0..8]),
-        .serverPtr = std.mem.bytesAsValue( u64, memPtr[2048..(2048+8)])
+        .clientPtr = std.mem.bytesAsValue( u64, memPtr[c.SPIN_CLIENT_OFFSET..(c.SPIN_CLIENT_OFFSET+8)]),
+        .serverPtr = std.mem.bytesAsValue( u64, memPtr[c.SPIN_SERVER_OFFSET..(c.SPIN_SERVER_OFFSET+8)])
     };
 
 }